@@ -15,8 +15,8 @@ fn main() {
 
     let a = al.output("o_A", 32);
     let b = al.output("o_B", 32);
-    al.assign(a._e(axi.named_reg("Calc_A")));
-    al.assign(b._e(axi.named_reg("Calc_B")));
+    al.assign(a._e(axi.named_reg("calc_a")));
+    al.assign(b._e(axi.named_reg("calc_b")));
 
     let w = al.wire("write_en_cdata", 0);
     al.assign(w._e(_Num(1)));
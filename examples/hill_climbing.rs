@@ -11,9 +11,9 @@ fn main() {
 
     let start = m.input("Start", 1);
     let data = m.input("Data", 32);
-    let ready = m.output("Ready", 1);
-    let busy = m.output("Busy", 1);
-    let done = m.output("Done", 1);
+    let ready = m.reg_out("Ready", 1);
+    let busy = m.reg_out("Busy", 1);
+    let done = m.reg_out("Done", 1);
 
     let evalfuncdata = m.reg_out("Eval", 32);
     let evalstart = m.reg_out("EvalS", 1);
@@ -39,7 +39,10 @@ fn main() {
     m.assign(result._e(&bestdata));
 
     let mut fsm = FsmModule::new(&clk, &rst, "state")
+        .output_default(&busy, 1)
         .AddState("IDLE")
+        .output(&ready, 1)
+        .output(&busy, 0)
         .goto("INIT", F!(start == 1))
         .AddState("INIT")
         .goto("NINIT", F!(evalvalid == 1))
@@ -54,15 +57,15 @@ fn main() {
         .goto("NINIT", Blank!())
         .goto("END", F!(nexteval <= besteval))
         .AddState("END")
+        .output(&done, 1)
+        .output(&busy, 0)
         .goto("IDLE", Blank!());
 
-    let s_idle = fsm.Param("IDLE");
     let s_init = fsm.Param("INIT");
     let s_ninit = fsm.Param("NINIT");
     let s_upd_n1 = fsm.Param("UPDATE_NEXT1");
     let s_upd_n2 = fsm.Param("UPDATE_NEXT2");
     let s_pproc = fsm.Param("POSTPROCESS");
-    let s_end = fsm.Param("END");
 
     let fstate = m.fsm(fsm);
 
@@ -121,9 +124,5 @@ fn main() {
             )),
     );
 
-    m.assign(ready._e(F!(fstate == s_idle)));
-    m.assign(done._e(F!(fstate == s_end)));
-    m.assign(busy._e(F!(fstate != s_idle).land(F!(fstate != s_end))));
-
     println!("{}", m.gen());
 }
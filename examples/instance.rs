@@ -0,0 +1,31 @@
+extern crate verugent;
+
+use verugent::vcore::*;
+
+fn main() {
+    let mut cnt = VModule::new("counter");
+    let width = cnt.add_io_param("WIDTH", 8);
+    let clk = cnt.input("CLK", 1);
+    let rst = cnt.input("RST", 1);
+    let q = cnt.reg_out("Q", &width);
+    cnt.always(
+        posedge(&clk)
+            .posedge(&rst)
+            .non()
+            .if_(&rst, Form(q.sst(0)))
+            .else_(Form(q.sst(&q + 1))),
+    );
+
+    let mut top = VModule::new("top");
+    let clk = top.input("clk", 1);
+    let rst = top.input("rst", 1);
+    let led = top.output("o_led", 4);
+    top.instance(&cnt, "u_counter")
+        .param("WIDTH", 4)
+        .port("CLK", &clk)
+        .port("RST", &rst)
+        .port("Q", &led);
+
+    println!("{}", cnt.gen());
+    println!("{}", top.gen());
+}
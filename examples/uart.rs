@@ -23,7 +23,7 @@ fn main() {
     let rxemp = m.reg_out("rx_empty", 0);
 
     let txreg = m.reg("tx_reg", 8);
-    let txovrn = m.reg("rx_over_run", 0);
+    let txovrn = m.reg("tx_over_run", 0);
     let txcnt = m.reg("tx_cnt", 4);
 
     let rxreg = m.reg("rx_reg", 8);
@@ -61,7 +61,7 @@ fn main() {
                     .Form(If(
                         &rxen,
                         Form(If(
-                            !rxbsy.land(rxd2.not()),
+                            rxbsy.not().land(rxd2.not()),
                             Form(F!(rxbsy = 1)).Form(F!(rxsmpl = 1)).Form(F!(rxcnt = 0)),
                         ))
                         .Form(If(
@@ -0,0 +1,97 @@
+//! コード生成時のエラー型
+
+use std::error;
+use std::fmt;
+use width::WidthMismatch;
+
+/// 生成前の検査で検出されるエラー
+#[derive(Clone, Debug)]
+pub enum VerugentError {
+    /// 入出力以外のポート種別
+    InvalidPort { module: String, signal: String },
+    /// 未対応のバスインタフェース
+    UnsupportedBus { module: String, bus: String },
+    /// 式が必要な位置の空の式
+    NullExpression { module: String, context: String },
+    /// FSM 構築時のエラー
+    Fsm {
+        module: String,
+        state_reg: String,
+        message: String,
+    },
+    /// メモリ構成のエラー
+    Memory {
+        module: String,
+        memory: String,
+        message: String,
+    },
+    /// インスタンス接続のエラー
+    Instance {
+        module: String,
+        instance: String,
+        message: String,
+    },
+    /// 代入時のビット幅不一致
+    WidthMismatch(WidthMismatch),
+    /// Verilog ソースの構文エラー
+    Parse { line: usize, message: String },
+    /// 出力言語で表せない構文
+    Unsupported {
+        module: String,
+        backend: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for VerugentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerugentError::InvalidPort {
+                ref module,
+                ref signal,
+            } => write!(f, "{}: {} is not an input, output or inout", module, signal),
+            VerugentError::UnsupportedBus {
+                ref module,
+                ref bus,
+            } => write!(f, "{}: {} interface is not supported", module, bus),
+            VerugentError::NullExpression {
+                ref module,
+                ref context,
+            } => write!(f, "{}: missing expression in {}", module, context),
+            VerugentError::Fsm {
+                ref module,
+                ref state_reg,
+                ref message,
+            } => write!(f, "{}: fsm {}: {}", module, state_reg, message),
+            VerugentError::Memory {
+                ref module,
+                ref memory,
+                ref message,
+            } => write!(f, "{}: memory {}: {}", module, memory, message),
+            VerugentError::Instance {
+                ref module,
+                ref instance,
+                ref message,
+            } => write!(f, "{}: {}: {}", module, instance, message),
+            VerugentError::WidthMismatch(ref w) => write!(f, "{}", w),
+            VerugentError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+            VerugentError::Unsupported {
+                ref module,
+                ref backend,
+                ref message,
+            } => write!(
+                f,
+                "{}: {} is not supported by the {} backend",
+                module, message, backend
+            ),
+        }
+    }
+}
+
+impl error::Error for VerugentError {}
+
+impl From<WidthMismatch> for VerugentError {
+    fn from(w: WidthMismatch) -> Self {
+        VerugentError::WidthMismatch(w)
+    }
+}
@@ -0,0 +1,388 @@
+//! generate 構文(genvar, generate for, generate if)
+
+use std::cmp;
+use vcore::*;
+
+// 展開時のループ回数の上限
+const MAX_ITERATION: i32 = 1 << 16;
+
+/// generate ブロックの要素
+#[derive(Clone, Debug)]
+pub enum GenItem {
+    Assign(Assign),
+    Always(Always),
+    Instance(Instance),
+    Generate(Generate),
+}
+
+// 名前付きスコープのブロック(cond は if の条件、else と for では Null)
+#[derive(Clone, Debug)]
+pub(crate) struct GenBlock {
+    pub(crate) cond: Box<E>,
+    pub(crate) label: String,
+    pub(crate) items: Vec<GenItem>,
+}
+
+// for (var = init; cond; var = step) の各要素
+#[derive(Clone, Debug)]
+pub(crate) struct GenLoop {
+    pub(crate) var: WireVar,
+    pub(crate) init: Box<E>,
+    pub(crate) cond: Box<E>,
+    pub(crate) step: Box<E>,
+}
+
+/// generate for / generate if 構文
+#[derive(Clone, Debug)]
+pub struct Generate {
+    pub(crate) lp: Option<GenLoop>,
+    pub(crate) blocks: Vec<GenBlock>,
+}
+
+impl Generate {
+    /// generate for 構文(for (var = init; cond; var = step) begin : label)
+    pub fn for_<T, U, V, W>(var: T, init: U, cond: V, step: W, label: &str) -> Generate
+    where
+        T: Into<Box<E>>,
+        U: Into<Box<E>>,
+        V: Into<Box<E>>,
+        W: Into<Box<E>>,
+    {
+        let var = match *var.into() {
+            E::Ldc(ref wr) if matches!(wr.io_param, IOType::Genvar) => wr.clone(),
+            ref e => panic!(
+                "generate for needs a genvar: {}",
+                decomp_ast(Box::new(e.clone()), "", 0)
+            ),
+        };
+        Generate {
+            lp: Some(GenLoop {
+                var,
+                init: init.into(),
+                cond: cond.into(),
+                step: step.into(),
+            }),
+            blocks: vec![GenBlock::new(Box::new(E::Null), label)],
+        }
+    }
+
+    /// generate if 構文(if (cond) begin : label)
+    pub fn if_<T: Into<Box<E>>>(cond: T, label: &str) -> Generate {
+        Generate {
+            lp: None,
+            blocks: vec![GenBlock::new(cond.into(), label)],
+        }
+    }
+
+    /// else if 節の追加(以降の要素はこの節に入る)
+    pub fn else_if<T: Into<Box<E>>>(&mut self, cond: T, label: &str) -> Generate {
+        self.check_else();
+        self.blocks.push(GenBlock::new(cond.into(), label));
+        self.clone()
+    }
+
+    /// else 節の追加(以降の要素はこの節に入る)
+    pub fn else_(&mut self, label: &str) -> Generate {
+        self.check_else();
+        self.blocks.push(GenBlock::new(Box::new(E::Null), label));
+        self.clone()
+    }
+
+    fn check_else(&self) {
+        if self.lp.is_some() {
+            panic!("generate for cannot have else branch");
+        }
+        if let E::Null = *self.blocks.last().unwrap().cond {
+            panic!("generate if already has else branch");
+        }
+    }
+
+    /// assign 文の追加
+    pub fn assign(&mut self, assign: Assign) -> Generate {
+        self.push(GenItem::Assign(assign));
+        self.clone()
+    }
+
+    /// always ブロックの追加
+    pub fn always(&mut self, always: Always) -> Generate {
+        self.push(GenItem::Always(always));
+        self.clone()
+    }
+
+    /// 入れ子の generate 構文の追加
+    pub fn generate(&mut self, generate: Generate) -> Generate {
+        self.push(GenItem::Generate(generate));
+        self.clone()
+    }
+
+    /// サブモジュールのインスタンス化
+    pub fn instance(&mut self, module: &VModule, name: &str) -> &mut Instance {
+        let dup = self.blocks.last().unwrap().items.iter().any(|x| match *x {
+            GenItem::Instance(ref inst) => inst.name == name,
+            _ => false,
+        });
+        if dup {
+            panic!("Some name instance exist. :{}\n", name)
+        }
+        self.push(GenItem::Instance(Instance::new(module, name)));
+        match *self.blocks.last_mut().unwrap().items.last_mut().unwrap() {
+            GenItem::Instance(ref mut inst) => inst,
+            _ => unreachable!(),
+        }
+    }
+
+    fn push(&mut self, item: GenItem) {
+        self.blocks.last_mut().unwrap().items.push(item);
+    }
+
+    // 要素の出力関数を指定した Verilog 構文の出力(indent は for/if 行の深さ)
+    pub(crate) fn print_with(&self, indent: i32, item: &dyn Fn(&GenItem) -> String) -> String {
+        let body = |b: &GenBlock| {
+            b.items
+                .iter()
+                .map(|x| match *x {
+                    GenItem::Generate(ref g) => g.print_with(indent + 1, item),
+                    _ => shift(&item(x), indent),
+                })
+                .collect::<String>()
+        };
+        let tab = "    ".repeat(cmp::max(indent, 0) as usize);
+        if let Some(ref lp) = self.lp {
+            let b = &self.blocks[0];
+            return format!(
+                "{}for ({} = {}; {}; {} = {}) begin : {}\n{}{}end\n",
+                tab,
+                lp.var.name,
+                decomp_ast(lp.init.clone(), "", 0),
+                decomp_ast(lp.cond.clone(), "", 0),
+                lp.var.name,
+                decomp_ast(lp.step.clone(), "", 0),
+                b.label,
+                body(b),
+                tab
+            );
+        }
+        let mut st = tab.clone();
+        for (i, b) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                st += " else ";
+            }
+            if let E::Null = *b.cond {
+                st += &format!("begin : {}\n", b.label);
+            } else {
+                st += &format!(
+                    "if ({}) begin : {}\n",
+                    decomp_ast(b.cond.clone(), "", 0),
+                    b.label
+                );
+            }
+            st += &body(b);
+            st += &format!("{}end", tab);
+        }
+        st + "\n"
+    }
+
+    pub(crate) fn print_list(list: &[Generate], item: &dyn Fn(&GenItem) -> String) -> String {
+        list.iter()
+            .map(|g| format!("    generate\n{}    endgenerate\n", g.print_with(2, item)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // 選択されたブロックを展開して m に追加(prefix はインスタンス名の階層)
+    fn unroll(&self, scope: &VModule, prefix: &str, m: &mut VModule) {
+        match self.lp {
+            Some(ref lp) => {
+                let b = &self.blocks[0];
+                let name = &lp.var.name;
+                let mut v = eval(scope, &lp.init);
+                let mut count = 0;
+                while eval(scope, &bind(&lp.cond, name, v)) != 0 {
+                    count += 1;
+                    if count > MAX_ITERATION {
+                        panic!("generate for does not terminate: {}", b.label);
+                    }
+                    let items = b.items.iter().map(|x| x.bind(name, v)).collect::<Vec<_>>();
+                    let prefix = format!("{}{}[{}].", prefix, b.label, v);
+                    unroll_items(scope, &items, &prefix, m);
+                    v = eval(scope, &bind(&lp.step, name, v));
+                }
+            }
+            None => {
+                let hit = self.blocks.iter().find(|b| match *b.cond {
+                    E::Null => true,
+                    _ => eval(scope, &b.cond) != 0,
+                });
+                if let Some(b) = hit {
+                    let prefix = format!("{}{}.", prefix, b.label);
+                    unroll_items(scope, &b.items, &prefix, m);
+                }
+            }
+        }
+    }
+
+    // genvar を値に置き換えた generate 構文
+    fn bind(&self, name: &str, v: i64) -> Generate {
+        let mut g = self.clone();
+        if let Some(ref mut lp) = g.lp {
+            lp.init = bind(&lp.init, name, v);
+            lp.cond = bind(&lp.cond, name, v);
+            lp.step = bind(&lp.step, name, v);
+        }
+        for b in g.blocks.iter_mut() {
+            b.cond = bind(&b.cond, name, v);
+            b.items = b.items.iter().map(|x| x.bind(name, v)).collect();
+        }
+        g
+    }
+}
+
+impl GenBlock {
+    fn new(cond: Box<E>, label: &str) -> GenBlock {
+        GenBlock {
+            cond,
+            label: label.to_string(),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl GenItem {
+    // Verilog 構文の出力
+    pub(crate) fn print(&self) -> String {
+        match *self {
+            GenItem::Assign(ref a) => format!("    {}\n", a.print()),
+            GenItem::Always(ref al) => al.print(),
+            GenItem::Instance(ref inst) => inst.print(),
+            GenItem::Generate(ref g) => g.print_with(1, &GenItem::print),
+        }
+    }
+
+    fn bind(&self, name: &str, v: i64) -> GenItem {
+        let b = |x: &E| bind(x, name, v);
+        match *self {
+            GenItem::Assign(ref a) => GenItem::Assign(Assign::new().lhs(b(&a.lhs)).rhs(b(&a.rhs))),
+            GenItem::Always(ref al) => {
+                let mut al = al.clone();
+                al.stmt = al.stmt.iter().map(|x| b(x)).collect();
+                GenItem::Always(al)
+            }
+            GenItem::Instance(ref inst) => {
+                let mut inst = inst.clone();
+                for p in inst.port_map.iter_mut().chain(inst.param_map.iter_mut()) {
+                    p.1 = b(&p.1);
+                }
+                GenItem::Instance(inst)
+            }
+            GenItem::Generate(ref g) => GenItem::Generate(g.bind(name, v)),
+        }
+    }
+}
+
+// 各行のインデントを深くする
+pub(crate) fn shift(st: &str, indent: i32) -> String {
+    let tab = "    ".repeat(cmp::max(indent, 0) as usize);
+    st.lines()
+        .map(|l| {
+            if l.is_empty() {
+                "\n".to_string()
+            } else {
+                format!("{}{}\n", tab, l)
+            }
+        })
+        .collect()
+}
+
+fn eval(scope: &VModule, e: &E) -> i64 {
+    match scope.const_eval(e) {
+        Some(v) => v,
+        None => panic!(
+            "generate expression is not a constant: {}",
+            decomp_ast(Box::new(e.clone()), "", 0)
+        ),
+    }
+}
+
+fn unroll_items(scope: &VModule, items: &[GenItem], prefix: &str, m: &mut VModule) {
+    for x in items {
+        match *x {
+            GenItem::Assign(ref a) => m.assign.push(a.clone()),
+            GenItem::Always(ref al) => m.always.push(al.clone()),
+            GenItem::Instance(ref inst) => {
+                let mut inst = inst.clone();
+                inst.name = prefix.to_string() + &inst.name;
+                m.instance.push(inst);
+            }
+            GenItem::Generate(ref g) => g.unroll(scope, prefix, m),
+        }
+    }
+}
+
+// 式中の genvar を値に置き換える
+fn bind(e: &E, name: &str, v: i64) -> Box<E> {
+    let b = |x: &E| bind(x, name, v);
+    Box::new(match *e {
+        E::Ldc(ref wr) if wr.name == name => E::Num(v as i32),
+        E::Un(op, ref a) => E::Un(op, b(a)),
+        E::Red(op, ref a) => E::Red(op, b(a)),
+        E::Bin(op, ref l, ref r) => E::Bin(op, b(l), b(r)),
+        E::Cat(ref items) => E::Cat(items.iter().map(|x| b(x)).collect()),
+        E::Rep(ref n, ref x) => E::Rep(b(n), b(x)),
+        E::Signed(ref a) => E::Signed(b(a)),
+        E::Unsigned(ref a) => E::Unsigned(b(a)),
+        E::Clog2(ref a) => E::Clog2(b(a)),
+        E::PL(ref c, ref t, ref f) => E::PL(b(c), b(t), b(f)),
+        E::SB(ref l, ref r) => E::SB(b(l), b(r)),
+        E::SBX(s, ref l, ref r) => E::SBX(s, b(l), b(r)),
+        E::CS(ref c) => {
+            let mut c = c.clone();
+            for x in c.Select.iter_mut() {
+                x.CaseT = b(&x.CaseT);
+                x.CaseS = x.CaseS.iter().map(|x| b(x)).collect();
+            }
+            E::CS(c)
+        }
+        E::BL(ref list) => E::BL(
+            list.iter()
+                .map(|x| {
+                    let mut x = x.clone();
+                    x.cond = b(&x.cond);
+                    x.stmt = x.stmt.iter().map(|x| b(x)).collect();
+                    x
+                })
+                .collect(),
+        ),
+        E::Func(ref top, ref args) => E::Func(top.clone(), args.iter().map(|x| b(x)).collect()),
+        E::MEM(ref m, ref a) => E::MEM(b(m), b(a)),
+        E::MBT(ref m, ref h, ref l) => E::MBT(b(m), b(h), b(l)),
+        _ => e.clone(),
+    })
+}
+
+impl VModule {
+    /// genvar の宣言
+    pub fn genvar(&mut self, name: &str) -> Box<E> {
+        let mut tmp = WireVar::new();
+        tmp.name = name.to_string();
+        tmp.io_param = IOType::Genvar;
+        self.local_param.push(tmp.clone());
+        _V(tmp)
+    }
+
+    /// generate 構文の追加
+    pub fn generate(&mut self, generate: Generate) {
+        self.generate.push(generate)
+    }
+
+    // generate 構文をパラメータのデフォルト値で展開したモジュール
+    pub(crate) fn unroll_generate(&self) -> VModule {
+        let mut m = self.clone();
+        m.generate.clear();
+        m.local_param
+            .retain(|wr| !matches!(wr.io_param, IOType::Genvar));
+        for g in &self.generate {
+            g.unroll(self, "", &mut m);
+        }
+        m
+    }
+}
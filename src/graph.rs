@@ -0,0 +1,165 @@
+//! FSM の状態遷移図の出力(Graphviz DOT, Mermaid)
+
+use vcore::*;
+
+// DOT のリセット入口のノード ID(ステート名と重ならない予約 ID)
+const RESET_NODE: &str = "__reset";
+
+// 状態遷移(遷移元, 遷移先, 条件, Mealy 出力)
+pub(crate) struct Edge {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    // 遷移元ステートでの分岐番号
+    pub(crate) index: usize,
+    // 条件無しの遷移は None
+    pub(crate) cond: Option<String>,
+    pub(crate) outputs: Vec<String>,
+}
+
+impl FsmModule {
+    // 各ステートの分岐から求めた状態遷移の一覧
+    pub(crate) fn edges(&self) -> Vec<Edge> {
+        let mut list = Vec::new();
+        for s in &self.states {
+            for (i, b) in s.branch.iter().enumerate() {
+                let to = b.stmt.iter().find_map(|x| match **x {
+                    E::SB(_, ref r) => match **r {
+                        E::Ldc(ref wr) => Some(wr.name.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+                let to = match to {
+                    Some(x) => x,
+                    None => continue,
+                };
+                list.push(Edge {
+                    from: _StrOut(s.state.clone()),
+                    to,
+                    index: i,
+                    cond: match *b.cond {
+                        E::Null => None,
+                        ref c => Some(decomp_ast(Box::new(c.clone()), "", 0)),
+                    },
+                    outputs: s
+                        .mealy
+                        .iter()
+                        .filter(|m| m.0 == i)
+                        .map(|m| assign_label(&m.1, &m.2))
+                        .collect(),
+                });
+            }
+        }
+        list
+    }
+
+    // 遷移のラベル(条件 / Mealy 出力)
+    fn edge_label(e: &Edge) -> String {
+        let cond = e
+            .cond
+            .clone()
+            .unwrap_or_else(|| "(unconditional)".to_string());
+        if e.outputs.is_empty() {
+            cond
+        } else {
+            format!("{} / {}", cond, e.outputs.join(", "))
+        }
+    }
+
+    /// Graphviz DOT 形式の状態遷移図(リセットステートは二重丸)
+    pub fn to_dot(&self) -> String {
+        let reg = _StrOut(self.state_reg.clone());
+        let mut st = format!("digraph {} {{\n    rankdir=LR;\n", reg);
+        st += "    node [shape=circle];\n";
+        st += &format!("    \"{}\" [shape=point];\n", RESET_NODE);
+        for (i, s) in self.states.iter().enumerate() {
+            let name = _StrOut(s.state.clone());
+            let mut label = dot_escape(&name);
+            for m in &s.moore {
+                label += "\\n";
+                label += &dot_escape(&assign_label(&m.0, &m.1));
+            }
+            let attr = if i == 0 {
+                ", shape=doublecircle, style=filled, fillcolor=lightgray"
+            } else {
+                ""
+            };
+            st += &format!(
+                "    \"{}\" [label=\"{}\"{}];\n",
+                dot_escape(&name),
+                label,
+                attr
+            );
+        }
+        if let Some(s) = self.states.first() {
+            st += &format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                RESET_NODE,
+                dot_escape(&_StrOut(s.state.clone())),
+                dot_escape(&_StrOut(self.rst.clone()))
+            );
+        }
+        for e in self.edges() {
+            let style = if e.cond.is_none() { ", style=bold" } else { "" };
+            st += &format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+                dot_escape(&e.from),
+                dot_escape(&e.to),
+                dot_escape(&FsmModule::edge_label(&e)),
+                style
+            );
+        }
+        st + "}\n"
+    }
+
+    /// Mermaid の stateDiagram 形式の状態遷移図(リセットステートは強調表示)
+    pub fn to_mermaid(&self) -> String {
+        let mut st = "stateDiagram-v2\n".to_string();
+        if let Some(s) = self.states.first() {
+            let name = _StrOut(s.state.clone());
+            st += &format!(
+                "    [*] --> {} : {}\n",
+                name,
+                mermaid_escape(&_StrOut(self.rst.clone()))
+            );
+            st += "    classDef reset font-weight:bold,stroke-width:3px\n";
+            st += &format!("    class {} reset\n", name);
+        }
+        for s in &self.states {
+            for m in &s.moore {
+                st += &format!(
+                    "    {} : {}\n",
+                    _StrOut(s.state.clone()),
+                    mermaid_escape(&assign_label(&m.0, &m.1))
+                );
+            }
+        }
+        for e in self.edges() {
+            st += &format!(
+                "    {} --> {} : {}\n",
+                e.from,
+                e.to,
+                mermaid_escape(&FsmModule::edge_label(&e))
+            );
+        }
+        st
+    }
+}
+
+fn assign_label(signal: &E, value: &E) -> String {
+    format!(
+        "{}={}",
+        decomp_ast(Box::new(signal.clone()), "", 0),
+        decomp_ast(Box::new(value.clone()), "", 0)
+    )
+}
+
+// DOT の引用符付き文字列のエスケープ(\ を先に置き換える)
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Mermaid のラベルでタグと解釈される文字はエンティティで表す
+fn mermaid_escape(s: &str) -> String {
+    s.replace('<', "#lt;").replace('>', "#gt;")
+}
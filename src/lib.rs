@@ -1,6 +1,15 @@
 #[cfg(test)]
 mod tests {
+    use error::*;
+    use generate::*;
+    use lint::*;
+    use literal::*;
+    use memory::*;
+    use parser::*;
+    use sim::*;
+    use testbench::*;
     use vcore::*;
+    use F;
     #[test]
     fn it_works() {
         let mut m = VModule::new("LED");
@@ -8,7 +17,1489 @@ mod tests {
         m.input("RST", 1);
         assert!(!m.gen().is_empty(), "Code not generated successfully...");
     }
+
+    #[test]
+    fn instance() {
+        let mut sub = VModule::new("SUB");
+        let w = sub.add_io_param("W", 8);
+        sub.input("CLK", 1);
+        sub.input("D", 8);
+        sub.output("Q", &w);
+
+        let mut m = VModule::new("TOP");
+        let clk = m.input("CLK", 1);
+        let d = m.input("D", 8);
+        let q = m.output("Q", 4);
+        m.instance(&sub, "u_sub")
+            .param("W", 4)
+            .port("CLK", &clk)
+            .port("D", &d)
+            .port("Q", &q);
+        let st = m.gen();
+        assert!(st.contains("SUB #(\n        .W(4)\n    ) u_sub ("));
+        assert!(st.contains(".Q(Q)"));
+    }
+
+    #[test]
+    fn instance_width_mismatch() {
+        let mut sub = VModule::new("SUB");
+        sub.input("D", 8);
+
+        let mut m = VModule::new("TOP");
+        let d = m.input("D", 4);
+        m.instance(&sub, "u_sub").port("D", &d);
+        let errs = m.try_gen().unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].to_string(), "TOP: u_sub: port D is 8 bit but D is 4 bit");
+        assert!(m.gen().contains(".D(D)"));
+    }
+
+    #[test]
+    fn declarations() {
+        let mut m = VModule::new("DECL");
+        let w = m.add_io_param("W", 8);
+        let n = m.add_io_param("N", 16);
+        m.input("CLK", 1);
+        m.input("D", &w);
+        m.reg_out("Q", 4);
+        m.wire("S", 0);
+        m.reg("R", 32);
+        m.Mem("MEM", (&w, &n));
+        m.add_local_param("LP", 3);
+        let st = m.gen();
+        assert!(st.contains("    input  CLK,\n"));
+        assert!(st.contains("    input  [W-1:0] D,\n"));
+        assert!(st.contains("    output reg [3:0] Q\n"));
+        assert!(st.contains("    wire S;\n"));
+        assert!(st.contains("    reg [31:0] R;\n"));
+        assert!(st.contains("    reg [W-1:0] MEM [0:N-1];\n"));
+        assert!(st.contains("    localparam LP = 3;\n"));
+    }
+    #[test]
+    fn width_check() {
+        let mut m = VModule::new("WIDTH");
+        let a = m.input("A", 9);
+        let b = m.input("B", 9);
+        let c = m.wire("C", 10);
+        let d = m.wire("D", 4);
+        let e = m.reg("E", 8);
+        m.assign(c._e(&a - &b));
+        m.assign(d._e(a.range(3, 0)));
+        m.always(
+            posedge(&a)
+                .non()
+                .if_(&b, Form(e.sst(255)))
+                .else_(Form(e.sst(256))),
+        );
+        let errs = m.check_widths();
+        assert_eq!(errs.len(), 2);
+        assert_eq!((errs[0].lhs_width, errs[0].rhs_width), (10, 9));
+        assert!(!errs[0].is_truncation());
+        assert_eq!(errs[1].lhs, "E");
+        assert!(errs[1].is_truncation());
+        assert!(m.gen_checked().is_err());
+
+        // 桁あふれするパラメータ式は幅不明(None)になる
+        let p = m.add_io_param("P", 1);
+        let big = m.add_io_param("BIG", 0x4000_0000);
+        assert_eq!(m.const_eval(&(&p << 62)), Some(1 << 62));
+        assert_eq!(m.const_eval(&(&p << 64)), None);
+        assert_eq!(m.const_eval(&(&big * &big * &big)), None);
+        let ovf = m.add_io_param("OVF", &p << 64);
+        assert_eq!(m.infer_width(&ovf), None);
+    }
+    #[test]
+    fn try_gen_errors() {
+        let mut m = VModule::new("ERR");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let c = m.output("C", 8);
+        m.assign(c._e(Box::new(E::Null)));
+        m.fsm(FsmModule::new(&clk, &rst, "state").goto("IDLE", Box::new(E::Null)));
+        let errs = m.try_gen().unwrap_err();
+        assert_eq!(errs.len(), 3);
+        assert_eq!(errs[0].to_string(), "ERR: missing expression in assign C");
+        assert_eq!(
+            errs[1].to_string(),
+            "ERR: fsm state: goto IDLE called before AddState"
+        );
+        assert_eq!(errs[2].to_string(), "ERR: fsm state: no state added");
+
+        // ポートにできない種別は出力せずにエラーとして報告する
+        let mut m = VModule::new("PORT");
+        let a = m.input("A", 1);
+        let y = m.output("Y", 1);
+        m.assign(y._e(&a));
+        let mut p = WireVar::new();
+        p.parameter("P", 0);
+        m.io_port.push(p);
+        let errs = m.try_gen().unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].to_string(), "PORT: P is not an input, output or inout");
+        assert!(m.gen().contains("module PORT (\n    input  A,\n    output Y\n);\n"));
+        assert!(m
+            .gen_with(Backend::SystemVerilog)
+            .contains("(\n    input  logic A,\n    output logic Y\n);\n"));
+
+        let mut m = VModule::new("CUR");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let mut fsm = FsmModule::new(&clk, &rst, "state").AddState("A");
+        fsm.from("B", &go);
+        fsm.AddState("B");
+        fsm.Current("B");
+        fsm.from("A", &go);
+        m.fsm(fsm);
+        let errs = m.try_gen().unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(
+            errs[0].to_string(),
+            "CUR: fsm state: from B called without a current state"
+        );
+        assert!(m.gen().contains("                A : begin\n                    if(GO) begin\n                        state_Next <= B;\n"));
+    }
+
+    #[test]
+    fn parse_verilog() {
+        let src = "
+module CNT #(parameter W = 8) (
+    input  CLK,
+    input  RST,
+    input  EN,
+    output reg [W-1:0] Q
+);
+    wire INC = EN && !RST;
+    always @(posedge CLK) begin
+        if (RST) Q <= 0;
+        else if (INC) Q <= Q + 8'h01;
+    end
+endmodule
+";
+        let m = VModule::from_verilog(src).unwrap();
+        let st = m.gen();
+        assert!(st.contains("    output reg [W-1:0] Q\n"));
+        assert_eq!(VModule::from_verilog(&st).unwrap().gen(), st);
+
+        let bb = parse_black_box(src).unwrap();
+        assert_eq!(bb[0].io_port.len(), 4);
+        assert!(bb[0].always.is_empty());
+
+        match VModule::from_verilog("module M(input A);\n assign B = A;\nendmodule") {
+            Err(VerugentError::Parse { line: 2, .. }) => {}
+            r => panic!("{:?}", r.map(|m| m.gen())),
+        }
+
+        // 置き換えると意味の変わる構文はエラーにする
+        for (body, msg) in [
+            (
+                "assign Y = A === B;",
+                "case equality operator '===' is not supported",
+            ),
+            (
+                "always @(posedge A or B) Y <= A;",
+                "sensitivity list mixes edges and levels",
+            ),
+            ("reg R = 1;", "initial value of reg R is not supported"),
+        ]
+        .iter()
+        {
+            let src = format!(
+                "module M(input A, input B, output reg Y);\n{}\nendmodule",
+                body
+            );
+            match VModule::from_verilog(&src) {
+                Err(VerugentError::Parse { line: 2, message }) => assert_eq!(message, *msg),
+                r => panic!("{:?}", r.map(|m| m.gen())),
+            }
+        }
+
+        // 属性付きの FSM の出力を読み戻す
+        let mut m = VModule::new("ENC");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let mut fsm = FsmModule::new(&clk, &rst, "state");
+        fsm.encoding(FsmEncoding::OneHot);
+        fsm.AddState("IDLE");
+        fsm.goto("RUN", &go);
+        fsm.AddState("RUN");
+        fsm.goto("IDLE", Box::new(E::Null));
+        m.fsm(fsm);
+        let st = m.gen();
+        assert!(st.contains("(* fsm_encoding = \"one_hot\" *)"));
+        let st = VModule::from_verilog(&st).unwrap().gen();
+        assert!(st.contains("    reg [1:0] state;\n"));
+        assert!(st.contains("                        state_Next <= RUN;\n"));
+        assert_eq!(VModule::from_verilog(&st).unwrap().gen(), st);
+    }
+
+    #[test]
+    fn systemverilog() {
+        let mut m = VModule::new("SV");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let q = m.reg_out("Q", 8);
+        let n = m.reg("N", 8);
+        m.always(
+            onedge()
+                .Case(&q)
+                .S(0, vec![n.sst(1)])
+                .Default(vec![n.sst(0)]),
+        );
+        m.always(
+            posedge(&clk)
+                .non()
+                .if_(&rst, vec![q.sst(0)])
+                .else_(vec![q.sst(&n)]),
+        );
+        let mut fsm = FsmModule::new(&clk, &rst, "state").AddState("IDLE");
+        fsm.goto("RUN", &go);
+        fsm.AddState("RUN");
+        fsm.goto("IDLE", Box::new(E::Null));
+        m.fsm(fsm);
+        let st = m.gen_with(Backend::SystemVerilog);
+        assert!(st.contains("    output logic [7:0] Q\n"));
+        assert!(st.contains("    logic [7:0] N;\n"));
+        assert!(st.contains("    always_comb begin\n        unique case (Q)\n"));
+        assert!(st.contains("    always_ff @(posedge CLK) begin\n"));
+        assert!(st.contains(
+            "    typedef enum logic [0:0] {\n        IDLE,\n        RUN\n    } state_t;\n"
+        ));
+        assert!(st.contains("    state_t state, state_Next;\n"));
+        assert!(!st.contains("localparam"));
+    }
+
+    #[test]
+    fn vhdl() {
+        let mut m = VModule::new("VH");
+        let w = m.add_io_param("W", 8);
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let q = m.reg_out("Q", &w);
+        m.always(
+            posedge(&clk)
+                .posedge(&rst)
+                .non()
+                .if_(&rst, vec![q.sst(0)])
+                .else_(vec![q.sst(&q + 1)]),
+        );
+        let mut fsm = FsmModule::new(&clk, &rst, "state").AddState("IDLE");
+        fsm.goto("END", &go);
+        fsm.AddState("END");
+        m.fsm(fsm);
+        let st = m.gen_with(Backend::Vhdl);
+        assert!(st.contains("entity VH is\n    generic (\n        W : integer := 8\n    );\n"));
+        assert!(st.contains("        Q : out unsigned(W-1 downto 0)\n"));
+        assert!(st.contains("    type state_t is (IDLE, \\END\\);\n"));
+        assert!(st.contains("        if RST = '1' then\n            Q <= to_unsigned(0, Q'length);\n        elsif rising_edge(CLK) then\n"));
+        assert!(st.contains("            Q <= resize(Q + 1, Q'length);\n"));
+        assert!(st.ends_with("end architecture rtl;\n"));
+    }
+
+    #[test]
+    fn simulator() {
+        let mut sub = VModule::new("CNT");
+        let w = sub.add_io_param("W", 8);
+        let c = sub.input("CLK", 1);
+        let r = sub.input("RST", 1);
+        let q = sub.reg_out("Q", &w);
+        sub.always(
+            posedge(&c)
+                .non()
+                .if_(&r, vec![q.sst(0)])
+                .else_(vec![q.sst(&q + 1)]),
+        );
+
+        let mut m = VModule::new("TOP");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let cnt = m.output("CNT", 4);
+        let busy = m.output("BUSY", 1);
+        m.instance(&sub, "u_cnt")
+            .param("W", 4)
+            .port("CLK", &clk)
+            .port("RST", &rst)
+            .port("Q", &cnt);
+        let mut fsm = FsmModule::new(&clk, &rst, "state").AddState("IDLE");
+        fsm.goto("RUN", &go);
+        fsm.AddState("RUN");
+        let run = fsm.Param("RUN");
+        let state = m.fsm(fsm);
+        m.assign(busy._e(state.eq(&run)));
+
+        let mut s = Simulator::new(&m);
+        s.poke(&rst, 1);
+        s.tick(&clk);
+        s.poke(&rst, 0);
+        for _ in 0..18 {
+            s.tick(&clk);
+        }
+        assert_eq!(s.peek(&cnt), 2);
+        assert_eq!(s.value("u_cnt.Q"), Some(2));
+        assert_eq!(s.peek(&busy), 0);
+        s.poke(&go, 1);
+        s.tick(&clk);
+        s.tick(&clk);
+        assert_eq!(s.peek(&busy), 1);
+        assert_eq!(s.cycle(), 21);
+    }
+
+    #[test]
+    fn vcd() {
+        let mut sub = VModule::new("CNT");
+        let c = sub.input("CLK", 1);
+        let q = sub.reg_out("Q", 2);
+        sub.always(posedge(&c).non().if_(&c, vec![q.sst(&q + 1)]));
+
+        let mut m = VModule::new("TOP");
+        let clk = m.input("CLK", 1);
+        let en = m.input("EN", 1);
+        let cnt = m.output("CNT", 2);
+        m.instance(&sub, "u_cnt").port("CLK", &clk).port("Q", &cnt);
+
+        let mut s = Simulator::new(&m);
+        s.trace_vcd(&[]);
+        s.tick(&clk);
+        let all = s.vcd().unwrap();
+        assert!(all.contains("$scope module TOP $end"));
+        assert!(all.contains("$scope module u_cnt $end"));
+        assert!(all.contains("$var reg 2"));
+        assert!(all.contains("#1\n"));
+
+        let mut s = Simulator::new(&m);
+        s.trace_vcd(&["u_cnt.*"]);
+        for _ in 0..3 {
+            s.tick(&clk);
+        }
+        let st = s.vcd().unwrap();
+        assert!(!st.contains(" EN $end"));
+        assert!(st.contains(" Q $end"));
+        assert!(st.contains("#5\n1!\nb11 "));
+        s.poke(&en, 1);
+        assert!(s.save_vcd("/dev/null").is_ok());
+    }
+
+    #[test]
+    fn testbench() {
+        let mut m = VModule::new("CNT");
+        let w = m.add_io_param("W", 4);
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let en = m.input("EN", 1);
+        let q = m.reg_out("Q", &w);
+        m.always(
+            posedge(&clk)
+                .non()
+                .if_(&rst, vec![q.sst(0)])
+                .else_if(&en, vec![q.sst(&q + 1)]),
+        );
+
+        let mut tb = Testbench::new(&m);
+        tb.clock(&clk, 10).reset(&rst, 1, 20);
+        tb.at(20).drive(&en, 1);
+        tb.at(50).expect(&q, 3).drive(&en, 0);
+        let st = tb.gen();
+        assert!(st.contains("module CNT_tb;"));
+        assert!(st.contains("    wire [W-1:0] Q;"));
+        assert!(st.contains("        .Q(Q)"));
+        assert!(st.contains("forever #5 CLK = ~CLK;"));
+        assert!(st.contains("$dumpfile(\"CNT_tb.vcd\");"));
+        assert!(st.contains("        RST = 1;\n        EN = 0;\n        #20;\n        RST = 0;\n        EN = 1;\n        #30;\n        if (Q !== 3)"));
+
+        // 奇数の周期は High を 1 長くする
+        let st = Testbench::new(&m).clock(&clk, 3).gen();
+        assert!(st.contains(
+            "        CLK = 0;\n        forever begin\n            #1 CLK = 1;\n            #2 CLK = 0;\n        end\n"
+        ));
+    }
+
+    #[test]
+    fn lint() {
+        let mut m = VModule::new("LINT");
+        let clk = m.input("CLK", 1);
+        let a = m.input("A", 1);
+        let _unused = m.input("B", 1);
+        let x = m.output("X", 2);
+        let _y = m.output("Y", 1);
+        let w = m.wire("W", 1);
+        let r = m.reg("R", 1);
+        let z = m.output("Z", 1);
+        m.assign(x.range(0, 0)._e(&a));
+        m.assign(x.range(1, 1)._e(&w));
+        m.assign(z._e(&a));
+        m.always(posedge(&clk).non().if_(&a, vec![r.sst(&a)]));
+        m.always(posedge(&clk).non().if_(&a, vec![r.sst(0), z.sst(&r)]));
+
+        let lints = m.lint();
+        let find = |kind: LintKind| {
+            lints
+                .iter()
+                .filter(|l| l.kind == kind)
+                .map(|l| l.signal.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(find(LintKind::MultipleAssign).is_empty());
+        assert_eq!(find(LintKind::MultipleAlways), vec!["R"]);
+        assert_eq!(find(LintKind::MixedDrivers), vec!["Z"]);
+        assert_eq!(find(LintKind::UndrivenOutput), vec!["Y"]);
+        assert_eq!(find(LintKind::UndrivenSignal), vec!["W"]);
+        assert_eq!(find(LintKind::UnusedInput), vec!["B"]);
+        let y = lints.iter().find(|l| l.signal == "Y").unwrap();
+        assert_eq!(y.severity, Severity::Error);
+        assert_eq!(y.to_string(), "error: LINT: Y: output is never driven");
+    }
+
+    #[test]
+    fn latch() {
+        let mut m = VModule::new("LATCH");
+        let a = m.input("A", 1);
+        let s = m.input("S", 2);
+        let x = m.reg_out("X", 1);
+        let y = m.reg_out("Y", 1);
+        let z = m.reg_out("Z", 1);
+        let full = onedge()
+            .if_(&a, vec![x.sst(1)])
+            .else_(vec![x.sst(0)])
+            .Case(&s)
+            .S(0, vec![z.sst(1)])
+            .Default(vec![z.sst(0)]);
+        m.always(full);
+        m.always(
+            onedge()
+                .if_(&a, vec![y.sst(1)])
+                .Case(&s)
+                .S(0, vec![y.sst(0)]),
+        );
+        let latches = m
+            .lint()
+            .into_iter()
+            .filter(|l| l.kind == LintKind::Latch)
+            .map(|l| l.signal)
+            .collect::<Vec<_>>();
+        assert_eq!(latches, vec!["Y"]);
+
+        let mut m = VModule::new("NOLATCH");
+        let a = m.input("A", 1);
+        let y = m.reg_out("Y", 1);
+        m.always(onedge().if_(&a, vec![y.sst(1)]).no_latch());
+        assert!(m.lint().is_empty());
+        assert!(m
+            .gen()
+            .contains("always @(*) begin\n        Y = 0;\n        if(A) begin"));
+    }
+
+    #[test]
+    fn subst_rules() {
+        let mut m = VModule::new("SUBST");
+        let clk = m.input("CLK", 1);
+        let a = m.input("A", 1);
+        let x = m.reg_out("X", 1);
+        let y = m.reg_out("Y", 1);
+        let t = m.reg("T", 1);
+        let c = m.reg_out("C", 1);
+        m.always(
+            posedge(&clk)
+                .non()
+                .if_(&a, vec![t.sst_block(&a), x.sst(&t)])
+                .else_(vec![t.sst_block(0), x.sst(0)]),
+        );
+        m.always(posedge(&clk).block().if_(&a, vec![y.sst(&a)]));
+        m.always(onedge().if_(&a, vec![c.sst_non(1)]).else_(vec![c.sst(0)]));
+
+        let lints = m.lint();
+        let find = |kind: LintKind| {
+            lints
+                .iter()
+                .filter(|l| l.kind == kind)
+                .map(|l| l.signal.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(find(LintKind::BlockingInSequential), vec!["T", "Y"]);
+        assert_eq!(find(LintKind::NonBlockingInCombinational), vec!["C"]);
+        assert_eq!(find(LintKind::MixedSubst), vec!["C"]);
+
+        let st = m.gen();
+        assert!(st.contains("            T = A;\n            X <= T;\n"));
+        assert!(st.contains("            C <= 1;\n"));
+        let mut s = Simulator::new(&m);
+        s.poke(&a, 1);
+        s.tick(&clk);
+        assert_eq!(s.peek(&x), 1);
+        let p = VModule::from_verilog(&st).unwrap();
+        assert_eq!(p.gen(), st);
+    }
+
+    // 比較用の式の構造
+    fn shape(e: &E) -> String {
+        match *e {
+            E::Ldc(ref wr) => wr.name.clone(),
+            E::Num(i) => i.to_string(),
+            E::Un(op, ref a) => format!("({:?} {})", op, shape(a)),
+            E::Red(op, ref a) => format!("({:?} {})", op, shape(a)),
+            E::Bin(op, ref l, ref r) => format!("({:?} {} {})", op, shape(l), shape(r)),
+            E::PL(ref c, ref t, ref f) => format!("(? {} {} {})", shape(c), shape(t), shape(f)),
+            _ => panic!("unexpected expression {:?}", e),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let mut m = VModule::new("PREC");
+        let a = m.input("A", 4);
+        let b = m.input("B", 4);
+        let c = m.input("C", 4);
+        let y = m.output("Y", 4);
+        let print = |e: Box<E>| decomp_ast(e, "", 0);
+        assert_eq!(print(&a & (&b | &c)), "A&(B|C)");
+        assert_eq!(print((&a << 2) + 1), "(A<<2)+1");
+        assert_eq!(print(!(&a & &b)), "~(A&B)");
+        assert_eq!(print(&a - (&b - &c)), "A-(B-C)");
+        assert_eq!(print(&a - &b - &c), "A-B-C");
+        assert_eq!(print(&a * (&b + &c)), "A*(B+C)");
+        assert_eq!(print(&a ^ !b.clone()), "A^(~B)");
+        assert_eq!(print((&a | &b) & &c), "(A|B)&C");
+        assert_eq!(print(&a << (&b + &c)), "A<<B+C");
+
+        // LRM の優先順位表(高い順)と出力を比較する
+        let lrm: [&[(BinOp, &str)]; 10] = [
+            &[(BinOp::Mul, "*"), (BinOp::Div, "/"), (BinOp::Mod, "%")],
+            &[(BinOp::Add, "+"), (BinOp::Sub, "-")],
+            &[(BinOp::Shl, "<<"), (BinOp::Shr, ">>"), (BinOp::Sra, ">>>")],
+            &[
+                (BinOp::Lt, "<"),
+                (BinOp::Le, "<="),
+                (BinOp::Gt, ">"),
+                (BinOp::Ge, ">="),
+            ],
+            &[(BinOp::Eq, "=="), (BinOp::Ne, "!=")],
+            &[(BinOp::And, "&")],
+            &[(BinOp::Xor, "^")],
+            &[(BinOp::Or, "|")],
+            &[(BinOp::LAnd, "&&")],
+            &[(BinOp::LOr, "||")],
+        ];
+        let bin = |op: BinOp, l: &Box<E>, r: &Box<E>| Box::new(E::Bin(op, l.clone(), r.clone()));
+        for (i, level) in lrm.iter().enumerate() {
+            for &(x, xs) in level.iter() {
+                // 同じ優先順位は左結合
+                for &(y, ys) in level.iter() {
+                    assert_eq!(
+                        print(bin(y, &bin(x, &a, &b), &c)),
+                        format!("A{}B{}C", xs, ys)
+                    );
+                    assert_eq!(
+                        print(bin(x, &a, &bin(y, &b, &c))),
+                        format!("A{}(B{}C)", xs, ys)
+                    );
+                }
+                // 優先順位の低い演算子のオペランドは括弧で囲む
+                for &(y, ys) in lrm[i + 1..].iter().flat_map(|l| l.iter()) {
+                    assert_eq!(
+                        print(bin(y, &bin(x, &a, &b), &c)),
+                        format!("A{}B{}C", xs, ys)
+                    );
+                    assert_eq!(
+                        print(bin(y, &a, &bin(x, &b, &c))),
+                        format!("A{}B{}C", ys, xs)
+                    );
+                    assert_eq!(
+                        print(bin(x, &bin(y, &a, &b), &c)),
+                        format!("(A{}B){}C", ys, xs)
+                    );
+                    assert_eq!(
+                        print(bin(x, &a, &bin(y, &b, &c))),
+                        format!("A{}(B{}C)", xs, ys)
+                    );
+                }
+            }
+        }
+
+        // 乱数で生成した式の出力を再度構文解析して構造を比較する
+        let vars = [a, b, c];
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        fn gen(depth: u32, vars: &[Box<E>], rand: &mut dyn FnMut(u64) -> u64) -> Box<E> {
+            let bins = [
+                BinOp::Add,
+                BinOp::Sub,
+                BinOp::Mul,
+                BinOp::Div,
+                BinOp::Mod,
+                BinOp::Or,
+                BinOp::And,
+                BinOp::Xor,
+                BinOp::LOr,
+                BinOp::LAnd,
+                BinOp::Shl,
+                BinOp::Shr,
+                BinOp::Sra,
+                BinOp::Eq,
+                BinOp::Ne,
+                BinOp::Lt,
+                BinOp::Gt,
+                BinOp::Le,
+                BinOp::Ge,
+            ];
+            let uns = [UnaryOp::Not, UnaryOp::LNot, UnaryOp::Neg];
+            let reds = [
+                ReduceOp::And,
+                ReduceOp::Or,
+                ReduceOp::Xor,
+                ReduceOp::Nand,
+                ReduceOp::Nor,
+                ReduceOp::Xnor,
+            ];
+            match if depth == 0 { rand(2) } else { rand(6) } {
+                0 => vars[rand(3) as usize].clone(),
+                1 => Box::new(E::Num(rand(16) as i32)),
+                2 => Box::new(E::Un(uns[rand(3) as usize], gen(depth - 1, vars, rand))),
+                3 => Box::new(E::Red(reds[rand(6) as usize], gen(depth - 1, vars, rand))),
+                4 => Box::new(E::PL(
+                    gen(depth - 1, vars, rand),
+                    gen(depth - 1, vars, rand),
+                    gen(depth - 1, vars, rand),
+                )),
+                _ => Box::new(E::Bin(
+                    bins[rand(19) as usize],
+                    gen(depth - 1, vars, rand),
+                    gen(depth - 1, vars, rand),
+                )),
+            }
+        }
+        for _ in 0..500 {
+            let e = gen(4, &vars, &mut rand);
+            let mut t = m.clone();
+            t.assign(y._e(e.clone()));
+            let p = VModule::from_verilog(&t.gen()).unwrap();
+            assert_eq!(shape(&p.assign[0].rhs), shape(&e), "{}", print(e.clone()));
+        }
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(Literal::new(8, 0xff).to_string(), "8'hFF");
+        assert_eq!(
+            Literal::new(8, 5).radix(Radix::Bin).to_string(),
+            "8'b00000101"
+        );
+        assert_eq!(
+            Literal::new(9, 0o17).radix(Radix::Oct).to_string(),
+            "9'o017"
+        );
+        assert_eq!(Literal::z(4).to_string(), "4'bzzzz");
+        assert_eq!(Literal::x(8).radix(Radix::Hex).to_string(), "8'hxx");
+        assert_eq!(
+            Literal::from(u64::MAX).to_string(),
+            "64'd18446744073709551615"
+        );
+        assert_eq!(
+            Literal::from(u128::MAX).radix(Radix::Hex).to_string(),
+            format!("128'h{}", "F".repeat(32))
+        );
+        assert_eq!(Literal::from(-5i64).to_string(), "-64'sd5");
+        assert_eq!(Literal::from(-5i64).to_i64(), Some(-5));
+
+        for raw in &[
+            "8'hFF",
+            "4'b10xz",
+            "'bz",
+            "12'o70x7",
+            "16'sd300",
+            "8'dx",
+            "100'd1",
+            "72'd2361183241434822606848",
+        ] {
+            assert_eq!(Literal::parse(raw).unwrap().to_string(), *raw);
+        }
+        let l = Literal::parse("8'hx1").unwrap();
+        assert!(l.is_4state());
+        assert_eq!(l.to_string(), "8'hx1");
+        let l = Literal::parse("8'b1x010000").unwrap().radix(Radix::Hex);
+        assert_eq!(l.to_string(), "8'b1x010000");
+        assert_eq!(Literal::parse("6'h3").unwrap().to_string(), "6'h03");
+        assert_eq!(Literal::parse("4'bx").unwrap().to_string(), "4'bxxxx");
+        assert_eq!(Literal::parse("4'sb1100").unwrap().to_i64(), Some(-4));
+        assert!(Literal::parse("8'hG").is_err());
+
+        let mut m = VModule::new("LIT");
+        let a = m.input("A", 64);
+        let y = m.output("Y", 64);
+        let t = m.output("T", 8);
+        m.assign(y._e(&a + Literal::new(64, 0x1_0000_0000).radix(Radix::Hex)));
+        m.assign(t._e(Literal::z(8)));
+        let st = m.gen();
+        assert!(st.contains("assign Y = A+64'h0000000100000000;"));
+        assert!(st.contains("assign T = 8'bzzzzzzzz;"));
+        assert_eq!(VModule::from_verilog(&st).unwrap().gen(), st);
+        assert!(m.check_widths().is_empty());
+        assert!(m
+            .gen_with(Backend::Vhdl)
+            .contains("    T <= unsigned'(\"ZZZZZZZZ\");\n"));
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&a, 5);
+        sim.eval();
+        assert_eq!(sim.peek(&y), 0x1_0000_0005);
+    }
+
+    #[test]
+    fn concatenation() {
+        let mut m = VModule::new("CAT");
+        let a = m.input("A", 8);
+        let w = m.input("W", 16);
+        let ext = m.output("EXT", 16);
+        let swap = m.output("SWAP", 16);
+        let hi = m.output("HI", 4);
+        let lo = m.output("LO", 4);
+        let red = m.output("RED", 6);
+        m.assign(ext._e(concat(vec![replicate(8, a.addr(7)), a.clone()])));
+        m.assign(swap._e(concat(vec![w.range(7, 0), w.range(15, 8)])));
+        m.assign(concat(vec![hi.clone(), lo.clone()])._e(&a));
+        m.assign(red._e(concat(vec![
+            a.red_and(),
+            a.red_or(),
+            a.red_xor(),
+            a.red_nand(),
+            a.red_nor(),
+            a.red_xnor(),
+        ])));
+        let st = m.gen();
+        assert!(st.contains("assign EXT = {{8{A[7]}}, A};"));
+        assert!(st.contains("assign SWAP = {W[7:0], W[15:8]};"));
+        assert!(st.contains("assign {HI, LO} = A;"));
+        assert!(st.contains("assign RED = {&A, |A, ^A, ~&A, ~|A, ~^A};"));
+        assert_eq!(VModule::from_verilog(&st).unwrap().gen(), st);
+        assert!(m.check_widths().is_empty());
+        assert!(m.lint().is_empty());
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&a, 0x96);
+        sim.poke(&w, 0x1234);
+        sim.eval();
+        assert_eq!(sim.peek(&ext), 0xff96);
+        assert_eq!(sim.peek(&swap), 0x3412);
+        assert_eq!((sim.peek(&hi), sim.peek(&lo)), (0x9, 0x6));
+        assert_eq!(sim.peek(&red), 0b010101);
+
+        let vh = m.gen_with(Backend::Vhdl);
+        assert!(vh.contains("    (HI, LO) <= A;\n"));
+        assert!(vh.contains("    SWAP <= unsigned'(W(7 downto 0) & W(15 downto 8));\n"));
+    }
+
+    #[test]
+    fn signed() {
+        let mut m = VModule::new("SGN");
+        let a = m.input("A", Signed(8));
+        let b = m.input("B", Signed(8));
+        let u = m.input("U", 8);
+        let sum = m.output("SUM", Signed(16));
+        let lt = m.output("LT", 1);
+        let ult = m.output("ULT", 1);
+        let neg = m.output("NEG", 1);
+        let sra = m.output("SRA", Signed(8));
+        let mix = m.output("MIX", 16);
+        m.assign(sum._e(&a + &b));
+        m.assign(lt._e(F!(a < b)));
+        let (ua, ub) = (a.unsigned(), b.unsigned());
+        m.assign(ult._e(F!(ua < ub)));
+        m.assign(neg._e(F!(a < 0)));
+        m.assign(sra._e(_RSHA(&a, 2)));
+        m.assign(mix._e(&a + &u));
+        let st = m.gen();
+        assert!(st.contains("    input  signed [7:0] A,\n"));
+        assert!(st.contains("    output signed [15:0] SUM,\n"));
+        assert!(st.contains("assign ULT = $unsigned(A)<$unsigned(B);"));
+        assert!(st.contains("assign SRA = A>>>2;"));
+        assert_eq!(VModule::from_verilog(&st).unwrap().gen(), st);
+        assert!(m.is_signed(&(&a + &b)));
+        assert!(!m.is_signed(&(&a + &u)));
+        let errs = m.check_widths();
+        assert_eq!(
+            errs[0].to_string(),
+            "SGN: SUM (16 bit) = A+B (8 bit) is sign-extended"
+        );
+        assert_eq!(
+            errs.last().unwrap().to_string(),
+            "SGN: MIX (16 bit) = A+U (8 bit) is zero-extended"
+        );
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&a, 0xfd);
+        sim.poke(&b, 5);
+        sim.poke(&u, 1);
+        sim.eval();
+        assert_eq!(sim.peek(&sum), 0x0002);
+        assert_eq!(sim.peek(&lt), 1);
+        assert_eq!(sim.peek(&ult), 0);
+        assert_eq!(sim.peek(&neg), 1);
+        assert_eq!(sim.peek(&sra), 0xff);
+        assert_eq!(sim.peek(&mix), 0x00fe);
+        sim.poke(&b, 0xfb);
+        sim.eval();
+        assert_eq!(sim.peek(&sum), 0xfff8);
+        assert_eq!(sim.peek(&lt), 0);
+
+        let vh = m.gen_with(Backend::Vhdl);
+        assert!(vh.contains("A : in signed(7 downto 0)"));
+        assert!(vh.contains("ULT <= '1' when unsigned(A) < unsigned(B) else '0';"));
+    }
+    #[test]
+    fn generate_blocks() {
+        let mut sub = VModule::new("INV");
+        let a = sub.input("A", 1);
+        let y = sub.output("Y", 1);
+        sub.assign(y._e(!a));
+
+        let mut m = VModule::new("GEN");
+        let w = m.add_io_param("W", 4);
+        let a = m.input("A", &w);
+        let b = m.input("B", &w);
+        let x = m.output("X", &w);
+        let y = m.output("Y", &w);
+        let z = m.output("Z", 1);
+        let i = m.genvar("i");
+        let mut g = Generate::for_(&i, 0, F!(i < w), &i + 1, "g_bit");
+        g.assign(x.addr(&i)._e(a.addr(&i) ^ b.addr(&i)));
+        g.instance(&sub, "u_inv")
+            .port("A", a.addr(&i))
+            .port("Y", y.addr(&i));
+        m.generate(g);
+        let mut h = Generate::if_(F!(w > 8), "g_wide");
+        h.assign(z._e(1));
+        m.generate(h.else_("g_narrow").assign(z._e(0)));
+
+        let st = m.gen();
+        assert!(st.contains("    genvar i;\n"));
+        assert!(st.contains("        for (i = 0; i<W; i = i+1) begin : g_bit\n"));
+        assert!(st.contains("            assign X[i] = A[i]^B[i];\n"));
+        assert!(st.contains("            INV u_inv (\n"));
+        assert!(st.contains(
+            "        if (W>8) begin : g_wide\n            assign Z = 1;\n        end else begin : g_narrow\n"
+        ));
+        assert!(m.lint().is_empty());
+        let vhdl = m.gen_with(Backend::Vhdl);
+        assert!(vhdl.contains("    g_bit : for i in 0 to W - 1 generate\n"));
+        assert!(vhdl.contains("    else g_narrow : generate\n"));
+
+        // パラメータのデフォルト値で展開してシミュレーションする
+        let mut sim = Simulator::new(&m);
+        sim.poke(&a, 0b1010);
+        sim.poke(&b, 0b0110);
+        sim.eval();
+        assert_eq!(sim.peek(&x), 0b1100);
+        assert_eq!(sim.peek(&y), 0b0101);
+        assert_eq!(sim.peek(&z), 0);
+        assert_eq!(sim.value("g_bit[1].u_inv.Y"), Some(0));
+    }
+    #[test]
+    fn param_expressions() {
+        let mut sub = VModule::new("RAM");
+        let w = sub.add_io_param("WIDTH", 8);
+        let d = sub.add_io_param("DEPTH", 16);
+        let aw = sub.add_io_param("ADDR_W", clog2(&d));
+        let clk = sub.input("CLK", 1);
+        let ad = sub.input("AD", &aw);
+        let din = sub.input("D", &w * 2);
+        let q = sub.output("Q", &w * 2);
+        let mem = sub.Mem("MEM", (&w * 2, &d));
+        sub.assign(q._e(mem.addr(&ad)));
+        sub.always(
+            Always::new()
+                .posedge(&clk)
+                .non()
+                .if_(1, Form(mem.addr(&ad).sst(&din))),
+        );
+        let st = sub.gen();
+        assert!(st.contains("    parameter ADDR_W = $clog2(DEPTH)\n"));
+        assert!(st.contains("    input  [ADDR_W-1:0] AD,\n"));
+        assert!(st.contains("    output [WIDTH*2-1:0] Q\n"));
+        assert!(st.contains("    reg [WIDTH*2-1:0] MEM [0:DEPTH-1];\n"));
+        assert_eq!(sub.const_eval(&aw), Some(4));
+        let vhdl = sub.gen_with(Backend::Vhdl);
+        assert!(vhdl.contains("use ieee.math_real.all;\n"));
+        assert!(vhdl.contains("ADDR_W : integer := integer(ceil(log2(real(DEPTH))))"));
+        assert!(vhdl.contains("Q : out unsigned(WIDTH*2-1 downto 0)"));
+
+        // 上書きしたパラメータから幅の式を評価する
+        let mut m = VModule::new("TOP");
+        let clk = m.input("CLK", 1);
+        let ad = m.input("AD", 8);
+        let d = m.input("D", 8);
+        let q = m.output("Q", 8);
+        m.instance(&sub, "u_ram")
+            .param("WIDTH", 4)
+            .param("DEPTH", 256)
+            .port("CLK", &clk)
+            .port("AD", &ad)
+            .port("D", &d)
+            .port("Q", &q);
+        assert!(m.validate().is_empty());
+        let mut sim = Simulator::new(&m);
+        sim.poke(&ad, 200);
+        sim.poke(&d, 0x5A);
+        sim.tick(&clk);
+        assert_eq!(sim.peek(&q), 0x5A);
+
+        let parsed = VModule::from_verilog(&st).unwrap();
+        assert_eq!(parsed.gen(), st);
+    }
+
+    #[test]
+    fn memories() {
+        let mut m = VModule::new("MEMS");
+        let clk = m.input("CLK", 1);
+        let we = m.input("WE", 1);
+        let be = m.input("BE", 2);
+        let a = m.input("A", 4);
+        let d = m.input("D", 16);
+        let q = m.output("Q", 16);
+        let rq = m.output("RQ", 8);
+        m.memory(
+            Memory::new("ram", MemKind::SinglePort, 16, 16)
+                .mode(WriteMode::WriteFirst)
+                .byte_enable(8)
+                .port(
+                    MemPort::new(&clk, &a)
+                        .write(&we, &d)
+                        .byte_enable(&be)
+                        .read(&q),
+                ),
+        );
+        m.memory(
+            Memory::new("rom", MemKind::Rom, 8, 16)
+                .latency(2)
+                .init(vec![0x12, 0x34, 0xAB])
+                .port(MemPort::new(&clk, &a).read(&rq)),
+        );
+        let st = m.gen();
+        assert!(st.contains("    reg [7:0] rom_a_q2;\n"));
+        assert!(st.contains("    initial $readmemh(\"rom.mem\", rom);\n"));
+        assert!(st.contains("            ram[A][15:8] <= D[15:8];\n"));
+        assert!(st.contains("    assign RQ = rom_a_q2;\n"));
+        assert_eq!(
+            m.mem_files(),
+            vec![("rom.mem".to_string(), "12\n34\nAB\n".to_string())]
+        );
+        assert!(m
+            .gen_with(Backend::Vhdl)
+            .contains("signal rom : rom_t := (0 => \"00010010\", 1 => \"00110100\", 2 => \"10101011\", others => (others => '0'));"));
+        assert!(m.lint().is_empty());
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&a, 1);
+        sim.tick(&clk);
+        sim.tick(&clk);
+        assert_eq!(sim.peek(&rq), 0x34);
+        sim.poke(&we, 1);
+        sim.poke(&be, 2);
+        sim.poke(&d, 0xBEEF);
+        sim.tick(&clk);
+        assert_eq!(sim.peek(&q), 0xBE00);
+
+        // トゥルーデュアルポートは両ポートから書き込める
+        let mut t = VModule::new("TDP");
+        let clk = t.input("CLK", 1);
+        let (wa, wb) = (t.input("WA", 1), t.input("WB", 1));
+        let (aa, ab) = (t.input("AA", 3), t.input("AB", 3));
+        let d = t.input("D", 8);
+        let (qa, qb) = (t.output("QA", 8), t.output("QB", 8));
+        t.memory(
+            Memory::new("tdp", MemKind::TrueDualPort, 8, 8)
+                .mode(WriteMode::NoChange)
+                .port(MemPort::new(&clk, &aa).write(&wa, &d).read(&qa))
+                .port(MemPort::new(&clk, &ab).write(&wb, &d).read(&qb)),
+        );
+        assert!(t.lint().is_empty());
+        let mut sim = Simulator::new(&t);
+        sim.poke(&wa, 1);
+        sim.poke(&aa, 5);
+        sim.poke(&d, 0x77);
+        sim.poke(&ab, 5);
+        sim.tick(&clk);
+        sim.poke(&wa, 0);
+        sim.tick(&clk);
+        assert_eq!((sim.peek(&qa), sim.peek(&qb)), (0x77, 0x77));
+
+        // Verilog と初期値ファイルを同じディレクトリに書き出す
+        let dir = std::env::temp_dir().join(format!("verugent_mem_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("MEMS.v");
+        m.save(path.to_str().unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), m.gen());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("rom.mem")).unwrap(),
+            "12\n34\nAB\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // 構成の誤りは validate() のエラーとして報告する
+        let mut bad = VModule::new("BAD");
+        let clk = bad.input("CLK", 1);
+        let a = bad.input("A", 2);
+        let q = bad.output("Q", 12);
+        bad.memory(
+            Memory::new("lut", MemKind::Rom, 12, 4)
+                .byte_enable(5)
+                .port(MemPort::new(&clk, &a).read(&q)),
+        );
+        bad.memory(Memory::new("buf", MemKind::SinglePort, 8, 4));
+        let errs = bad
+            .try_gen()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errs,
+            vec![
+                "BAD: memory lut: width must be a constant multiple of byte width",
+                "BAD: memory lut: ROM needs initial data",
+                "BAD: memory buf: SinglePort needs 1 port(s)",
+            ]
+        );
+        assert_eq!(bad.gen_checked().unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn fsm_outputs() {
+        let mut m = VModule::new("OUTS");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let busy = m.reg_out("BUSY", 1);
+        let ack = m.reg_out("ACK", 1);
+        let code = m.reg_out("CODE", 4);
+        let fsm = FsmModule::new(&clk, &rst, "state")
+            .output_default(&code, 0xF)
+            .AddState("IDLE")
+            .goto("RUN", &go)
+            .mealy(&ack, 1)
+            .AddState("RUN")
+            .output(&busy, 1)
+            .output(&code, 3)
+            .goto("IDLE", Box::new(E::Null));
+        m.fsm(fsm);
+        let st = m.gen();
+        assert!(st.contains(
+            "    always @(*) begin\n        CODE = 15;\n        ACK = 0;\n        BUSY = 0;\n"
+        ));
+        assert!(st.contains("            IDLE :begin \n                if(GO) begin\n                    ACK = 1;\n"));
+        assert!(m
+            .gen_with(Backend::SystemVerilog)
+            .contains("    always_comb begin\n        CODE = 15;\n"));
+        assert!(m
+            .gen_with(Backend::Vhdl)
+            .contains("    process (all)\n    begin\n"));
+        assert!(m.lint().is_empty());
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&rst, 1);
+        sim.tick(&clk);
+        sim.poke(&rst, 0);
+        sim.eval();
+        assert_eq!(
+            (sim.peek(&busy), sim.peek(&ack), sim.peek(&code)),
+            (0, 0, 0xF)
+        );
+        sim.poke(&go, 1);
+        sim.eval();
+        assert_eq!(sim.peek(&ack), 1);
+        sim.tick(&clk);
+        sim.tick(&clk);
+        assert_eq!((sim.peek(&busy), sim.peek(&code)), (1, 3));
+    }
+
+    #[test]
+    fn fsm_encoding() {
+        let build = |enc: Option<FsmEncoding>| {
+            let mut m = VModule::new("ENC");
+            let clk = m.input("CLK", 1);
+            let rst = m.input("RST", 1);
+            let go = m.input("GO", 1);
+            let mut fsm = FsmModule::new(&clk, &rst, "state");
+            if let Some(e) = enc {
+                fsm.encoding(e);
+            }
+            fsm.AddState("IDLE");
+            fsm.goto("RUN", &go);
+            fsm.AddState("RUN");
+            fsm.goto("DONE", Box::new(E::Null));
+            fsm.AddState("DONE");
+            fsm.goto("IDLE", Box::new(E::Null));
+            let state = m.fsm(fsm);
+            (m, clk, rst, go, state)
+        };
+        let st = build(None).0.gen();
+        assert!(st.contains("    reg [1:0] state;\n"));
+        assert!(!st.contains("fsm_encoding"));
+
+        let (m, clk, rst, go, state) = build(Some(FsmEncoding::OneHot));
+        let st = m.gen();
+        assert!(st.contains("    (* fsm_encoding = \"one_hot\" *) reg [2:0] state;\n"));
+        assert!(st.contains("    localparam DONE = 4;\n"));
+        let sv = m.gen_with(Backend::SystemVerilog);
+        assert!(sv.contains("        RUN = 3'b010,\n"));
+        assert!(sv.contains("    (* fsm_encoding = \"one_hot\" *) state_t state, state_Next;\n"));
+        assert!(m
+            .gen_with(Backend::Vhdl)
+            .contains("    attribute fsm_encoding of state : signal is \"one_hot\";\n"));
+        let mut sim = Simulator::new(&m);
+        sim.poke(&rst, 1);
+        sim.tick(&clk);
+        sim.poke(&rst, 0);
+        sim.poke(&go, 1);
+        sim.tick(&clk);
+        sim.tick(&clk);
+        assert_eq!(sim.peek(&state), 2);
+
+        let st = build(Some(FsmEncoding::Gray)).0.gen();
+        assert!(st.contains("    localparam DONE = 3;\n"));
+        let m = build(Some(FsmEncoding::Custom(vec![5, 9, 12]))).0;
+        assert!(m
+            .gen()
+            .contains("    (* fsm_encoding = \"none\" *) reg [3:0] state;\n"));
+        assert!(m
+            .gen_with(Backend::Vhdl)
+            .contains("    attribute enum_encoding of state_t : type is \"0101 1001 1100\";\n"));
+
+        // 不正な符号は try_gen() のエラーとして報告する
+        let errs = |enc: FsmEncoding| {
+            build(Some(enc))
+                .0
+                .try_gen()
+                .unwrap_err()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errs(FsmEncoding::Custom(vec![1, 2])),
+            vec!["ENC: fsm state: 3 states but 2 codes"]
+        );
+        assert_eq!(
+            errs(FsmEncoding::Custom(vec![1, 2, 1])),
+            vec!["ENC: fsm state: duplicate state code 1"]
+        );
+        let mut m = VModule::new("HOT");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let mut fsm = FsmModule::new(&clk, &rst, "state").encoding(FsmEncoding::OneHot);
+        for i in 0..65 {
+            fsm.AddState(&format!("S{}", i));
+        }
+        m.fsm(fsm);
+        assert_eq!(
+            m.try_gen().unwrap_err()[0].to_string(),
+            "HOT: fsm state: one-hot encoding of 65 states is wider than 64 bits"
+        );
+    }
+
+    #[test]
+    fn fsm_graph() {
+        let mut m = VModule::new("G");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let cnt = m.input("CNT", 4);
+        let busy = m.reg_out("BUSY", 1);
+        let ack = m.reg_out("ACK", 1);
+        let fsm = FsmModule::new(&clk, &rst, "state")
+            .AddState("IDLE")
+            .goto("RUN", &go)
+            .mealy(&ack, 1)
+            .AddState("RUN")
+            .output(&busy, 1)
+            .goto("IDLE", F!(cnt < 3))
+            .goto("IDLE", Box::new(E::Null));
+        let dot = fsm.to_dot();
+        assert!(dot.starts_with("digraph state {\n"));
+        assert!(dot.contains("    \"IDLE\" [label=\"IDLE\", shape=doublecircle"));
+        assert!(dot.contains("    \"RUN\" [label=\"RUN\\nBUSY=1\"];\n"));
+        assert!(dot.contains("    \"__reset\" -> \"IDLE\" [label=\"RST\"];\n"));
+        assert!(dot.contains("    \"IDLE\" -> \"RUN\" [label=\"GO / ACK=1\"];\n"));
+        assert!(dot.contains("    \"RUN\" -> \"IDLE\" [label=\"(unconditional)\", style=bold];\n"));
+        let mmd = fsm.to_mermaid();
+        assert!(mmd.starts_with("stateDiagram-v2\n    [*] --> IDLE : RST\n"));
+        assert!(mmd.contains("    class IDLE reset\n"));
+        assert!(mmd.contains("    RUN : BUSY=1\n"));
+        assert!(mmd.contains("    RUN --> IDLE : CNT#lt;3\n"));
+        m.fsm(fsm);
+
+        // ステート名 reset とバックスラッシュを含む条件
+        let esc = m.input("\\E", 1);
+        let fsm = FsmModule::new(&clk, &rst, "st")
+            .AddState("reset")
+            .goto("RUN", &esc)
+            .AddState("RUN");
+        let dot = fsm.to_dot();
+        assert!(dot.contains("    \"__reset\" -> \"reset\" [label=\"RST\"];\n"));
+        assert!(dot.contains("    \"reset\" -> \"RUN\" [label=\"\\\\E\"];\n"));
+    }
+
+    #[test]
+    fn fsm_lint() {
+        let mut m = VModule::new("FL");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let fsm = FsmModule::new(&clk, &rst, "state")
+            .AddState("IDLE")
+            .goto("RUN", &go)
+            .AddState("RUN")
+            .goto("IDLE", Box::new(E::Null))
+            .goto("END", &go)
+            .AddState("END")
+            .AddState("LOST")
+            .goto("NOWHERE", Box::new(E::Null));
+        m.fsm(fsm);
+        let lints = m
+            .lint()
+            .iter()
+            .map(|l| (l.kind, l.signal.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lints,
+            vec![
+                (LintKind::ShadowedTransition, "RUN".to_string()),
+                (LintKind::DeadEndState, "END".to_string()),
+                (LintKind::UndefinedState, "NOWHERE".to_string()),
+                (LintKind::UnreachableState, "END".to_string()),
+                (LintKind::UnreachableState, "LOST".to_string()),
+            ]
+        );
+        let shadow = m.lint().remove(0);
+        assert_eq!(shadow.severity, Severity::Warning);
+        assert_eq!(
+            shadow.message,
+            "transition to END (GO) is shadowed by unconditional transition to IDLE"
+        );
+        assert_eq!(
+            m.lint()[2].message,
+            "transition from LOST to NOWHERE: state is not added"
+        );
+    }
+
+    #[test]
+    fn sim_widths() {
+        let mut m = VModule::new("WID");
+        let a = m.input("A", 8);
+        let b = m.input("B", 8);
+        let z = m.output("Z", 8);
+        let z9 = m.output("Z9", 9);
+        let lo = m.output("LO", 4);
+        let hi = m.output("HI", 8);
+        m.assign(z._e((&a + &b) >> 1));
+        m.assign(z9._e((&a + &b) >> 1));
+        m.assign(lo._e(a.range(0, 3)));
+        m.assign(hi.range(4, 7)._e(a.range(7, 4)));
+        m.assign(hi.range(3, 0)._e(0));
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&a, 255);
+        sim.poke(&b, 1);
+        sim.eval();
+        assert_eq!(sim.peek(&z), 0x00);
+        assert_eq!(sim.peek(&z9), 0x80);
+        sim.poke(&a, 0xa5);
+        sim.eval();
+        assert_eq!(sim.peek(&lo), 0x5);
+        assert_eq!(sim.peek(&hi), 0xa0);
+    }
+
+    #[test]
+    fn fsm_mealy_else() {
+        let mut m = VModule::new("MEALY");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let x = m.reg_out("X", 1);
+        let y = m.reg_out("Y", 1);
+        let fsm = FsmModule::new(&clk, &rst, "state")
+            .AddState("IDLE")
+            .goto("RUN", &go)
+            .mealy(&y, 1)
+            .goto("IDLE", Box::new(E::Null))
+            .mealy(&x, 1)
+            .AddState("RUN")
+            .goto("IDLE", Box::new(E::Null));
+        m.fsm(fsm);
+        let st = m.gen();
+        assert!(!st.contains("else if()"));
+        assert!(st.contains("                else begin\n                    X = 1;\n"));
+        let sv = m.gen_with(Backend::SystemVerilog);
+        assert!(!sv.contains("else if ()"));
+        assert!(sv.contains("                    else begin\n                        state_Next <= IDLE;\n"));
+
+        let mut sim = Simulator::new(&m);
+        sim.poke(&rst, 1);
+        sim.tick(&clk);
+        sim.poke(&rst, 0);
+        sim.eval();
+        assert_eq!((sim.peek(&x), sim.peek(&y)), (1, 0));
+        sim.poke(&go, 1);
+        sim.eval();
+        assert_eq!((sim.peek(&x), sim.peek(&y)), (0, 1));
+    }
+
+    #[test]
+    fn vhdl_errors() {
+        let mut m = VModule::new("VHE");
+        let a = m.input("A", 4);
+        let s = m.input("S", 1);
+        let y = m.output("Y", 4);
+        let z = m.output("Z", 8);
+        let x = m.output("X", 4);
+        m.assign(y._e(&a + _Branch(&s, &a, 1)));
+        m.assign(z._e(replicate(&s, &a)));
+        let i = m.genvar("i");
+        let mut g = Generate::for_(&i, 0, F!(i < 4), &i + 2, "g_even");
+        g.assign(x.addr(&i)._e(a.addr(&i)));
+        m.generate(g);
+        m.inline("    // hand written\n");
+        assert!(m.try_gen().is_ok());
+        let errs = m
+            .try_gen_with(Backend::Vhdl)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errs,
+            vec![
+                "VHE: inline Verilog code is not supported by the VHDL backend",
+                "VHE: conditional expression (S)? A: 1 outside an assignment value is not supported by the VHDL backend",
+                "VHE: replication with a non-constant count S is not supported by the VHDL backend",
+                "VHE: generate for g_even not counting by one is not supported by the VHDL backend",
+            ]
+        );
+
+        assert!(m.gen_with(Backend::Vhdl).starts_with(
+            "-- error: VHE: inline Verilog code is not supported by the VHDL backend\n"
+        ));
+
+        // クロック同期のプロセスではブロッキング代入を表せない
+        let mut b = VModule::new("VHB");
+        let clk = b.input("CLK", 1);
+        let d = b.input("D", 4);
+        let t = b.reg("T", 4);
+        let q = b.reg_out("Q", 4);
+        b.always(posedge(&clk).block().if_(1, vec![t.sst(&d), q.sst(&t)]));
+        let errs = b
+            .try_gen_with(Backend::Vhdl)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errs,
+            vec![
+                "VHB: blocking assignment to T in a clocked process is not supported by the VHDL backend",
+                "VHB: blocking assignment to Q in a clocked process is not supported by the VHDL backend",
+            ]
+        );
+
+        let mut ok = VModule::new("VHOK");
+        let a = ok.input("A", 4);
+        let y = ok.output("Y", 4);
+        ok.assign(y._e(!a));
+        assert_eq!(
+            ok.try_gen_with(Backend::Vhdl).unwrap(),
+            ok.gen_with(Backend::Vhdl)
+        );
+    }
+
+    #[test]
+    fn fsm_else_order() {
+        let mut m = VModule::new("ORDER");
+        let clk = m.input("CLK", 1);
+        let rst = m.input("RST", 1);
+        let go = m.input("GO", 1);
+        let stop = m.input("STOP", 1);
+        let fsm = FsmModule::new(&clk, &rst, "state")
+            .AddState("IDLE")
+            .goto("RUN", &go)
+            .goto("IDLE", Box::new(E::Null))
+            .goto("WAIT", &stop)
+            .AddState("RUN")
+            .goto("WAIT", Box::new(E::Null))
+            .goto("IDLE", &stop)
+            .AddState("WAIT")
+            .goto("IDLE", Box::new(E::Null));
+        m.fsm(fsm);
+        // 条件無しの遷移より後の分岐は出力しない
+        let st = m.gen();
+        assert!(!st.contains("(STOP)"));
+        assert!(st.contains("                    else begin\n                        state_Next <= IDLE;\n                    end\n                end\n"));
+        assert!(st.contains("                RUN : begin\n                    state_Next <= WAIT;\n                end\n"));
+        let sv = m.gen_with(Backend::SystemVerilog);
+        assert!(!sv.contains("(STOP)"));
+        assert!(sv.contains("                    else begin\n                        state_Next <= IDLE;\n                    end\n                end\n"));
+        assert!(sv.contains("                RUN : begin\n                    state_Next <= WAIT;\n                end\n"));
+        assert!(!m.gen_with(Backend::Vhdl).contains("STOP = '1'"));
+        assert_eq!(
+            m.lint()
+                .iter()
+                .filter(|l| l.kind == LintKind::ShadowedTransition)
+                .count(),
+            2
+        );
+
+        // シミュレータも最初の条件無しの遷移を選ぶ
+        let mut sim = Simulator::new(&m);
+        sim.poke(&rst, 1);
+        sim.tick(&clk);
+        sim.poke(&rst, 0);
+        sim.poke(&stop, 1);
+        sim.tick(&clk);
+        assert_eq!(sim.value("state_Next"), Some(0));
+        sim.poke(&go, 1);
+        sim.tick(&clk);
+        sim.tick(&clk);
+        sim.poke(&go, 0);
+        sim.tick(&clk);
+        assert_eq!(sim.value("state_Next"), Some(2));
+    }
+
+    #[test]
+    fn compare_ops() {
+        // メソッド名と出力される比較演算子の対応
+        let mut m = VModule::new("CMP");
+        let a = m.input("A", 8);
+        let b = m.input("B", 8);
+        assert_eq!(decomp_ast(a.clone().lt(&b), "", 0), "A>B");
+        assert_eq!(decomp_ast(a.clone().le(&b), "", 0), "A>=B");
+        assert_eq!(decomp_ast(a.clone().gt(&b), "", 0), "A<B");
+        assert_eq!(decomp_ast(a.clone().ge(&b), "", 0), "A<=B");
+        assert_eq!(decomp_ast((&a).lt(&b), "", 0), "A>B");
+        assert_eq!(decomp_ast((&a).ge(&b), "", 0), "A<=B");
+        assert_eq!(decomp_ast(F!(a < b), "", 0), "A<B");
+        assert_eq!(decomp_ast(F!(a <= b), "", 0), "A<=B");
+        assert_eq!(decomp_ast(F!(a > b), "", 0), "A>B");
+        assert_eq!(decomp_ast(F!(a >= b), "", 0), "A>=B");
+    }
 }
 
 pub mod bus;
+pub mod error;
+pub mod generate;
+pub mod graph;
+pub mod lint;
+pub mod literal;
+pub mod memory;
+pub mod parser;
+pub mod sim;
+pub mod sv;
+pub mod testbench;
+pub mod vcd;
 pub mod vcore;
+pub mod vhdl;
+pub mod width;
@@ -0,0 +1,658 @@
+//! 多重駆動・未駆動・未使用信号と FSM のステート・遷移の検査
+
+use memory::MemKind;
+use std::fmt;
+use vcore::*;
+
+/// 検査結果の重要度
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// 検査項目
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintKind {
+    /// 複数の assign による駆動
+    MultipleAssign,
+    /// 複数の always による駆動
+    MultipleAlways,
+    /// assign と always の両方による駆動
+    MixedDrivers,
+    /// 駆動されない出力
+    UndrivenOutput,
+    /// 参照されるが代入されない wire/reg
+    UndrivenSignal,
+    /// 使用されない入力
+    UnusedInput,
+    /// 組み合わせ回路の always で全経路では代入されない信号(ラッチ推論)
+    Latch,
+    /// エッジで起動する always 中のブロッキング代入
+    BlockingInSequential,
+    /// 組み合わせ回路の always 中のノンブロッキング代入
+    NonBlockingInCombinational,
+    /// ブロッキング代入とノンブロッキング代入の両方で代入される信号
+    MixedSubst,
+    /// リセットステートから到達できない FSM のステート
+    UnreachableState,
+    /// 遷移の無い FSM のステート
+    DeadEndState,
+    /// 追加されていないステートへの遷移
+    UndefinedState,
+    /// 先行する条件無しの遷移により起こらない遷移
+    ShadowedTransition,
+}
+
+/// 検査結果
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub severity: Severity,
+    pub kind: LintKind,
+    pub module: String,
+    pub signal: String,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{}: {}: {}: {}",
+            level, self.module, self.signal, self.message
+        )
+    }
+}
+
+// 信号の駆動元(assign とインスタンス出力は継続的な駆動)
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Driver {
+    Assign(usize),
+    Instance(usize),
+    Always(usize),
+    Fsm(usize),
+    // メモリの初期値
+    Init,
+}
+
+impl Driver {
+    fn is_continuous(&self) -> bool {
+        matches!(*self, Driver::Assign(_) | Driver::Instance(_))
+    }
+}
+
+// 駆動されるビット範囲(None は全体)
+type Range = Option<(i64, i64)>;
+
+// 信号の使用状況
+struct Usage {
+    // (信号名, 駆動元, ビット範囲)
+    drivers: Vec<(String, Driver, Range)>,
+    reads: Vec<String>,
+}
+
+impl Usage {
+    // 式中で参照される信号
+    fn read(&mut self, e: &E) {
+        match *e {
+            E::Ldc(ref wr) => self.reads.push(wr.name.clone()),
+            E::Bin(_, ref l, ref r) | E::MEM(ref l, ref r) | E::Rep(ref l, ref r) => {
+                self.read(l);
+                self.read(r);
+            }
+            E::Red(_, ref a)
+            | E::Un(_, ref a)
+            | E::Signed(ref a)
+            | E::Unsigned(ref a)
+            | E::Clog2(ref a) => self.read(a),
+            E::PL(ref c, ref t, ref f) | E::MBT(ref c, ref t, ref f) => {
+                self.read(c);
+                self.read(t);
+                self.read(f);
+            }
+            E::Func(_, ref args) | E::Cat(ref args) => {
+                for a in args {
+                    self.read(a);
+                }
+            }
+            E::SB(ref l, ref r) | E::SBX(_, ref l, ref r) => {
+                self.read_index(l);
+                self.read(r);
+            }
+            E::BL(ref list) => {
+                for x in list {
+                    self.read(&x.cond);
+                    for s in &x.stmt {
+                        self.read(s);
+                    }
+                }
+            }
+            E::CS(ref c) => {
+                self.reads.push(c.CaseVar.name.clone());
+                for x in &c.Select {
+                    self.read(&x.CaseT);
+                    for s in &x.CaseS {
+                        self.read(s);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 代入先の添字で参照される信号
+    fn read_index(&mut self, e: &E) {
+        match *e {
+            E::MEM(_, ref a) => self.read(a),
+            E::MBT(_, ref h, ref l) => {
+                self.read(h);
+                self.read(l);
+            }
+            E::Cat(ref items) => {
+                for x in items {
+                    self.read_index(x);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 文中の代入先
+    fn drive(&mut self, m: &VModule, e: &E, by: Driver) {
+        match *e {
+            E::SB(ref l, _) | E::SBX(_, ref l, _) => self.drive_target(m, l, by),
+            E::BL(ref list) => {
+                for x in list {
+                    for s in &x.stmt {
+                        self.drive(m, s, by);
+                    }
+                }
+            }
+            E::CS(ref c) => {
+                for x in &c.Select {
+                    for s in &x.CaseS {
+                        self.drive(m, s, by);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn drive_target(&mut self, m: &VModule, e: &E, by: Driver) {
+        match *e {
+            E::Ldc(ref wr) => self.drivers.push((wr.name.clone(), by, None)),
+            E::MEM(ref v, ref i) => match **v {
+                // 定数の添字は 1 ビット(メモリなら 1 要素)の範囲
+                E::Ldc(ref wr) => {
+                    let range = m.const_eval(i).map(|i| (i, i));
+                    self.drivers.push((wr.name.clone(), by, range));
+                }
+                _ => self.drive_target(m, v, by),
+            },
+            E::Cat(ref items) => {
+                for x in items {
+                    self.drive_target(m, x, by);
+                }
+            }
+            E::MBT(ref v, ref h, ref l) => {
+                if let E::Ldc(ref wr) = **v {
+                    let range = match (m.const_eval(h), m.const_eval(l)) {
+                        (Some(h), Some(l)) => Some((l.min(h), l.max(h))),
+                        _ => None,
+                    };
+                    self.drivers.push((wr.name.clone(), by, range));
+                } else {
+                    self.drive_target(m, v, by);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_read(&self, name: &str) -> bool {
+        self.reads.iter().any(|r| r == name)
+    }
+
+    fn is_driven(&self, name: &str) -> bool {
+        self.drivers.iter().any(|d| d.0 == name)
+    }
+}
+
+// ビット範囲の重なり
+fn overlap(a: Range, b: Range) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.0 <= b.1 && b.0 <= a.1,
+        _ => true,
+    }
+}
+
+// 代入先の信号
+fn targets(e: &E) -> Vec<&WireVar> {
+    match *e {
+        E::Ldc(ref wr) => vec![wr],
+        E::MEM(ref v, _) | E::MBT(ref v, _, _) => targets(v),
+        E::Cat(ref items) => items.iter().flat_map(|x| targets(x)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// 信号全体への代入となる代入先
+fn whole_targets(e: &E) -> Vec<String> {
+    match *e {
+        E::Ldc(ref wr) => vec![wr.name.clone()],
+        E::Cat(ref items) => items.iter().flat_map(|x| whole_targets(x)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// 文の並びで代入され得る信号
+fn assigned_any(stmts: &[Box<E>], out: &mut Vec<WireVar>) {
+    for s in stmts {
+        match **s {
+            E::SB(ref l, _) | E::SBX(_, ref l, _) => {
+                for wr in targets(l) {
+                    if !out.iter().any(|x| x.name == wr.name) {
+                        out.push(wr.clone());
+                    }
+                }
+            }
+            E::BL(ref list) => {
+                for x in list {
+                    assigned_any(&x.stmt, out);
+                }
+            }
+            E::CS(ref c) => {
+                for x in &c.Select {
+                    assigned_any(&x.CaseS, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// 文の並びの全経路で信号全体に代入される信号
+fn assigned_all(stmts: &[Box<E>]) -> Vec<String> {
+    let mut out = Vec::new();
+    for s in stmts {
+        let names = match **s {
+            E::SB(ref l, _) | E::SBX(_, ref l, _) => whole_targets(l),
+            E::BL(ref list) => {
+                let list = IfElseAST::reachable(list);
+                match list.first() {
+                    // 条件無しの分岐は文のみが有効
+                    Some(x) if matches!(*x.cond, E::Null) => assigned_all(&x.stmt),
+                    Some(_) if list.last().is_some_and(|x| !x.if_) => {
+                        intersect(list.iter().map(|x| assigned_all(&x.stmt)))
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            E::CS(ref c) if c.Select.iter().any(|x| matches!(*x.CaseT, E::Null)) => {
+                intersect(c.Select.iter().map(|x| assigned_all(&x.CaseS)))
+            }
+            _ => Vec::new(),
+        };
+        for n in names {
+            if !out.contains(&n) {
+                out.push(n);
+            }
+        }
+    }
+    out
+}
+
+// 文の並びの代入先と代入方法
+pub(crate) fn substs(stmts: &[Box<E>], default: Subst, out: &mut Vec<(String, Subst)>) {
+    for s in stmts {
+        let (l, sub) = match **s {
+            E::SB(ref l, _) => (l, default),
+            E::SBX(sub, ref l, _) => (l, sub),
+            E::BL(ref list) => {
+                for x in list {
+                    substs(&x.stmt, default, out);
+                }
+                continue;
+            }
+            E::CS(ref c) => {
+                for x in &c.Select {
+                    substs(&x.CaseS, default, out);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        for wr in targets(l) {
+            if !out.contains(&(wr.name.clone(), sub)) {
+                out.push((wr.name.clone(), sub));
+            }
+        }
+    }
+}
+
+// 全分岐で代入される信号
+fn intersect<I: Iterator<Item = Vec<String>>>(mut branches: I) -> Vec<String> {
+    let first = branches.next().unwrap_or_default();
+    branches.fold(first, |acc, b| {
+        acc.into_iter().filter(|n| b.contains(n)).collect()
+    })
+}
+
+impl Always {
+    // 組み合わせ回路の always でラッチが推論される信号
+    pub(crate) fn latches(&self) -> Vec<WireVar> {
+        if !self.posedges.is_empty() || !self.negedges.is_empty() {
+            return Vec::new();
+        }
+        let mut any = Vec::new();
+        assigned_any(&self.stmt, &mut any);
+        let all = assigned_all(&self.stmt);
+        any.into_iter()
+            .filter(|wr| !all.contains(&wr.name))
+            .collect()
+    }
+
+    /// ラッチが推論される信号への 0 の代入をブロックの先頭に追加
+    pub fn no_latch(&mut self) -> Always {
+        let defaults = self
+            .latches()
+            .into_iter()
+            .filter(|wr| wr.length == 0 && wr.length_p.is_none())
+            .map(|wr| Box::new(E::SB(Box::new(E::Ldc(wr)), Box::new(E::Num(0)))))
+            .collect::<Vec<_>>();
+        self.stmt.splice(0..0, defaults);
+        self.clone()
+    }
+}
+
+impl VModule {
+    // assign, always, function, FSM, インスタンスにおける信号の使用状況
+    fn usage(&self) -> Usage {
+        let mut u = Usage {
+            drivers: Vec::new(),
+            reads: Vec::new(),
+        };
+        for (i, a) in self.assign.iter().enumerate() {
+            u.drive_target(self, &a.lhs, Driver::Assign(i));
+            u.read_index(&a.lhs);
+            u.read(&a.rhs);
+        }
+        for (i, al) in self.always.iter().enumerate() {
+            for wr in al.posedges.iter().chain(al.negedges.iter()) {
+                u.reads.push(wr.name.clone());
+            }
+            for s in &al.stmt {
+                u.drive(self, s, Driver::Always(i));
+                u.read(s);
+            }
+        }
+        let mut n = 0;
+        for fsm in &self.fsm {
+            for al in fsm.to_always() {
+                for wr in al.posedges.iter().chain(al.negedges.iter()) {
+                    u.reads.push(wr.name.clone());
+                }
+                for s in &al.stmt {
+                    u.drive(self, s, Driver::Fsm(n));
+                    u.read(s);
+                }
+                n += 1;
+            }
+        }
+        for mem in self.memory.iter().filter(|x| !x.init.is_empty()) {
+            u.drivers.push((mem.name.clone(), Driver::Init, None));
+        }
+        for f in &self.func {
+            // 関数の入力と戻り値は関数内の変数
+            let mut local = vec![_StrOut(f.top.clone())];
+            local.extend(f.input.iter().map(|x| _StrOut(x.clone())));
+            let mut inner = Usage {
+                drivers: Vec::new(),
+                reads: Vec::new(),
+            };
+            for s in &f.stmt {
+                inner.read(s);
+            }
+            u.reads
+                .extend(inner.reads.into_iter().filter(|r| !local.contains(r)));
+        }
+        for (i, inst) in self.instance.iter().enumerate() {
+            for (pname, sig) in &inst.port_map {
+                match inst.ports.iter().find(|p| &p.name == pname) {
+                    Some(p) if matches!(p.io_param, IOType::Output) => {
+                        u.drive_target(self, sig, Driver::Instance(i));
+                        u.read_index(sig);
+                    }
+                    _ => u.read(sig),
+                }
+            }
+        }
+        u
+    }
+
+    /// 多重駆動・未駆動・未使用信号の検査
+    pub fn lint(&self) -> Vec<Lint> {
+        if !self.generate.is_empty() {
+            return self.unroll_generate().lint();
+        }
+        let u = self.usage();
+        let mut lints = Vec::new();
+        let mut push = |severity, kind, signal: &str, message: String| {
+            lints.push(Lint {
+                severity,
+                kind,
+                module: self.name.clone(),
+                signal: signal.to_string(),
+                message,
+            })
+        };
+
+        let signals = self
+            .io_port
+            .iter()
+            .chain(self.local_param.iter())
+            .filter(|wr| !matches!(wr.io_param, IOType::Param | IOType::Genvar))
+            .collect::<Vec<_>>();
+        // トゥルーデュアルポート RAM は両ポートの always から書き込む
+        let dual = self
+            .memory
+            .iter()
+            .filter(|x| x.kind == MemKind::TrueDualPort)
+            .map(|x| x.name.clone())
+            .collect::<Vec<_>>();
+        for wr in signals.iter().filter(|wr| !dual.contains(&wr.name)) {
+            let drivers = u
+                .drivers
+                .iter()
+                .filter(|d| d.0 == wr.name)
+                .collect::<Vec<_>>();
+            let mut kinds = Vec::new();
+            for (i, a) in drivers.iter().enumerate() {
+                for b in &drivers[..i] {
+                    let init = a.1 == Driver::Init || b.1 == Driver::Init;
+                    if a.1 == b.1 || init || !overlap(a.2, b.2) {
+                        continue;
+                    }
+                    let kind = match (a.1.is_continuous(), b.1.is_continuous()) {
+                        (true, true) => LintKind::MultipleAssign,
+                        (false, false) => LintKind::MultipleAlways,
+                        _ => LintKind::MixedDrivers,
+                    };
+                    if !kinds.contains(&kind) {
+                        kinds.push(kind);
+                    }
+                }
+            }
+            for kind in kinds {
+                let message = match kind {
+                    LintKind::MultipleAssign => "driven by more than one assign",
+                    LintKind::MultipleAlways => "driven by more than one always block",
+                    _ => "driven by both assign and always block",
+                };
+                push(Severity::Error, kind, &wr.name, message.to_string());
+            }
+        }
+        let mut assigned: Vec<(String, Subst)> = Vec::new();
+        for al in &self.always {
+            let mut list = Vec::new();
+            substs(&al.stmt, al.subst(), &mut list);
+            let edge = !al.posedges.is_empty() || !al.negedges.is_empty();
+            for (name, sub) in &list {
+                match (edge, *sub) {
+                    (true, Subst::Blocking) => push(
+                        Severity::Warning,
+                        LintKind::BlockingInSequential,
+                        name,
+                        "blocking assignment (=) in edge-triggered always block".to_string(),
+                    ),
+                    (false, Subst::NonBlocking) => push(
+                        Severity::Warning,
+                        LintKind::NonBlockingInCombinational,
+                        name,
+                        "non-blocking assignment (<=) in always @(*)".to_string(),
+                    ),
+                    _ => {}
+                }
+            }
+            for x in list {
+                if !assigned.contains(&x) {
+                    assigned.push(x);
+                }
+            }
+            for wr in al.latches() {
+                push(
+                    Severity::Warning,
+                    LintKind::Latch,
+                    &wr.name,
+                    "not assigned on all paths of always @(*), latch inferred".to_string(),
+                );
+            }
+        }
+
+        for wr in &signals {
+            let both = [Subst::Blocking, Subst::NonBlocking]
+                .iter()
+                .all(|sub| assigned.contains(&(wr.name.clone(), *sub)));
+            if both {
+                push(
+                    Severity::Error,
+                    LintKind::MixedSubst,
+                    &wr.name,
+                    "assigned with both blocking and non-blocking assignments".to_string(),
+                );
+            }
+        }
+
+        for fsm in &self.fsm {
+            for (severity, kind, state, message) in fsm.lint_states() {
+                push(severity, kind, &state, message);
+            }
+        }
+
+        // 埋め込みコードとバスインタフェースの中の駆動・参照は解析できない
+        if !self.inline.is_empty() || !self.axi.is_empty() {
+            return lints;
+        }
+        for wr in &signals {
+            match wr.io_param {
+                IOType::Output if !u.is_driven(&wr.name) => push(
+                    Severity::Error,
+                    LintKind::UndrivenOutput,
+                    &wr.name,
+                    "output is never driven".to_string(),
+                ),
+                IOType::Input if !u.is_read(&wr.name) => push(
+                    Severity::Warning,
+                    LintKind::UnusedInput,
+                    &wr.name,
+                    "input is never used".to_string(),
+                ),
+                IOType::None if u.is_read(&wr.name) && !u.is_driven(&wr.name) => push(
+                    Severity::Warning,
+                    LintKind::UndrivenSignal,
+                    &wr.name,
+                    format!(
+                        "{} is read but never assigned",
+                        if wr.reg_set { "reg" } else { "wire" }
+                    ),
+                ),
+                _ => {}
+            }
+        }
+        lints
+    }
+}
+
+impl FsmModule {
+    // ステートと遷移の検査(重要度, 検査項目, ステート名, 内容)
+    fn lint_states(&self) -> Vec<(Severity, LintKind, String, String)> {
+        let mut out = Vec::new();
+        let names = self.state_names();
+        let edges = self.edges();
+        let mut live = Vec::new();
+        for s in &self.states {
+            let name = _StrOut(s.state.clone());
+            let from = edges.iter().filter(|e| e.from == name).collect::<Vec<_>>();
+            if from.is_empty() {
+                out.push((
+                    Severity::Warning,
+                    LintKind::DeadEndState,
+                    name.clone(),
+                    "state has no outgoing transition".to_string(),
+                ));
+            }
+            // 最初の条件無しの遷移より後の遷移は起こらない
+            let first = from.iter().find(|e| e.cond.is_none()).cloned();
+            for e in from {
+                if !names.contains(&e.to) {
+                    out.push((
+                        Severity::Error,
+                        LintKind::UndefinedState,
+                        e.to.clone(),
+                        format!("transition from {} to {}: state is not added", name, e.to),
+                    ));
+                }
+                match first {
+                    Some(u) if e.index > u.index => out.push((
+                        Severity::Warning,
+                        LintKind::ShadowedTransition,
+                        name.clone(),
+                        format!(
+                            "transition to {} ({}) is shadowed by unconditional transition to {}",
+                            e.to,
+                            e.cond.clone().unwrap_or_default(),
+                            u.to
+                        ),
+                    )),
+                    _ => live.push((e.from.clone(), e.to.clone())),
+                }
+            }
+        }
+        // リセットステートからの到達可能性
+        let mut reached = names.iter().take(1).cloned().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < reached.len() {
+            let cur = reached[i].clone();
+            for e in live.iter().filter(|e| e.0 == cur) {
+                if !reached.contains(&e.1) {
+                    reached.push(e.1.clone());
+                }
+            }
+            i += 1;
+        }
+        for n in names.iter().filter(|n| !reached.contains(n)) {
+            out.push((
+                Severity::Warning,
+                LintKind::UnreachableState,
+                n.clone(),
+                "state is not reachable from the reset state".to_string(),
+            ));
+        }
+        out
+    }
+}
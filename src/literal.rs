@@ -0,0 +1,402 @@
+//! 幅、基数、符号と x/z を含むビット列を持つ数値リテラル
+
+use std::fmt;
+
+/// リテラルの1ビット
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bit {
+    Zero,
+    One,
+    X,
+    Z,
+}
+
+impl Bit {
+    fn from_bool(b: bool) -> Bit {
+        if b {
+            Bit::One
+        } else {
+            Bit::Zero
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Bit::Zero => '0',
+            Bit::One => '1',
+            Bit::X => 'x',
+            Bit::Z => 'z',
+        }
+    }
+}
+
+/// 出力時の基数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Radix {
+    Bin,
+    Oct,
+    Dec,
+    Hex,
+}
+
+impl Radix {
+    fn letter(self) -> char {
+        match self {
+            Radix::Bin => 'b',
+            Radix::Oct => 'o',
+            Radix::Dec => 'd',
+            Radix::Hex => 'h',
+        }
+    }
+
+    // 1桁あたりのビット数(10進は対象外)
+    fn digit_bits(self) -> usize {
+        match self {
+            Radix::Bin => 1,
+            Radix::Oct => 3,
+            Radix::Hex => 4,
+            Radix::Dec => 0,
+        }
+    }
+}
+
+/// 数値リテラル
+#[derive(Clone, Debug, PartialEq)]
+pub struct Literal {
+    /// ビット幅(None は幅指定なし)
+    pub width: Option<usize>,
+    pub signed: bool,
+    pub radix: Radix,
+    // LSB から順のビット列
+    bits: Vec<Bit>,
+}
+
+impl Literal {
+    /// 幅と値を指定したリテラル(16進で出力)
+    pub fn new(width: usize, value: u128) -> Literal {
+        Literal {
+            width: Some(width),
+            signed: false,
+            radix: Radix::Hex,
+            bits: (0..width)
+                .map(|i| Bit::from_bool(i < 128 && (value >> i) & 1 == 1))
+                .collect(),
+        }
+    }
+
+    /// 全ビットが x のリテラル
+    pub fn x(width: usize) -> Literal {
+        Literal::fill(width, Bit::X)
+    }
+
+    /// 全ビットが z のリテラル
+    pub fn z(width: usize) -> Literal {
+        Literal::fill(width, Bit::Z)
+    }
+
+    fn fill(width: usize, b: Bit) -> Literal {
+        Literal {
+            width: Some(width),
+            signed: false,
+            radix: Radix::Bin,
+            bits: vec![b; width],
+        }
+    }
+
+    /// LSB から順のビット列によるリテラル
+    pub fn from_bits(bits: Vec<Bit>) -> Literal {
+        Literal {
+            width: Some(bits.len()),
+            signed: false,
+            radix: Radix::Bin,
+            bits,
+        }
+    }
+
+    /// Verilog の数値表記(8'hFF, 'bz, 4'sb10x1, 123 など)の読み込み
+    pub fn parse(raw: &str) -> Result<Literal, String> {
+        let s = raw.replace('_', "");
+        let err = || format!("invalid number {}", raw);
+        let (size, rest) = match s.find('\'') {
+            Some(p) => (&s[..p], &s[p + 1..]),
+            None => {
+                if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(err());
+                }
+                let mut l = Literal::unsized_bits(dec_bits(&s), Radix::Dec);
+                l.signed = true;
+                return Ok(l);
+            }
+        };
+        let width = if size.is_empty() {
+            None
+        } else {
+            match size.parse::<usize>() {
+                Ok(0) | Err(_) => return Err(err()),
+                Ok(w) => Some(w),
+            }
+        };
+        let signed = rest.starts_with(['s', 'S']);
+        let rest = rest.trim_start_matches(['s', 'S']);
+        let radix = match rest.chars().next() {
+            Some('b') | Some('B') => Radix::Bin,
+            Some('o') | Some('O') => Radix::Oct,
+            Some('d') | Some('D') => Radix::Dec,
+            Some('h') | Some('H') => Radix::Hex,
+            _ => return Err(err()),
+        };
+        let digits = rest[1..].to_lowercase();
+        if digits.is_empty() {
+            return Err(err());
+        }
+        let bits = if radix == Radix::Dec {
+            match digits.as_str() {
+                "x" => vec![Bit::X],
+                "z" | "?" => vec![Bit::Z],
+                d if d.chars().all(|c| c.is_ascii_digit()) => dec_bits(d),
+                _ => return Err(err()),
+            }
+        } else {
+            let n = radix.digit_bits();
+            let mut bits = Vec::new();
+            for c in digits.chars().rev() {
+                let d = match c {
+                    'x' => vec![Bit::X; n],
+                    'z' | '?' => vec![Bit::Z; n],
+                    _ => match c.to_digit(1 << n) {
+                        Some(v) => (0..n).map(|i| Bit::from_bool((v >> i) & 1 == 1)).collect(),
+                        None => return Err(err()),
+                    },
+                };
+                bits.extend(d);
+            }
+            bits
+        };
+        let mut l = Literal::unsized_bits(bits, radix);
+        l.signed = signed;
+        if let Some(w) = width {
+            // 上位桁が x/z なら x/z で、それ以外は 0 で拡張する
+            let top = match l.bits.last() {
+                Some(&Bit::X) => Bit::X,
+                Some(&Bit::Z) => Bit::Z,
+                _ => Bit::Zero,
+            };
+            l.bits.resize(w, top);
+            l.width = Some(w);
+        }
+        Ok(l)
+    }
+
+    fn unsized_bits(bits: Vec<Bit>, radix: Radix) -> Literal {
+        Literal {
+            width: None,
+            signed: false,
+            radix,
+            bits,
+        }
+    }
+
+    /// 出力基数の設定
+    pub fn radix(mut self, radix: Radix) -> Literal {
+        self.radix = radix;
+        self
+    }
+
+    /// 符号付きに設定
+    pub fn signed(mut self) -> Literal {
+        self.signed = true;
+        self
+    }
+
+    /// 幅の変更(符号付きなら符号拡張、それ以外は 0 拡張)
+    pub fn resize(mut self, width: usize) -> Literal {
+        let top = match self.bits.last() {
+            Some(&b) if self.signed => b,
+            _ => Bit::Zero,
+        };
+        self.bits.resize(width, top);
+        self.width = Some(width);
+        self
+    }
+
+    /// LSB から順のビット列
+    pub fn bits(&self) -> &[Bit] {
+        &self.bits
+    }
+
+    /// ビット幅(幅指定なしなら表記に必要なビット数)
+    pub fn bit_width(&self) -> usize {
+        self.width.unwrap_or(self.bits.len())
+    }
+
+    /// x か z のビットを含むか
+    pub fn is_4state(&self) -> bool {
+        self.bits.iter().any(|&b| b == Bit::X || b == Bit::Z)
+    }
+
+    /// 下位 64 ビットの値(x/z は 0 とする)
+    pub fn to_u64(&self) -> u64 {
+        self.bits
+            .iter()
+            .take(64)
+            .enumerate()
+            .fold(0, |v, (i, &b)| v | ((b == Bit::One) as u64) << i)
+    }
+
+    /// 整数値(x/z を含むか 64 ビットに収まらない場合は None)
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.is_4state() {
+            return None;
+        }
+        let neg = self.signed && self.bits.last() == Some(&Bit::One);
+        let fill = if neg { Bit::One } else { Bit::Zero };
+        if self.bits.iter().skip(63).any(|&b| b != fill) {
+            return None;
+        }
+        let v = self.to_u64();
+        Some(if neg && self.bits.len() < 64 {
+            (v | !0 << self.bits.len()) as i64
+        } else {
+            v as i64
+        })
+    }
+
+    /// 指定した基数での Verilog 表記
+    pub fn to_radix(&self, radix: Radix) -> String {
+        let size = self.width.map_or(String::new(), |w| w.to_string());
+        let s = if self.signed { "s" } else { "" };
+        if radix == Radix::Dec {
+            if self.width.is_none() && self.signed && !self.is_4state() {
+                return dec_string(&self.bits);
+            }
+            if self.bits.iter().all(|&b| b == Bit::X) {
+                return format!("{}'{}dx", size, s);
+            }
+            if self.bits.iter().all(|&b| b == Bit::Z) {
+                return format!("{}'{}dz", size, s);
+            }
+            if !self.is_4state() {
+                if self.signed && self.bits.last() == Some(&Bit::One) {
+                    return format!("-{}'sd{}", size, dec_string(&negate(&self.bits)));
+                }
+                return format!("{}'{}d{}", size, s, dec_string(&self.bits));
+            }
+            // x/z の混在は 10 進で表せないため 2 進で出力する
+            return self.to_radix(Radix::Bin);
+        }
+        let n = radix.digit_bits();
+        let mut digits = String::new();
+        for chunk in self.bits.chunks(n) {
+            let c = if chunk.len() == 1 {
+                chunk[0].to_char()
+            } else if chunk.iter().all(|&b| b == Bit::X) {
+                'x'
+            } else if chunk.iter().all(|&b| b == Bit::Z) {
+                'z'
+            } else if chunk.iter().any(|&b| b == Bit::X || b == Bit::Z) {
+                return self.to_radix(Radix::Bin);
+            } else {
+                let v = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |v, (i, &b)| v | ((b == Bit::One) as u32) << i);
+                std::char::from_digit(v, 1 << n)
+                    .unwrap()
+                    .to_ascii_uppercase()
+            };
+            digits.insert(0, c);
+        }
+        if digits.is_empty() {
+            digits.push('0');
+        }
+        format!("{}'{}{}{}", size, s, radix.letter(), digits)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_radix(self.radix))
+    }
+}
+
+impl From<u64> for Literal {
+    fn from(v: u64) -> Self {
+        Literal::new(64, u128::from(v)).radix(Radix::Dec)
+    }
+}
+
+impl From<u128> for Literal {
+    fn from(v: u128) -> Self {
+        Literal::new(128, v).radix(Radix::Dec)
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(v: i64) -> Self {
+        Literal::new(64, u128::from(v as u64))
+            .radix(Radix::Dec)
+            .signed()
+    }
+}
+
+// 2 の補数による符号反転
+fn negate(bits: &[Bit]) -> Vec<Bit> {
+    let mut carry = true;
+    bits.iter()
+        .map(|&b| {
+            let v = (b != Bit::One) as u8 + carry as u8;
+            carry = v > 1;
+            Bit::from_bool(v & 1 == 1)
+        })
+        .collect()
+}
+
+// 10 進数字列のビット列(最小桁数)
+fn dec_bits(digits: &str) -> Vec<Bit> {
+    // 32 ビット単位で下位から格納した多倍長整数
+    let mut limbs: Vec<u32> = vec![0];
+    for c in digits.chars() {
+        let mut carry = u64::from(c.to_digit(10).unwrap());
+        for l in limbs.iter_mut() {
+            let v = u64::from(*l) * 10 + carry;
+            *l = v as u32;
+            carry = v >> 32;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+    }
+    let mut bits = limbs
+        .iter()
+        .flat_map(|&l| (0..32).map(move |i| Bit::from_bool((l >> i) & 1 == 1)))
+        .collect::<Vec<_>>();
+    while bits.len() > 1 && bits.last() == Some(&Bit::Zero) {
+        bits.pop();
+    }
+    bits
+}
+
+// ビット列の 10 進表記
+fn dec_string(bits: &[Bit]) -> String {
+    let mut limbs = bits
+        .chunks(32)
+        .map(|c| {
+            c.iter()
+                .enumerate()
+                .fold(0u32, |v, (i, &b)| v | ((b == Bit::One) as u32) << i)
+        })
+        .collect::<Vec<_>>();
+    let mut digits = String::new();
+    loop {
+        let mut rem = 0u64;
+        for l in limbs.iter_mut().rev() {
+            let v = rem << 32 | u64::from(*l);
+            *l = (v / 10) as u32;
+            rem = v % 10;
+        }
+        digits.insert(0, std::char::from_digit(rem as u32, 10).unwrap());
+        if limbs.iter().all(|&l| l == 0) {
+            return digits;
+        }
+    }
+}
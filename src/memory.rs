@@ -0,0 +1,396 @@
+//! RAM/ROM のメモリプリミティブと $readmemh/$readmemb 用の初期値ファイル
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use vcore::*;
+
+/// メモリの構成
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemKind {
+    /// 読み書き共用の 1 ポート
+    SinglePort,
+    /// 書き込み専用ポート A と読み出し専用ポート B
+    SimpleDualPort,
+    /// 読み書き共用の 2 ポート
+    TrueDualPort,
+    /// 読み出し専用(初期値必須)
+    Rom,
+}
+
+/// 書き込みと同じサイクルの読み出し値
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteMode {
+    /// 書き込み前の値
+    ReadFirst,
+    /// 書き込んだ値
+    WriteFirst,
+    /// 読み出しレジスタを保持
+    NoChange,
+}
+
+/// メモリのポート
+#[derive(Clone, Debug)]
+pub struct MemPort {
+    pub(crate) clk: Box<E>,
+    pub(crate) addr: Box<E>,
+    pub(crate) en: Option<Box<E>>,
+    pub(crate) we: Option<Box<E>>,
+    pub(crate) din: Option<Box<E>>,
+    pub(crate) be: Option<Box<E>>,
+    pub(crate) dout: Option<Box<E>>,
+}
+
+impl MemPort {
+    /// クロックとアドレスを指定したポート
+    pub fn new<T: Into<Box<E>>, U: Into<Box<E>>>(clk: T, addr: U) -> MemPort {
+        MemPort {
+            clk: clk.into(),
+            addr: addr.into(),
+            en: None,
+            we: None,
+            din: None,
+            be: None,
+            dout: None,
+        }
+    }
+
+    /// ポートイネーブル(読み出しレジスタのクロックイネーブルを兼ねる)
+    pub fn enable<T: Into<Box<E>>>(&mut self, en: T) -> MemPort {
+        self.en = Some(en.into());
+        self.clone()
+    }
+
+    /// 書き込みイネーブルと書き込みデータ
+    pub fn write<T: Into<Box<E>>, U: Into<Box<E>>>(&mut self, we: T, din: U) -> MemPort {
+        self.we = Some(we.into());
+        self.din = Some(din.into());
+        self.clone()
+    }
+
+    /// バイトイネーブル(1 ビットが 1 レーン)
+    pub fn byte_enable<T: Into<Box<E>>>(&mut self, be: T) -> MemPort {
+        self.be = Some(be.into());
+        self.clone()
+    }
+
+    /// 読み出しデータの出力先
+    pub fn read<T: Into<Box<E>>>(&mut self, dout: T) -> MemPort {
+        self.dout = Some(dout.into());
+        self.clone()
+    }
+}
+
+// 書き込みレーン(バイトイネーブルのビット、ビット範囲)
+type Lane = (Option<Box<E>>, Option<(i32, i32)>);
+
+/// RAM/ROM の定義
+#[derive(Clone, Debug)]
+pub struct Memory {
+    pub(crate) name: String,
+    pub(crate) kind: MemKind,
+    pub(crate) width: Box<E>,
+    pub(crate) depth: Box<E>,
+    pub(crate) latency: i32,
+    pub(crate) byte_width: Option<i32>,
+    pub(crate) mode: WriteMode,
+    pub(crate) init: Vec<u64>,
+    // 初期値ファイルを 2 進で出力する
+    pub(crate) bin: bool,
+    pub(crate) ports: Vec<MemPort>,
+}
+
+impl Memory {
+    /// 構成、データ幅、ワード数を指定したメモリ(読み出しレイテンシ 1、ReadFirst)
+    pub fn new<T: Into<Box<E>>, U: Into<Box<E>>>(
+        name: &str,
+        kind: MemKind,
+        width: T,
+        depth: U,
+    ) -> Memory {
+        Memory {
+            name: name.to_string(),
+            kind,
+            width: width.into(),
+            depth: depth.into(),
+            latency: 1,
+            byte_width: None,
+            mode: WriteMode::ReadFirst,
+            init: Vec::new(),
+            bin: false,
+            ports: Vec::new(),
+        }
+    }
+
+    /// 読み出しレイテンシ(0 は非同期読み出し)
+    pub fn latency(&mut self, cycles: i32) -> Memory {
+        self.latency = cycles;
+        self.clone()
+    }
+
+    /// バイトイネーブル付き書き込み(byte_width はレーンのビット幅)
+    pub fn byte_enable(&mut self, byte_width: i32) -> Memory {
+        self.byte_width = Some(byte_width);
+        self.clone()
+    }
+
+    /// 書き込みモード
+    pub fn mode(&mut self, mode: WriteMode) -> Memory {
+        self.mode = mode;
+        self.clone()
+    }
+
+    /// 初期値($readmemh で読み込む)
+    pub fn init(&mut self, data: Vec<u64>) -> Memory {
+        self.init = data;
+        self.bin = false;
+        self.clone()
+    }
+
+    /// 初期値($readmemb で読み込む)
+    pub fn init_bin(&mut self, data: Vec<u64>) -> Memory {
+        self.init = data;
+        self.bin = true;
+        self.clone()
+    }
+
+    /// ポートの追加(デュアルポートは A, B の順)
+    pub fn port(&mut self, port: MemPort) -> Memory {
+        self.ports.push(port);
+        self.clone()
+    }
+
+    /// 初期値ファイル名
+    pub fn file_name(&self) -> String {
+        format!("{}.mem", self.name)
+    }
+
+    // 構成とポートの整合性の検査(エラーメッセージの一覧)
+    pub(crate) fn check(&self, m: &VModule) -> Vec<String> {
+        let mut errs = Vec::new();
+        let count = match self.kind {
+            MemKind::SinglePort => 1,
+            MemKind::Rom => self.ports.len().clamp(1, 2),
+            _ => 2,
+        };
+        if self.ports.len() != count {
+            errs.push(format!("{:?} needs {} port(s)", self.kind, count));
+        }
+        if self.latency < 0 {
+            errs.push("latency must not be negative".to_string());
+        }
+        for (i, p) in self.ports.iter().enumerate() {
+            let (w, r) = (p.we.is_some(), p.dout.is_some());
+            let ok = match self.kind {
+                MemKind::SimpleDualPort if i == 0 => w && !r,
+                MemKind::SimpleDualPort => !w && r,
+                MemKind::Rom => !w && r,
+                _ => w || r,
+            };
+            if !ok {
+                errs.push(format!(
+                    "port {} has invalid read/write setting",
+                    port_label(i)
+                ));
+            }
+            if p.be.is_some() != (w && self.byte_width.is_some()) {
+                errs.push(format!(
+                    "port {} byte enable does not match byte_enable setting",
+                    port_label(i)
+                ));
+            }
+        }
+        if let Some(bw) = self.byte_width {
+            match m.const_eval(&self.width) {
+                Some(w) if bw > 0 && w % bw as i64 == 0 => {}
+                _ => errs.push("width must be a constant multiple of byte width".to_string()),
+            }
+        }
+        if self.kind == MemKind::Rom && self.init.is_empty() {
+            errs.push("ROM needs initial data".to_string());
+        }
+        if let Some(d) = m.const_eval(&self.depth) {
+            if self.init.len() as i64 > d {
+                errs.push("initial data exceeds depth".to_string());
+            }
+        }
+        errs
+    }
+
+    // 書き込みレーンの一覧(バイトイネーブル無しなら 1 レーン、幅が定数でなければ None)
+    fn lanes(&self, m: &VModule, p: &MemPort) -> Option<Vec<Lane>> {
+        match (self.byte_width, &p.be) {
+            (Some(bw), Some(be)) => {
+                let w = m.const_eval(&self.width)?;
+                if bw <= 0 || w % bw as i64 != 0 {
+                    return None;
+                }
+                let n = (w / bw as i64) as i32;
+                Some(
+                    (0..n)
+                        .map(|i| (Some(be.addr(i)), Some((i * bw + bw - 1, i * bw))))
+                        .collect(),
+                )
+            }
+            _ => Some(vec![(None, None)]),
+        }
+    }
+
+    // ポートのクロック同期部分(word は読み書きするワード、q は読み出しレジスタ)
+    fn port_always(&self, m: &VModule, word: Box<E>, p: &MemPort, q: &[Box<E>]) -> Option<Always> {
+        let sel = |x: Box<E>, r: Option<(i32, i32)>| match r {
+            Some((h, l)) => x.range(h, l),
+            None => x,
+        };
+        let mut first = Vec::new();
+        match (&p.we, &p.din) {
+            (Some(we), Some(din)) => {
+                let lanes = self.lanes(m, p)?;
+                let cond = |b: Option<Box<E>>| match b {
+                    Some(b) => we.land(b),
+                    None => we.clone(),
+                };
+                match (q.first(), self.mode) {
+                    (Some(q1), WriteMode::WriteFirst) => {
+                        for (b, r) in lanes {
+                            let write = vec![
+                                sel(word.clone(), r).sst(sel(din.clone(), r)),
+                                sel(q1.clone(), r).sst(sel(din.clone(), r)),
+                            ];
+                            let read = sel(q1.clone(), r).sst(sel(word.clone(), r));
+                            first.push(If(cond(b), write).Else(Form(read)));
+                        }
+                    }
+                    (Some(q1), WriteMode::NoChange) => {
+                        let write = lanes
+                            .into_iter()
+                            .map(|(b, r)| {
+                                let st = sel(word.clone(), r).sst(sel(din.clone(), r));
+                                match b {
+                                    Some(b) => If(b, Form(st)),
+                                    None => st,
+                                }
+                            })
+                            .collect::<Vec<_>>();
+                        first.push(If(we, write).Else(Form(q1.sst(&word))));
+                    }
+                    (q1, _) => {
+                        for (b, r) in lanes {
+                            let st = sel(word.clone(), r).sst(sel(din.clone(), r));
+                            first.push(If(cond(b), Form(st)));
+                        }
+                        if let Some(q1) = q1 {
+                            first.push(q1.sst(&word));
+                        }
+                    }
+                }
+            }
+            _ => {
+                if let Some(q1) = q.first() {
+                    first.push(q1.sst(&word));
+                }
+            }
+        }
+        for k in 1..q.len() {
+            first.push(q[k].sst(&q[k - 1]));
+        }
+        let mut al = Always::new().posedge(&p.clk).non();
+        al.stmt = match p.en {
+            Some(ref en) => Form(If(en, first)),
+            None => first,
+        };
+        Some(al)
+    }
+
+    // 初期化文の出力
+    pub(crate) fn print_init(&self) -> String {
+        if self.init.is_empty() {
+            return String::new();
+        }
+        let task = if self.bin { "$readmemb" } else { "$readmemh" };
+        format!(
+            "    initial {}(\"{}\", {});\n",
+            task,
+            self.file_name(),
+            self.name
+        )
+    }
+
+    pub(crate) fn print_init_list(list: &[Memory]) -> String {
+        list.iter().map(|x| x.print_init()).collect()
+    }
+
+    // 初期値ファイルの内容(幅が定数なら桁数を揃える)
+    fn mem_file(&self, m: &VModule) -> String {
+        let w = m.const_eval(&self.width).map(|w| w as usize);
+        self.init
+            .iter()
+            .map(|&v| match (self.bin, w) {
+                (true, Some(w)) => format!("{:0w$b}\n", v, w = w),
+                (true, None) => format!("{:b}\n", v),
+                (false, Some(w)) => format!("{:0w$X}\n", v, w = w.div_ceil(4)),
+                (false, None) => format!("{:X}\n", v),
+            })
+            .collect()
+    }
+}
+
+fn port_label(i: usize) -> &'static str {
+    if i == 0 {
+        "a"
+    } else {
+        "b"
+    }
+}
+
+impl VModule {
+    /// RAM/ROM の追加(配列、読み出しレジスタ、always と assign を生成)
+    /// 構成の誤りは validate() で報告する
+    pub fn memory(&mut self, mem: Memory) -> Box<E> {
+        let array = self.Mem(&mem.name, (mem.width.clone(), mem.depth.clone()));
+        for (i, p) in mem.ports.iter().enumerate() {
+            let q = match p.dout {
+                Some(_) => (1..=mem.latency)
+                    .map(|k| {
+                        let name = format!("{}_{}_q{}", mem.name, port_label(i), k);
+                        self.reg(&name, mem.width.clone())
+                    })
+                    .collect::<Vec<_>>(),
+                None => Vec::new(),
+            };
+            if p.we.is_some() || !q.is_empty() {
+                if let Some(al) = mem.port_always(self, array.addr(&p.addr), p, &q) {
+                    self.always.push(al);
+                }
+            }
+            if let Some(ref dout) = p.dout {
+                let rhs = match q.last() {
+                    Some(x) => x.clone(),
+                    None => array.addr(&p.addr),
+                };
+                self.assign.push(dout._e(rhs));
+            }
+        }
+        self.memory.push(mem);
+        array
+    }
+
+    /// 初期値ファイルの名前と内容の一覧
+    pub fn mem_files(&self) -> Vec<(String, String)> {
+        self.memory
+            .iter()
+            .filter(|x| !x.init.is_empty())
+            .map(|x| (x.file_name(), x.mem_file(self)))
+            .collect()
+    }
+
+    /// Verilog と初期値ファイルを同じディレクトリに出力
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.gen())?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        for (name, body) in self.mem_files() {
+            fs::write(dir.join(name), body)?;
+        }
+        Ok(())
+    }
+}
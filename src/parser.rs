@@ -0,0 +1,1255 @@
+//! Verilog-2001 パーサ
+//! 既存の Verilog モジュールを VModule として取り込む
+
+use error::VerugentError;
+use literal::Literal;
+use std::collections::HashMap;
+use vcore::*;
+
+type PResult<T> = Result<T, VerugentError>;
+// begin-end を展開した文のリスト
+type Stmts = Vec<Box<E>>;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Num(String),
+    Str(String),
+    Sym(&'static str),
+    Eof,
+}
+
+/// 記号(最長一致のため長いものから並べる)
+const SYMS: [&str; 45] = [
+    "<<<", ">>>", "===", "!==", "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "~&", "~|", "~^",
+    "^~", "**", "+:", "-:", "(", ")", "[", "]", "{", "}", ":", ";", ",", ".", "#", "@", "=", "+",
+    "-", "*", "/", "%", "&", "|", "^", "~", "!", "?", "<", ">",
+];
+
+/// 二項演算子の記号と対応する演算子(~^ と ^~ は xor の反転)
+const BIN_SYMS: [(&str, BinOp); 22] = [
+    ("||", BinOp::LOr),
+    ("&&", BinOp::LAnd),
+    ("|", BinOp::Or),
+    ("^", BinOp::Xor),
+    ("~^", BinOp::Xor),
+    ("^~", BinOp::Xor),
+    ("&", BinOp::And),
+    ("==", BinOp::Eq),
+    ("!=", BinOp::Ne),
+    ("<", BinOp::Lt),
+    ("<=", BinOp::Le),
+    (">", BinOp::Gt),
+    (">=", BinOp::Ge),
+    ("<<", BinOp::Shl),
+    (">>", BinOp::Shr),
+    ("<<<", BinOp::Shl),
+    (">>>", BinOp::Sra),
+    ("+", BinOp::Add),
+    ("-", BinOp::Sub),
+    ("*", BinOp::Mul),
+    ("/", BinOp::Div),
+    ("%", BinOp::Mod),
+];
+
+fn parse_err(line: usize, message: &str) -> VerugentError {
+    VerugentError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+// 字句解析
+fn lex(src: &str) -> PResult<Vec<(Tok, usize)>> {
+    let cs = src.chars().collect::<Vec<_>>();
+    let mut toks = Vec::new();
+    let mut i = 0;
+    let mut line = 1;
+    while i < cs.len() {
+        let c = cs[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && i + 1 < cs.len() && cs[i + 1] == '/' {
+            while i < cs.len() && cs[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && i + 1 < cs.len() && cs[i + 1] == '*' {
+            i += 2;
+            while i + 1 < cs.len() && !(cs[i] == '*' && cs[i + 1] == '/') {
+                if cs[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c == '`' {
+            // コンパイラ指示子は読み飛ばす
+            while i < cs.len() && cs[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let st = i;
+            while i < cs.len() && (cs[i].is_alphanumeric() || cs[i] == '_' || cs[i] == '$') {
+                i += 1;
+            }
+            toks.push((Tok::Ident(cs[st..i].iter().collect()), line));
+        } else if c.is_ascii_digit() || c == '\'' {
+            let st = i;
+            while i < cs.len() && (cs[i].is_ascii_digit() || cs[i] == '_') {
+                i += 1;
+            }
+            if i < cs.len() && cs[i] == '\'' {
+                i += 1;
+                if i < cs.len() && (cs[i] == 's' || cs[i] == 'S') {
+                    i += 1;
+                }
+                i += 1;
+                while i < cs.len() && (cs[i].is_ascii_hexdigit() || "xXzZ?_".contains(cs[i])) {
+                    i += 1;
+                }
+            }
+            toks.push((Tok::Num(cs[st..i].iter().collect()), line));
+        } else if c == '"' {
+            // 文字列(属性の値)はエスケープを含めてそのまま保持する
+            let st = i + 1;
+            i += 1;
+            while i < cs.len() && cs[i] != '"' && cs[i] != '\n' {
+                i += if cs[i] == '\\' { 2 } else { 1 };
+            }
+            if i >= cs.len() || cs[i] != '"' {
+                return Err(parse_err(line, "unterminated string"));
+            }
+            toks.push((Tok::Str(cs[st..i].iter().collect()), line));
+            i += 1;
+        } else {
+            let rest = cs[i..cs.len().min(i + 3)].iter().collect::<String>();
+            match SYMS.iter().find(|s| rest.starts_with(*s)) {
+                Some(s) => {
+                    toks.push((Tok::Sym(s), line));
+                    i += s.len();
+                }
+                None => return Err(parse_err(line, &format!("unexpected character '{}'", c))),
+            }
+        }
+    }
+    toks.push((Tok::Eof, line));
+    Ok(toks)
+}
+
+struct Parser {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    black_box: bool,
+    known: Vec<VModule>,
+    syms: HashMap<String, WireVar>,
+    funcs: HashMap<String, Box<E>>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> &Tok {
+        &self.toks[cmp_min(self.pos + n, self.toks.len() - 1)].0
+    }
+
+    fn line(&self) -> usize {
+        self.toks[cmp_min(self.pos, self.toks.len() - 1)].1
+    }
+
+    // 終端以降も位置を進めるので、読み戻しは常に pos -= 1 でよい
+    fn next(&mut self) -> Tok {
+        let t = self.peek().clone();
+        self.pos += 1;
+        t
+    }
+
+    fn err<T>(&self, message: &str) -> PResult<T> {
+        Err(parse_err(self.line(), message))
+    }
+
+    fn is_sym(&self, s: &str) -> bool {
+        matches!(*self.peek(), Tok::Sym(x) if x == s)
+    }
+
+    fn is_kw(&self, k: &str) -> bool {
+        matches!(*self.peek(), Tok::Ident(ref x) if x == k)
+    }
+
+    fn eat_sym(&mut self, s: &str) -> bool {
+        if self.is_sym(s) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_kw(&mut self, k: &str) -> bool {
+        if self.is_kw(k) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_sym(&mut self, s: &str) -> PResult<()> {
+        if self.eat_sym(s) {
+            Ok(())
+        } else {
+            self.err(&format!("expected '{}' but found {}", s, self.describe()))
+        }
+    }
+
+    fn expect_kw(&mut self, k: &str) -> PResult<()> {
+        if self.eat_kw(k) {
+            Ok(())
+        } else {
+            self.err(&format!("expected {} but found {}", k, self.describe()))
+        }
+    }
+
+    fn ident(&mut self) -> PResult<String> {
+        match self.next() {
+            Tok::Ident(s) => Ok(s),
+            _ => {
+                self.pos -= 1;
+                self.err(&format!(
+                    "expected identifier but found {}",
+                    self.describe()
+                ))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match *self.peek() {
+            Tok::Ident(ref s) | Tok::Num(ref s) => format!("'{}'", s),
+            Tok::Str(ref s) => format!("\"{}\"", s),
+            Tok::Sym(s) => format!("'{}'", s),
+            Tok::Eof => "end of file".to_string(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> PResult<Box<E>> {
+        match self.syms.get(name) {
+            Some(wr) => Ok(Box::new(E::Ldc(wr.clone()))),
+            None => self.err(&format!("undeclared identifier {}", name)),
+        }
+    }
+
+    // ------------------------------------------------------------------ module
+
+    fn module(&mut self) -> PResult<VModule> {
+        self.syms.clear();
+        self.funcs.clear();
+        self.expect_kw("module")?;
+        let name = self.ident()?;
+        let mut m = VModule::new(&name);
+        let mut header_params = false;
+        if self.eat_sym("#") {
+            header_params = true;
+            self.expect_sym("(")?;
+            if !self.is_sym(")") {
+                loop {
+                    self.eat_kw("parameter");
+                    self.eat_kw("integer");
+                    self.param_decl(&mut m, true)?;
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+            }
+            self.expect_sym(")")?;
+        }
+
+        let mut order = Vec::new();
+        if self.eat_sym("(") && !self.eat_sym(")") {
+            if self.is_kw("input") || self.is_kw("output") || self.is_kw("inout") {
+                self.ansi_ports(&mut m)?;
+            } else {
+                loop {
+                    order.push(self.ident()?);
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(")")?;
+            }
+        }
+        self.expect_sym(";")?;
+        self.prescan_funcs(&m)?;
+
+        while !self.eat_kw("endmodule") {
+            self.item(&mut m, &order, header_params)?;
+        }
+
+        for name in &order {
+            match self.syms.get(name) {
+                Some(wr) if !matches!(wr.io_param, IOType::None | IOType::Param) => {
+                    m.io_port.push(wr.clone())
+                }
+                _ => return self.err(&format!("port {} has no direction", name)),
+            }
+        }
+        Ok(m)
+    }
+
+    // パラメータ宣言 NAME = expr
+    fn param_decl(&mut self, m: &mut VModule, overridable: bool) -> PResult<()> {
+        if self.is_sym("[") {
+            self.range()?;
+        }
+        let name = self.ident()?;
+        self.expect_sym("=")?;
+        let value = self.expr()?;
+        if m.const_eval(&value).is_none() {
+            return self.err(&format!("parameter {} is not a constant", name));
+        }
+        let p = if overridable {
+            m.add_io_param(&name, value)
+        } else {
+            m.add_local_param(&name, value)
+        };
+        if let E::Ldc(wr) = *p {
+            self.syms.insert(name, wr);
+        }
+        Ok(())
+    }
+
+    // ANSI 形式のポートリスト
+    fn ansi_ports(&mut self, m: &mut VModule) -> PResult<()> {
+        let mut dir = String::new();
+        let mut is_reg = false;
+        let mut range = None;
+        let mut signed = false;
+        loop {
+            if self.is_kw("input") || self.is_kw("output") || self.is_kw("inout") {
+                dir = self.ident()?;
+                is_reg = false;
+                if self.eat_kw("reg") {
+                    is_reg = true;
+                } else {
+                    self.eat_kw("wire");
+                }
+                signed = self.eat_kw("signed");
+                range = if self.is_sym("[") {
+                    Some(self.range()?)
+                } else {
+                    None
+                };
+            }
+            let name = self.ident()?;
+            let mut wr = self.port(m, &name, &dir, is_reg, &range)?;
+            wr.signed = signed;
+            m.io_port.push(wr.clone());
+            self.syms.insert(name, wr);
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+        self.expect_sym(")")
+    }
+
+    fn port(
+        &self,
+        m: &VModule,
+        name: &str,
+        dir: &str,
+        is_reg: bool,
+        range: &Option<(Box<E>, Box<E>)>,
+    ) -> PResult<WireVar> {
+        let mut wr = WireVar::new();
+        match dir {
+            "input" => wr.input(name, 0),
+            "inout" => wr.inout(name, 0),
+            _ if is_reg => wr.output_reg(name, 0),
+            _ => wr.output(name, 0),
+        };
+        self.apply_range(m, &mut wr, range)?;
+        Ok(wr)
+    }
+
+    fn no_signed(&mut self) -> PResult<()> {
+        if self.is_kw("signed") {
+            return self.err("signed declarations are not supported");
+        }
+        Ok(())
+    }
+
+    // [msb:lsb]
+    fn range(&mut self) -> PResult<(Box<E>, Box<E>)> {
+        self.expect_sym("[")?;
+        let msb = self.expr()?;
+        self.expect_sym(":")?;
+        let lsb = self.expr()?;
+        self.expect_sym("]")?;
+        Ok((msb, lsb))
+    }
+
+    // [expr:0] はパラメータを含む幅の式、それ以外は定数として評価
+    fn apply_range(
+        &self,
+        m: &VModule,
+        wr: &mut WireVar,
+        range: &Option<(Box<E>, Box<E>)>,
+    ) -> PResult<()> {
+        let (msb, lsb) = match *range {
+            Some((ref msb, ref lsb)) => (msb, lsb),
+            None => {
+                wr.width = 0;
+                return Ok(());
+            }
+        };
+        if m.const_eval(lsb) == Some(0) && !is_number(msb) {
+            wr.width_p = Some(size_expr(msb));
+            return Ok(());
+        }
+        match (m.const_eval(msb), m.const_eval(lsb)) {
+            (Some(h), Some(l)) => {
+                wr.width = ((h - l).abs() + 1) as i32;
+                Ok(())
+            }
+            _ => self.err(&format!("unsupported range for {}", wr.name)),
+        }
+    }
+
+    // [0:expr] はパラメータを含む長さの式、それ以外は定数として評価
+    fn apply_array(&self, m: &VModule, wr: &mut WireVar, range: (Box<E>, Box<E>)) -> PResult<()> {
+        let (a, b) = range;
+        for (z, p) in [(&a, &b), (&b, &a)].iter() {
+            if m.const_eval(z) == Some(0) && !is_number(p) {
+                wr.length_p = Some(size_expr(p));
+                return Ok(());
+            }
+        }
+        match (m.const_eval(&a), m.const_eval(&b)) {
+            (Some(a), Some(b)) => {
+                wr.length = ((a - b).abs() + 1) as i32;
+                Ok(())
+            }
+            _ => self.err(&format!("unsupported array range for {}", wr.name)),
+        }
+    }
+
+    // 関数名を先に登録(宣言前の呼び出しに対応)
+    fn prescan_funcs(&mut self, m: &VModule) -> PResult<()> {
+        let start = self.pos;
+        while *self.peek() != Tok::Eof && !self.is_kw("endmodule") {
+            if self.eat_kw("function") {
+                self.eat_kw("automatic");
+                let range = if self.is_sym("[") {
+                    Some(self.range()?)
+                } else {
+                    None
+                };
+                let name = self.ident()?;
+                let mut top = WireVar::new();
+                top.wire(&name, 0);
+                self.apply_range(m, &mut top, &range)?;
+                self.funcs.insert(name, Box::new(E::Ldc(top)));
+            } else {
+                self.next();
+            }
+        }
+        self.pos = start;
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------ items
+
+    fn item(&mut self, m: &mut VModule, order: &[String], header_params: bool) -> PResult<()> {
+        if self.is_sym("(") && *self.peek_at(1) == Tok::Sym("*") {
+            while !(self.eat_sym("*") && self.is_sym(")")) {
+                if *self.peek() == Tok::Eof {
+                    return self.err("unterminated attribute");
+                }
+                self.next();
+            }
+            self.next();
+            return Ok(());
+        }
+        let kw = match *self.peek() {
+            Tok::Ident(ref s) => s.clone(),
+            _ => return self.err(&format!("unexpected {}", self.describe())),
+        };
+        match kw.as_str() {
+            "parameter" | "localparam" => {
+                self.next();
+                self.no_signed()?;
+                let overridable = kw == "parameter" && !header_params;
+                loop {
+                    self.param_decl(m, overridable)?;
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "input" | "output" | "inout" => {
+                self.next();
+                let is_reg = self.eat_kw("reg");
+                self.eat_kw("wire");
+                let signed = self.eat_kw("signed");
+                let range = if self.is_sym("[") {
+                    Some(self.range()?)
+                } else {
+                    None
+                };
+                loop {
+                    let name = self.ident()?;
+                    if !order.contains(&name) {
+                        return self.err(&format!("{} is not in the port list", name));
+                    }
+                    let mut wr = self.port(m, &name, &kw, is_reg, &range)?;
+                    wr.signed = signed;
+                    self.syms.insert(name, wr);
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "wire" | "reg" | "integer" => self.net_decl(m, order),
+            _ if self.black_box => self.skip_item(),
+            "assign" => {
+                self.next();
+                loop {
+                    let lhs = self.lvalue()?;
+                    self.expect_sym("=")?;
+                    let rhs = self.expr()?;
+                    m.assign(lhs._e(rhs));
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")
+            }
+            "always" => {
+                let al = self.always()?;
+                m.always(al);
+                Ok(())
+            }
+            "function" => {
+                let f = self.function(m)?;
+                m.func(f);
+                Ok(())
+            }
+            _ => match self.known.iter().find(|x| x.name == kw).cloned() {
+                Some(sub) => self.instance(m, &sub),
+                None => self.err(&format!("unsupported item {}", kw)),
+            },
+        }
+    }
+
+    fn net_decl(&mut self, m: &mut VModule, order: &[String]) -> PResult<()> {
+        let kw = self.ident()?;
+        let signed = self.eat_kw("signed") || kw == "integer";
+        let range = if kw == "integer" {
+            Some((Box::new(E::Num(31)), Box::new(E::Num(0))))
+        } else if self.is_sym("[") {
+            Some(self.range()?)
+        } else {
+            None
+        };
+        loop {
+            let name = self.ident()?;
+            if order.contains(&name) {
+                // 非 ANSI 形式の output reg
+                if let Some(wr) = self.syms.get_mut(&name) {
+                    wr.reg_set = kw != "wire";
+                    wr.signed |= signed;
+                }
+            } else {
+                let mut wr = WireVar::new();
+                if kw == "wire" {
+                    wr.wire(&name, 0);
+                } else {
+                    wr.reg(&name, 0);
+                }
+                wr.signed = signed;
+                self.apply_range(m, &mut wr, &range)?;
+                if self.is_sym("[") {
+                    let arr = self.range()?;
+                    self.apply_array(m, &mut wr, arr)?;
+                }
+                if !self.black_box {
+                    m.local_param.push(wr.clone());
+                }
+                self.syms.insert(name.clone(), wr);
+                if self.eat_sym("=") {
+                    // reg の初期値は継続代入にできない
+                    if kw != "wire" && !self.black_box {
+                        return self.err(&format!(
+                            "initial value of {} {} is not supported",
+                            kw, name
+                        ));
+                    }
+                    let rhs = self.expr()?;
+                    let lhs = self.lookup(&name)?;
+                    if !self.black_box {
+                        m.assign(lhs._e(rhs));
+                    }
+                }
+            }
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+        self.expect_sym(";")
+    }
+
+    fn always(&mut self) -> PResult<Always> {
+        self.expect_kw("always")?;
+        let mut al = onedge();
+        self.expect_sym("@")?;
+        if !self.eat_sym("*") {
+            self.expect_sym("(")?;
+            if !self.eat_sym("*") {
+                let mut edges = Vec::new();
+                loop {
+                    let edge = if self.eat_kw("posedge") {
+                        1
+                    } else if self.eat_kw("negedge") {
+                        -1
+                    } else {
+                        0
+                    };
+                    let sig = self.ident()?;
+                    edges.push((edge, self.lookup(&sig)?));
+                    if !(self.eat_kw("or") || self.eat_sym(",")) {
+                        break;
+                    }
+                }
+                // レベルセンシティブなリストは組み合わせ回路として扱う
+                if edges.iter().any(|e| e.0 != 0) && edges.iter().any(|e| e.0 == 0) {
+                    return self.err("sensitivity list mixes edges and levels");
+                }
+                if edges.iter().all(|e| e.0 != 0) {
+                    for (edge, sig) in edges {
+                        if edge > 0 {
+                            al.posedge(sig);
+                        } else {
+                            al.negedge(sig);
+                        }
+                    }
+                }
+            }
+            self.expect_sym(")")?;
+        }
+        let mut nonblock = None;
+        al.stmt = self.stmt(&mut nonblock)?;
+        if nonblock == Some(true) {
+            al.non();
+        } else {
+            al.block();
+        }
+        Ok(al)
+    }
+
+    fn function(&mut self, m: &VModule) -> PResult<Func> {
+        self.expect_kw("function")?;
+        self.eat_kw("automatic");
+        self.no_signed()?;
+        if self.is_sym("[") {
+            self.range()?;
+        }
+        let name = self.ident()?;
+        let top = self.funcs[&name].clone();
+        let width = if let E::Ldc(ref wr) = *top {
+            m.resolve_width(wr).unwrap_or(0)
+        } else {
+            0
+        };
+        let mut f = Func::new(&name, width);
+        let saved = self.syms.clone();
+        if let E::Ldc(ref wr) = *top {
+            self.syms.insert(name.clone(), wr.clone());
+        }
+        if self.eat_sym("(") {
+            loop {
+                self.func_input(m, &mut f)?;
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+            self.expect_sym(")")?;
+            self.expect_sym(";")?;
+        } else {
+            self.expect_sym(";")?;
+            while self.is_kw("input") {
+                loop {
+                    self.func_input(m, &mut f)?;
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(";")?;
+            }
+        }
+        let mut nonblock = None;
+        while !self.eat_kw("endfunction") {
+            let stmts = self.stmt(&mut nonblock)?;
+            f.stmt.extend(stmts);
+        }
+        self.syms = saved;
+        Ok(f)
+    }
+
+    fn func_input(&mut self, m: &VModule, f: &mut Func) -> PResult<()> {
+        if !self.eat_kw("input") && f.input.is_empty() {
+            return self.err("expected input");
+        }
+        if self.is_sym("[") {
+            let range = Some(self.range()?);
+            let name = self.ident()?;
+            let mut wr = WireVar::new();
+            wr.input(&name, 0);
+            self.apply_range(m, &mut wr, &range)?;
+            let width = m.resolve_width(&wr).unwrap_or(0);
+            if let E::Ldc(wr) = *f.Input(&name, width) {
+                self.syms.insert(name, wr);
+            }
+        } else {
+            let name = self.ident()?;
+            if let E::Ldc(wr) = *f.Input(&name, 0) {
+                self.syms.insert(name, wr);
+            }
+        }
+        Ok(())
+    }
+
+    fn instance(&mut self, m: &mut VModule, sub: &VModule) -> PResult<()> {
+        self.next();
+        let mut params = Vec::new();
+        if self.eat_sym("#") {
+            self.expect_sym("(")?;
+            params = self.connections(&sub.io_param)?;
+        }
+        let name = self.ident()?;
+        self.expect_sym("(")?;
+        let ports = self.connections(&sub.io_port)?;
+        self.expect_sym(";")?;
+        let inst = m.instance(sub, &name);
+        for (n, v) in params {
+            inst.param(&n, v);
+        }
+        for (n, v) in ports {
+            inst.port(&n, v);
+        }
+        Ok(())
+    }
+
+    // 名前付き/順序付きの接続リスト(開き括弧の後から閉じ括弧まで)
+    fn connections(&mut self, decl: &[WireVar]) -> PResult<Vec<(String, Box<E>)>> {
+        let mut list = Vec::new();
+        if self.eat_sym(")") {
+            return Ok(list);
+        }
+        loop {
+            if self.eat_sym(".") {
+                let n = self.ident()?;
+                self.expect_sym("(")?;
+                if !self.eat_sym(")") {
+                    let e = self.expr()?;
+                    self.expect_sym(")")?;
+                    list.push((n, e));
+                }
+            } else {
+                let e = self.expr()?;
+                match decl.get(list.len()) {
+                    Some(wr) => list.push((wr.name.clone(), e)),
+                    None => return self.err("too many connections"),
+                }
+            }
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+        self.expect_sym(")")?;
+        Ok(list)
+    }
+
+    // ------------------------------------------------------------------ skip
+
+    // ブラックボックス取り込み時の読み飛ばし
+    fn skip_item(&mut self) -> PResult<()> {
+        if self.eat_kw("always") || self.eat_kw("initial") {
+            if self.eat_sym("@") && !self.eat_sym("*") {
+                self.skip_parens()?;
+            }
+            return self.skip_stmt();
+        }
+        for &(start, end) in &[
+            ("function", "endfunction"),
+            ("task", "endtask"),
+            ("generate", "endgenerate"),
+        ] {
+            if self.eat_kw(start) {
+                while !self.eat_kw(end) {
+                    if self.next() == Tok::Eof {
+                        return self.err(&format!("missing {}", end));
+                    }
+                }
+                return Ok(());
+            }
+        }
+        self.skip_to(";")
+    }
+
+    fn skip_to(&mut self, s: &str) -> PResult<()> {
+        while !self.eat_sym(s) {
+            if *self.peek() == Tok::Eof {
+                return self.err(&format!("missing '{}'", s));
+            }
+            self.next();
+        }
+        Ok(())
+    }
+
+    fn skip_parens(&mut self) -> PResult<()> {
+        self.expect_sym("(")?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Tok::Sym("(") => depth += 1,
+                Tok::Sym(")") => depth -= 1,
+                Tok::Eof => return self.err("missing ')'"),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_stmt(&mut self) -> PResult<()> {
+        if self.eat_kw("begin") {
+            while !self.eat_kw("end") {
+                if *self.peek() == Tok::Eof {
+                    return self.err("missing end");
+                }
+                self.skip_stmt()?;
+            }
+            if self.eat_sym(":") {
+                self.ident()?;
+            }
+            Ok(())
+        } else if self.eat_kw("if") {
+            self.skip_parens()?;
+            self.skip_stmt()?;
+            if self.eat_kw("else") {
+                self.skip_stmt()?;
+            }
+            Ok(())
+        } else if self.eat_kw("case") || self.eat_kw("casez") || self.eat_kw("casex") {
+            while !self.eat_kw("endcase") {
+                if *self.peek() == Tok::Eof {
+                    return self.err("missing endcase");
+                }
+                if self.is_kw("begin") || self.is_kw("if") || self.is_kw("case") {
+                    self.skip_stmt()?;
+                } else {
+                    self.next();
+                }
+            }
+            Ok(())
+        } else if self.eat_kw("for") || self.eat_kw("while") || self.eat_kw("repeat") {
+            self.skip_parens()?;
+            self.skip_stmt()
+        } else if self.eat_kw("forever") {
+            self.skip_stmt()
+        } else if self.eat_sym("@") || self.eat_sym("#") {
+            if self.is_sym("(") {
+                self.skip_parens()?;
+            } else {
+                self.next();
+            }
+            self.skip_stmt()
+        } else {
+            self.skip_to(";")
+        }
+    }
+
+    // ------------------------------------------------------------------ statements
+
+    // 文(begin-end は展開した文のリストとして返す)
+    fn stmt(&mut self, nonblock: &mut Option<bool>) -> PResult<Stmts> {
+        if self.eat_kw("begin") {
+            if self.eat_sym(":") {
+                self.ident()?;
+            }
+            let mut list = Vec::new();
+            while !self.eat_kw("end") {
+                if *self.peek() == Tok::Eof {
+                    return self.err("missing end");
+                }
+                list.extend(self.stmt(nonblock)?);
+            }
+            return Ok(list);
+        }
+        if self.eat_sym(";") {
+            return Ok(Vec::new());
+        }
+        if self.eat_kw("if") {
+            let mut list = Vec::new();
+            self.expect_sym("(")?;
+            let cond = self.expr()?;
+            self.expect_sym(")")?;
+            let body = self.stmt(nonblock)?;
+            list.push(IfElseAST {
+                if_: true,
+                cond,
+                stmt: body,
+            });
+            while self.eat_kw("else") {
+                if self.eat_kw("if") {
+                    self.expect_sym("(")?;
+                    let cond = self.expr()?;
+                    self.expect_sym(")")?;
+                    let body = self.stmt(nonblock)?;
+                    list.push(IfElseAST {
+                        if_: true,
+                        cond,
+                        stmt: body,
+                    });
+                } else {
+                    let body = self.stmt(nonblock)?;
+                    list.push(IfElseAST {
+                        if_: false,
+                        cond: Box::new(E::Null),
+                        stmt: body,
+                    });
+                    break;
+                }
+            }
+            return Ok(vec![Box::new(E::BL(list))]);
+        }
+        if self.eat_kw("case") {
+            self.expect_sym("(")?;
+            let sel = self.expr()?;
+            self.expect_sym(")")?;
+            let var = match *sel {
+                E::Ldc(wr) => wr,
+                _ => return self.err("case selector must be a signal"),
+            };
+            let mut cs = CaseStmt {
+                CaseVar: var,
+                Select: Vec::new(),
+            };
+            while !self.eat_kw("endcase") {
+                if self.eat_kw("default") {
+                    self.eat_sym(":");
+                    let body = self.stmt(nonblock)?;
+                    cs.SetCaseS(Box::new(E::Null), body);
+                    continue;
+                }
+                let mut labels = vec![self.expr()?];
+                while self.eat_sym(",") {
+                    labels.push(self.expr()?);
+                }
+                self.expect_sym(":")?;
+                let body = self.stmt(nonblock)?;
+                for l in labels {
+                    cs.SetCaseS(l, body.clone());
+                }
+            }
+            return Ok(vec![Box::new(E::CS(cs))]);
+        }
+        if let Tok::Ident(ref s) = *self.peek() {
+            if [
+                "casez", "casex", "for", "while", "repeat", "forever", "wait", "fork",
+            ]
+            .contains(&s.as_str())
+                || s.starts_with('$')
+            {
+                return self.err(&format!("unsupported statement {}", s));
+            }
+        }
+        let lhs = self.lvalue()?;
+        let nb = if self.eat_sym("<=") {
+            true
+        } else {
+            self.expect_sym("=")?;
+            false
+        };
+        if nonblock.is_none() {
+            *nonblock = Some(nb);
+        }
+        let rhs = self.expr()?;
+        self.expect_sym(";")?;
+        // ブロックの先頭の代入と異なる代入方法は文ごとに指定する
+        Ok(vec![match (*nonblock == Some(nb), nb) {
+            (true, _) => lhs.sst(rhs),
+            (false, true) => lhs.sst_non(rhs),
+            (false, false) => lhs.sst_block(rhs),
+        }])
+    }
+
+    fn lvalue(&mut self) -> PResult<Box<E>> {
+        if self.eat_sym("{") {
+            let mut items = vec![self.lvalue()?];
+            while self.eat_sym(",") {
+                items.push(self.lvalue()?);
+            }
+            self.expect_sym("}")?;
+            return Ok(concat(items));
+        }
+        let name = self.ident()?;
+        let base = self.lookup(&name)?;
+        self.selects(base)
+    }
+
+    fn selects(&mut self, mut e: Box<E>) -> PResult<Box<E>> {
+        while self.eat_sym("[") {
+            let idx = self.expr()?;
+            if self.is_sym("+:") || self.is_sym("-:") {
+                return self.err("indexed part select is not supported");
+            }
+            if self.eat_sym(":") {
+                let l = self.expr()?;
+                e = e.range(idx, l);
+            } else {
+                e = e.addr(idx);
+            }
+            self.expect_sym("]")?;
+        }
+        Ok(e)
+    }
+
+    // ------------------------------------------------------------------ expressions
+
+    fn expr(&mut self) -> PResult<Box<E>> {
+        let c = self.binary(1)?;
+        if self.eat_sym("?") {
+            let t = self.expr()?;
+            self.expect_sym(":")?;
+            let f = self.expr()?;
+            return Ok(_Branch(c, t, f));
+        }
+        Ok(c)
+    }
+
+    fn binary(&mut self, min_prec: i32) -> PResult<Box<E>> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match *self.peek() {
+                Tok::Sym(s) => BIN_SYMS
+                    .iter()
+                    .find(|b| b.0 == s && b.1.precedence() >= min_prec),
+                _ => None,
+            };
+            let &(sym, op) = match op {
+                Some(op) => op,
+                None => {
+                    if self.is_sym("**") {
+                        return self.err("power operator is not supported");
+                    }
+                    // x/z を比較する演算子は == / != に置き換えられない
+                    if self.is_sym("===") || self.is_sym("!==") {
+                        return self.err(&format!(
+                            "case equality operator {} is not supported",
+                            self.describe()
+                        ));
+                    }
+                    return Ok(lhs);
+                }
+            };
+            self.next();
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Box::new(E::Bin(op, lhs, rhs));
+            if sym.contains('~') {
+                lhs = Box::new(E::Un(UnaryOp::Not, lhs));
+            }
+        }
+    }
+
+    fn unary(&mut self) -> PResult<Box<E>> {
+        let red = [
+            ("&", ReduceOp::And),
+            ("|", ReduceOp::Or),
+            ("^", ReduceOp::Xor),
+            ("~&", ReduceOp::Nand),
+            ("~|", ReduceOp::Nor),
+            ("~^", ReduceOp::Xnor),
+            ("^~", ReduceOp::Xnor),
+        ];
+        let unary = [
+            ("~", UnaryOp::Not),
+            ("!", UnaryOp::LNot),
+            ("-", UnaryOp::Neg),
+        ];
+        for &(s, op) in &unary {
+            if self.eat_sym(s) {
+                return Ok(Box::new(E::Un(op, self.unary()?)));
+            }
+        }
+        if self.eat_sym("+") {
+            return self.unary();
+        }
+        for &(s, op) in &red {
+            if self.eat_sym(s) {
+                return Ok(Box::new(E::Red(op, self.unary()?)));
+            }
+        }
+        self.primary()
+    }
+
+    // 繰り返しの中身(要素が1つならその式)
+    fn cat_list(&mut self) -> PResult<Box<E>> {
+        let mut items = vec![self.expr()?];
+        while self.eat_sym(",") {
+            items.push(self.expr()?);
+        }
+        self.expect_sym("}")?;
+        Ok(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            concat(items)
+        })
+    }
+
+    fn primary(&mut self) -> PResult<Box<E>> {
+        match self.next() {
+            // 幅・基数付きの数値と 32 ビットに収まらない数値は Literal として保持する
+            Tok::Num(raw) => match raw.replace('_', "").parse::<i32>() {
+                Ok(v) => Ok(_Num(v)),
+                Err(_) => match Literal::parse(&raw) {
+                    Ok(l) => Ok(Box::new(E::Lit(l))),
+                    Err(msg) => {
+                        self.pos -= 1;
+                        self.err(&msg)
+                    }
+                },
+            },
+            Tok::Sym("(") => {
+                let e = self.expr()?;
+                self.expect_sym(")")?;
+                Ok(e)
+            }
+            Tok::Sym("{") => {
+                let first = self.expr()?;
+                if self.eat_sym("{") {
+                    let inner = self.cat_list()?;
+                    self.expect_sym("}")?;
+                    return Ok(replicate(first, inner));
+                }
+                let mut items = vec![first];
+                while self.eat_sym(",") {
+                    items.push(self.expr()?);
+                }
+                self.expect_sym("}")?;
+                Ok(concat(items))
+            }
+            Tok::Ident(ref name) if name == "$clog2" => {
+                self.expect_sym("(")?;
+                let e = self.expr()?;
+                self.expect_sym(")")?;
+                Ok(clog2(e))
+            }
+            Tok::Ident(ref name) if name == "$signed" || name == "$unsigned" => {
+                self.expect_sym("(")?;
+                let e = self.expr()?;
+                self.expect_sym(")")?;
+                Ok(if name == "$signed" {
+                    e.signed()
+                } else {
+                    e.unsigned()
+                })
+            }
+            Tok::Ident(name) => {
+                if self.is_sym("(") {
+                    let top = match self.funcs.get(&name) {
+                        Some(top) => top.clone(),
+                        None => return self.err(&format!("unknown function {}", name)),
+                    };
+                    self.next();
+                    let mut args = Vec::new();
+                    if !self.eat_sym(")") {
+                        loop {
+                            args.push(self.expr()?);
+                            if !self.eat_sym(",") {
+                                break;
+                            }
+                        }
+                        self.expect_sym(")")?;
+                    }
+                    return Ok(Box::new(E::Func(top, args)));
+                }
+                let base = self.lookup(&name)?;
+                self.selects(base)
+            }
+            _ => {
+                self.pos -= 1;
+                self.err(&format!("unexpected {}", self.describe()))
+            }
+        }
+    }
+}
+
+fn cmp_min(a: usize, b: usize) -> usize {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+// 数値のみの式
+fn is_number(e: &E) -> bool {
+    match *e {
+        E::Num(_) | E::Lit(_) => true,
+        E::Bin(_, ref l, ref r) => is_number(l) && is_number(r),
+        E::Un(_, ref a) => is_number(a),
+        _ => false,
+    }
+}
+
+// 最上位の添字から幅・長さの式(X-1 なら X、それ以外は expr+1)
+fn size_expr(msb: &E) -> Box<E> {
+    match *msb {
+        E::Bin(BinOp::Sub, ref x, ref r) if matches!(**r, E::Num(1)) => x.clone(),
+        _ => Box::new(msb.clone()) + 1,
+    }
+}
+
+fn parse_modules(src: &str, black_box: bool, known: Vec<VModule>) -> PResult<Vec<VModule>> {
+    let mut p = Parser {
+        toks: lex(src)?,
+        pos: 0,
+        black_box,
+        known,
+        syms: HashMap::new(),
+        funcs: HashMap::new(),
+    };
+    let mut list = Vec::new();
+    while *p.peek() != Tok::Eof {
+        let m = p.module()?;
+        list.push(m);
+    }
+    Ok(list)
+}
+
+/// Verilog ソース中の全モジュールの取り込み
+pub fn parse(src: &str) -> Result<Vec<VModule>, VerugentError> {
+    let headers = parse_black_box(src)?;
+    parse_modules(src, false, headers)
+}
+
+/// モジュールのパラメータとポートのみの取り込み(インスタンス用のブラックボックス)
+pub fn parse_black_box(src: &str) -> Result<Vec<VModule>, VerugentError> {
+    parse_modules(src, true, Vec::new())
+}
+
+impl VModule {
+    /// Verilog ソースの先頭モジュールから VModule を生成
+    pub fn from_verilog(src: &str) -> Result<VModule, VerugentError> {
+        match parse(src)?.into_iter().next() {
+            Some(m) => Ok(m),
+            None => Err(parse_err(1, "no module found")),
+        }
+    }
+}
@@ -0,0 +1,818 @@
+//! VModule の AST を直接実行するサイクルシミュレータ
+
+use std::cmp;
+use std::collections::HashMap;
+use vcd::Vcd;
+use vcore::*;
+use width::{clog2_of, Width};
+
+/// 組み合わせ回路が収束しない場合の反復上限
+const SETTLE_LIMIT: usize = 1000;
+
+// ビット幅のマスク
+fn mask(width: i32) -> u64 {
+    if width >= 64 {
+        !0
+    } else {
+        (1u64 << cmp::max(width, 1)) - 1
+    }
+}
+
+// 64 ビットへの符号拡張
+fn sext(value: u64, width: i32) -> u64 {
+    if width >= 64 {
+        value
+    } else {
+        let sh = (64 - cmp::max(width, 1)) as u32;
+        (((value << sh) as i64) >> sh) as u64
+    }
+}
+
+// 文脈の幅と符号に合わせた値
+fn fit(value: u64, width: i32, signed: bool) -> u64 {
+    if signed {
+        sext(value, width)
+    } else {
+        value & mask(width)
+    }
+}
+
+// 部分選択の範囲(昇順の指定は入れ替え、64 ビットを超える位置は丸める)
+fn range(h: u64, l: u64) -> (u32, u32) {
+    let clip = |x: u64| cmp::min(x, 127) as u32;
+    (clip(cmp::max(h, l)), clip(cmp::min(h, l)))
+}
+
+// 部分選択のマスク
+fn range_mask(h: u32, l: u32) -> u64 {
+    mask((h - l + 1) as i32).checked_shl(l).unwrap_or(0)
+}
+
+// 代入先
+enum Target {
+    Whole(String),
+    Bit(String, u32),
+    Range(String, u32, u32),
+    Mem(String, usize),
+    MemRange(String, usize, u32, u32),
+    // 連接の各要素と幅(先頭が上位ビット)
+    Cat(Vec<(Target, i32)>),
+}
+
+/// 信号の値と幅
+#[derive(Clone, Debug)]
+pub(crate) struct Signal {
+    pub(crate) width: i32,
+    pub(crate) value: u64,
+}
+
+/// 階層スコープ(インスタンスごとのモジュール)
+#[derive(Clone, Debug)]
+pub(crate) struct Scope {
+    pub(crate) path: String,
+    pub(crate) module: VModule,
+}
+
+/// 組み合わせ代入(インスタンスのポート接続を含む)
+#[derive(Clone, Debug)]
+struct Comb {
+    lhs_scope: usize,
+    lhs: Box<E>,
+    rhs_scope: usize,
+    rhs: Box<E>,
+}
+
+/// 実行中の always ブロックの状態
+struct Frame {
+    blocking: bool,
+    nonblock: Vec<(Target, u64)>,
+}
+
+/// サイクルシミュレータ
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    pub(crate) scopes: Vec<Scope>,
+    pub(crate) signals: HashMap<String, Signal>,
+    mems: HashMap<String, (i32, Vec<u64>)>,
+    combs: Vec<Comb>,
+    blocks: Vec<(usize, Always)>,
+    edges: HashMap<String, u64>,
+    cycle: u64,
+    pub(crate) time: u64,
+    pub(crate) vcd: Option<Vcd>,
+}
+
+impl FsmModule {
+    // FSM を等価な always ブロックに展開
+    pub(crate) fn to_always(&self) -> Vec<Always> {
+        if self.states.is_empty() {
+            return Vec::new();
+        }
+        let reg = match *self.state_reg {
+            E::Ldc(ref wr) => wr.clone(),
+            _ => return Vec::new(),
+        };
+        let next = WireVar::new().reg(&(reg.name.clone() + "_Next"), self.state_width());
+        let state = Box::new(E::Ldc(reg.clone()));
+        let init = self.states[0].state.clone();
+
+        let mut fst = posedge(&self.clk).posedge(&self.rst).non();
+        fst.if_(_Eq(&self.rst, 1), vec![_Veq(&state, &init)]);
+        fst.else_(vec![_Veq(&state, &next)]);
+
+        let mut select = CaseStmt {
+            CaseVar: reg,
+            Select: Vec::new(),
+        };
+        for s in &self.states {
+            select.SetCaseS(s.state.clone(), vec![Box::new(E::BL(s.branch.clone()))]);
+        }
+        let mut snd = posedge(&self.clk).non();
+        snd.if_(&self.rst, vec![_Veq(&next, &init)]);
+        snd.else_(vec![Box::new(E::CS(select))]);
+        let mut list = vec![fst, snd];
+        list.extend(self.output_always());
+        list
+    }
+}
+
+impl Simulator {
+    /// モジュールからシミュレータを生成(全信号は 0 で初期化)
+    pub fn new(module: &VModule) -> Simulator {
+        let mut sim = Simulator {
+            scopes: Vec::new(),
+            signals: HashMap::new(),
+            mems: HashMap::new(),
+            combs: Vec::new(),
+            blocks: Vec::new(),
+            edges: HashMap::new(),
+            cycle: 0,
+            time: 0,
+            vcd: None,
+        };
+        sim.elaborate(module.clone(), String::new());
+        for (s, al) in &sim.blocks {
+            for wr in al.posedges.iter().chain(al.negedges.iter()) {
+                let key = sim.scopes[*s].path.clone() + &wr.name;
+                sim.edges.insert(key, 0);
+            }
+        }
+        sim.eval();
+        sim
+    }
+
+    // モジュールとサブモジュールの展開
+    fn elaborate(&mut self, module: VModule, path: String) -> usize {
+        if !module.axi.is_empty() {
+            panic!("AXI interface cannot be simulated: {}", module.name);
+        }
+        if !module.inline.is_empty() {
+            panic!("Inline verilog cannot be simulated: {}", module.name);
+        }
+        let module = module.unroll_generate();
+        let id = self.scopes.len();
+        for wr in module.io_port.iter().chain(module.local_param.iter()) {
+            if let IOType::Param = wr.io_param {
+                continue;
+            }
+            let width = module.resolve_width(wr).unwrap_or(1);
+            let key = path.clone() + &wr.name;
+            if wr.length > 0 || wr.length_p.is_some() {
+                let len = module.resolve_length(wr).unwrap_or(0);
+                self.mems
+                    .insert(key, (width, vec![0; cmp::max(len, 0) as usize]));
+            } else {
+                self.signals.insert(key, Signal { width, value: 0 });
+            }
+        }
+        // $readmemh/$readmemb による初期値
+        for mem in &module.memory {
+            if let Some(&mut (w, ref mut data)) = self.mems.get_mut(&(path.clone() + &mem.name)) {
+                for (x, &v) in data.iter_mut().zip(mem.init.iter()) {
+                    *x = v & mask(w);
+                }
+            }
+        }
+        for a in &module.assign {
+            self.combs.push(Comb {
+                lhs_scope: id,
+                lhs: a.lhs.clone(),
+                rhs_scope: id,
+                rhs: a.rhs.clone(),
+            });
+        }
+        for al in &module.always {
+            self.blocks.push((id, al.clone()));
+        }
+        for fsm in &module.fsm {
+            for al in fsm.to_always() {
+                self.blocks.push((id, al));
+            }
+        }
+        self.scopes.push(Scope {
+            path: path.clone(),
+            module: module.clone(),
+        });
+
+        for inst in &module.instance {
+            // パラメータの上書きを反映したサブモジュール
+            let sub = inst.overridden(&module);
+            let sid = self.elaborate(sub.clone(), format!("{}{}.", path, inst.name));
+            for (name, signal) in &inst.port_map {
+                let port = match sub.io_port.iter().find(|p| p.name == *name) {
+                    Some(p) => Box::new(E::Ldc(p.clone())),
+                    None => continue,
+                };
+                let output = matches!(
+                    sub.io_port
+                        .iter()
+                        .find(|p| p.name == *name)
+                        .unwrap()
+                        .io_param,
+                    IOType::Output
+                );
+                self.combs.push(if output {
+                    Comb {
+                        lhs_scope: id,
+                        lhs: signal.clone(),
+                        rhs_scope: sid,
+                        rhs: port,
+                    }
+                } else {
+                    Comb {
+                        lhs_scope: sid,
+                        lhs: port,
+                        rhs_scope: id,
+                        rhs: signal.clone(),
+                    }
+                });
+            }
+        }
+        id
+    }
+
+    /// 信号(input/output などの戻り値)への値の設定
+    pub fn poke<T: Into<Box<E>>>(&mut self, signal: T, value: u64) {
+        let key = self.key(0, &signal.into());
+        self.write_key(&key, value);
+    }
+
+    /// 信号の値の取得
+    pub fn peek<T: Into<Box<E>>>(&self, signal: T) -> u64 {
+        let e = signal.into();
+        let v = self.eval_expr(0, &e, None);
+        // 符号拡張された値は式の幅に戻す
+        if self.is_signed(0, &e) {
+            v & mask(self.width(0, &e))
+        } else {
+            v
+        }
+    }
+
+    /// 階層名("u_sub.Q" など)による信号の値の取得
+    pub fn value(&self, path: &str) -> Option<u64> {
+        self.signals.get(path).map(|s| s.value)
+    }
+
+    /// 経過したクロックサイクル数
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// シミュレーション時刻(クロックの半周期を 1 とする)
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// 組み合わせ回路の評価とエッジで起動する always ブロックの実行
+    pub fn eval(&mut self) {
+        for _ in 0..SETTLE_LIMIT {
+            self.settle();
+            let mut fired = Vec::new();
+            for (i, (s, al)) in self.blocks.iter().enumerate() {
+                let path = &self.scopes[*s].path;
+                let hit = |wr: &WireVar, rise: bool| {
+                    let key = path.clone() + &wr.name;
+                    let old = self.edges.get(&key).cloned().unwrap_or(0) & 1;
+                    let new = self.signals.get(&key).map_or(0, |x| x.value & 1);
+                    if rise {
+                        old == 0 && new == 1
+                    } else {
+                        old == 1 && new == 0
+                    }
+                };
+                if al.posedges.iter().any(|wr| hit(wr, true))
+                    || al.negedges.iter().any(|wr| hit(wr, false))
+                {
+                    fired.push(i);
+                }
+            }
+            let keys = self.edges.keys().cloned().collect::<Vec<_>>();
+            for key in keys {
+                let v = self.signals.get(&key).map_or(0, |x| x.value);
+                self.edges.insert(key, v);
+            }
+            if fired.is_empty() {
+                self.sample();
+                return;
+            }
+            // ノンブロッキング代入は起動した全ブロックの実行後に反映する
+            let mut updates = Vec::new();
+            for i in fired {
+                let (s, al) = self.blocks[i].clone();
+                let mut frame = Frame {
+                    blocking: al.subst() == Subst::Blocking,
+                    nonblock: Vec::new(),
+                };
+                for st in &al.stmt {
+                    self.exec(s, st, &mut frame);
+                }
+                updates.extend(frame.nonblock);
+            }
+            for (t, v) in updates {
+                self.apply(t, v);
+            }
+        }
+        panic!("Simulation did not settle: edge loop");
+    }
+
+    /// クロックの 1 サイクル(立ち上がりと立ち下がり)の実行
+    pub fn tick<T: Into<Box<E>>>(&mut self, clk: T) {
+        let clk = clk.into();
+        self.time += 1;
+        self.poke(&clk, 1);
+        self.eval();
+        self.time += 1;
+        self.poke(&clk, 0);
+        self.eval();
+        self.cycle += 1;
+    }
+
+    // 組み合わせ回路の収束計算
+    fn settle(&mut self) {
+        for _ in 0..SETTLE_LIMIT {
+            let before = self.signals.clone();
+            for c in self.combs.clone() {
+                let w = cmp::max(
+                    self.ctx_width(c.lhs_scope, &c.lhs),
+                    self.ctx_width(c.rhs_scope, &c.rhs),
+                );
+                let v = self.eval_ctx(c.rhs_scope, &c.rhs, w, None);
+                let t = self.target(c.lhs_scope, &c.lhs, None);
+                self.apply(t, v);
+            }
+            for (s, al) in self.blocks.clone() {
+                if !al.posedges.is_empty() || !al.negedges.is_empty() {
+                    continue;
+                }
+                let mut frame = Frame {
+                    blocking: true,
+                    nonblock: Vec::new(),
+                };
+                for st in &al.stmt {
+                    self.exec(s, st, &mut frame);
+                }
+                for (t, v) in frame.nonblock {
+                    self.apply(t, v);
+                }
+            }
+            if self
+                .signals
+                .iter()
+                .all(|(k, s)| before.get(k).map(|b| b.value) == Some(s.value))
+            {
+                return;
+            }
+        }
+        panic!("Simulation did not settle: combinational loop");
+    }
+
+    fn key(&self, scope: usize, e: &E) -> String {
+        match *e {
+            E::Ldc(ref wr) => self.scopes[scope].path.clone() + &wr.name,
+            _ => panic!("Not a signal: {:?}", e),
+        }
+    }
+
+    fn write_key(&mut self, key: &str, value: u64) {
+        match self.signals.get_mut(key) {
+            Some(s) => s.value = value & mask(s.width),
+            None => panic!("Unknown signal: {}", key),
+        }
+    }
+
+    fn read_var(
+        &self,
+        scope: usize,
+        wr: &WireVar,
+        locals: Option<&HashMap<String, Signal>>,
+    ) -> u64 {
+        let value = |s: &Signal| {
+            if wr.signed {
+                sext(s.value, s.width)
+            } else {
+                s.value
+            }
+        };
+        if let Some(v) = locals.and_then(|l| l.get(&wr.name)) {
+            return value(v);
+        }
+        let m = &self.scopes[scope].module;
+        match wr.io_param {
+            IOType::Param => m.const_eval(&E::Ldc(wr.clone())).unwrap_or(0) as u64,
+            _ => {
+                let key = self.scopes[scope].path.clone() + &wr.name;
+                self.signals.get(&key).map_or(0, value)
+            }
+        }
+    }
+
+    fn width(&self, scope: usize, e: &E) -> i32 {
+        self.scopes[scope]
+            .module
+            .infer_width(e)
+            .map_or(64, |w| w.bits())
+    }
+
+    // 文脈で決まる式の幅(幅指定の無い定数は 32 ビット)
+    fn ctx_width(&self, scope: usize, e: &E) -> i32 {
+        match self.scopes[scope].module.infer_width(e) {
+            Some(Width::Sized(w)) => w,
+            Some(Width::Unsized(w)) => cmp::max(w, 32),
+            None => 64,
+        }
+    }
+
+    fn is_signed(&self, scope: usize, e: &E) -> bool {
+        self.scopes[scope].module.is_signed(e)
+    }
+
+    // 演算のオペランド値(符号なしの演算では符号付きの値を元の幅に戻す)
+    fn operand(
+        &self,
+        scope: usize,
+        e: &E,
+        signed: bool,
+        width: i32,
+        locals: Option<&HashMap<String, Signal>>,
+    ) -> u64 {
+        let v = self.eval_ctx(scope, e, width, locals);
+        if !signed && self.is_signed(scope, e) {
+            match self.scopes[scope].module.infer_width(e) {
+                Some(Width::Sized(w)) => v & mask(w),
+                _ => v,
+            }
+        } else {
+            v
+        }
+    }
+
+    // 式の評価(自己決定幅)
+    fn eval_expr(&self, scope: usize, e: &E, locals: Option<&HashMap<String, Signal>>) -> u64 {
+        self.eval_ctx(scope, e, self.ctx_width(scope, e), locals)
+    }
+
+    // 代入右辺の評価(左辺と右辺の広い方の幅で演算する)
+    fn eval_rhs(
+        &self,
+        scope: usize,
+        l: &E,
+        r: &E,
+        locals: Option<&HashMap<String, Signal>>,
+    ) -> u64 {
+        let w = cmp::max(self.ctx_width(scope, l), self.ctx_width(scope, r));
+        self.eval_ctx(scope, r, w, locals)
+    }
+
+    // 文脈の幅 width での式の評価(演算ごとに幅で切り捨てる)
+    fn eval_ctx(
+        &self,
+        scope: usize,
+        e: &E,
+        width: i32,
+        locals: Option<&HashMap<String, Signal>>,
+    ) -> u64 {
+        let ev = |x: &E| self.eval_expr(scope, x, locals);
+        match *e {
+            E::Ldc(ref wr) => self.read_var(scope, wr, locals),
+            E::Num(i) => i as i64 as u64,
+            // x/z は 0 として扱う
+            E::Lit(ref l) if l.signed => sext(l.to_u64(), l.bit_width() as i32),
+            E::Lit(ref l) => l.to_u64(),
+            E::Signed(ref x) => sext(ev(x), self.width(scope, x)),
+            E::Unsigned(ref x) => ev(x) & mask(self.width(scope, x)),
+            E::Clog2(ref x) => clog2_of(ev(x) as i64) as u64,
+            E::Bin(op, ref l, ref r) => {
+                // 符号付きの値は 64 ビットに符号拡張されているため、
+                // 符号なしの演算では元の幅に戻す
+                let shift = matches!(op, BinOp::Shl | BinOp::Shr | BinOp::Sra);
+                let signed = if shift {
+                    self.is_signed(scope, l)
+                } else {
+                    self.is_signed(scope, l) && self.is_signed(scope, r)
+                };
+                // 比較と論理演算のオペランドは演算結果とは別の幅で評価する
+                let (lw, rw) = if op.is_compare() {
+                    let w = cmp::max(self.ctx_width(scope, l), self.ctx_width(scope, r));
+                    (w, w)
+                } else if op == BinOp::LAnd || op == BinOp::LOr {
+                    (self.ctx_width(scope, l), self.ctx_width(scope, r))
+                } else if shift {
+                    (width, self.ctx_width(scope, r))
+                } else {
+                    (width, width)
+                };
+                let (a, b) = (
+                    self.operand(scope, l, signed, lw, locals),
+                    self.operand(scope, r, signed && !shift, rw, locals),
+                );
+                let (sa, sb) = (a as i64, b as i64);
+                let v = match op {
+                    BinOp::Add => a.wrapping_add(b),
+                    BinOp::Sub => a.wrapping_sub(b),
+                    BinOp::Mul => a.wrapping_mul(b),
+                    BinOp::Div if signed => sa.checked_div(sb).unwrap_or(0) as u64,
+                    BinOp::Mod if signed => sa.checked_rem(sb).unwrap_or(0) as u64,
+                    BinOp::Div => a.checked_div(b).unwrap_or(0),
+                    BinOp::Mod => a.checked_rem(b).unwrap_or(0),
+                    BinOp::Or => a | b,
+                    BinOp::And => a & b,
+                    BinOp::Xor => a ^ b,
+                    BinOp::Shl => a.checked_shl(b as u32).unwrap_or(0),
+                    // 符号付きの値の論理シフトは文脈の幅の値をシフトする
+                    BinOp::Shr if signed => (a & mask(width)).checked_shr(b as u32).unwrap_or(0),
+                    BinOp::Shr => a.checked_shr(b as u32).unwrap_or(0),
+                    BinOp::Sra if signed => (sa >> cmp::min(b, 63)) as u64,
+                    BinOp::Sra => a.checked_shr(b as u32).unwrap_or(0),
+                    BinOp::LOr => return (a != 0 || b != 0) as u64,
+                    BinOp::LAnd => return (a != 0 && b != 0) as u64,
+                    BinOp::Eq => (a == b) as u64,
+                    BinOp::Ne => (a != b) as u64,
+                    BinOp::Lt if signed => (sa < sb) as u64,
+                    BinOp::Gt if signed => (sa > sb) as u64,
+                    BinOp::Le if signed => (sa <= sb) as u64,
+                    BinOp::Ge if signed => (sa >= sb) as u64,
+                    BinOp::Lt => (a < b) as u64,
+                    BinOp::Gt => (a > b) as u64,
+                    BinOp::Le => (a <= b) as u64,
+                    BinOp::Ge => (a >= b) as u64,
+                };
+                if op.is_compare() {
+                    v
+                } else {
+                    fit(v, width, signed)
+                }
+            }
+            E::Un(UnaryOp::LNot, ref x) => (ev(x) == 0) as u64,
+            E::Un(op, ref x) => {
+                let signed = self.is_signed(scope, x);
+                let v = self.eval_ctx(scope, x, width, locals);
+                match op {
+                    UnaryOp::Neg => fit(v.wrapping_neg(), width, signed),
+                    _ => fit(!v, width, signed),
+                }
+            }
+            E::Red(op, ref x) => {
+                let w = self.width(scope, x);
+                let v = ev(x) & mask(w);
+                match op {
+                    ReduceOp::And => (v == mask(w)) as u64,
+                    ReduceOp::Or => (v != 0) as u64,
+                    ReduceOp::Xor => (v.count_ones() % 2) as u64,
+                    ReduceOp::Nand => (v != mask(w)) as u64,
+                    ReduceOp::Nor => (v == 0) as u64,
+                    ReduceOp::Xnor => (v.count_ones() % 2 != 1) as u64,
+                }
+            }
+            E::PL(ref c, ref t, ref f) => {
+                let signed = self.is_signed(scope, t) && self.is_signed(scope, f);
+                let x = if ev(c) != 0 { t } else { f };
+                self.operand(scope, x, signed, width, locals)
+            }
+            E::MEM(ref m, ref a) => {
+                let idx = ev(a);
+                if let E::Ldc(ref wr) = **m {
+                    let key = self.scopes[scope].path.clone() + &wr.name;
+                    if let Some(&(w, ref mem)) = self.mems.get(&key) {
+                        let v = mem.get(idx as usize).cloned().unwrap_or(0);
+                        return if wr.signed { sext(v, w) } else { v };
+                    }
+                }
+                ev(m).checked_shr(idx as u32).unwrap_or(0) & 1
+            }
+            E::MBT(ref m, ref h, ref l) => {
+                let (h, l) = range(ev(h), ev(l));
+                ev(m).checked_shr(l).unwrap_or(0) & mask((h - l + 1) as i32)
+            }
+            E::Func(ref top, ref args) => {
+                let args = args.iter().map(|x| ev(x)).collect::<Vec<_>>();
+                self.call(scope, &_StrOut(top.clone()), &args)
+            }
+            E::Cat(ref items) => items.iter().fold(0, |v, x| {
+                let w = self.width(scope, x);
+                v.checked_shl(w as u32).unwrap_or(0) | (ev(x) & mask(w))
+            }),
+            E::Rep(ref n, ref x) => {
+                let w = self.width(scope, x);
+                let x = ev(x) & mask(w);
+                (0..ev(n)).fold(0, |v, _| v.checked_shl(w as u32).unwrap_or(0) | x)
+            }
+            _ => 0,
+        }
+    }
+
+    // function の呼び出し(代入は function 内のローカル変数のみを更新する)
+    fn call(&self, scope: usize, name: &str, args: &[u64]) -> u64 {
+        let m = &self.scopes[scope].module;
+        let f = match m.func.iter().find(|f| _StrOut(f.top.clone()) == name) {
+            Some(f) => f,
+            None => panic!("Unknown function: {}", name),
+        };
+        let mut locals = HashMap::new();
+        for x in f.input.iter().chain(Some(&f.top)) {
+            if let E::Ldc(ref wr) = **x {
+                let width = m.resolve_width(wr).unwrap_or(1);
+                locals.insert(wr.name.clone(), Signal { width, value: 0 });
+            }
+        }
+        for (x, v) in f.input.iter().zip(args) {
+            if let E::Ldc(ref wr) = **x {
+                let s = locals.get_mut(&wr.name).unwrap();
+                s.value = v & mask(s.width);
+            }
+        }
+        for st in &f.stmt {
+            self.exec_local(scope, st, &mut locals);
+        }
+        locals.get(name).map_or(0, |s| s.value)
+    }
+
+    fn exec_local(&self, scope: usize, e: &E, locals: &mut HashMap<String, Signal>) {
+        if let E::SB(ref l, ref r) | E::SBX(_, ref l, ref r) = *e {
+            let v = self.eval_rhs(scope, l, r, Some(locals));
+            let t = self.target(scope, l, Some(locals));
+            Simulator::apply_to(locals, &mut HashMap::new(), t, v);
+            return;
+        }
+        for st in self.select(scope, e, Some(locals)) {
+            self.exec_local(scope, st, locals);
+        }
+    }
+
+    // 代入先の解決
+    fn target(&self, scope: usize, e: &E, locals: Option<&HashMap<String, Signal>>) -> Target {
+        let name = |x: &E| match *x {
+            E::Ldc(ref wr) => {
+                if locals.is_some_and(|l| l.contains_key(&wr.name)) {
+                    wr.name.clone()
+                } else {
+                    self.scopes[scope].path.clone() + &wr.name
+                }
+            }
+            _ => panic!("Unsupported assignment target: {:?}", x),
+        };
+        match *e {
+            E::Ldc(_) => Target::Whole(name(e)),
+            E::MEM(ref m, ref a) => {
+                let key = name(m);
+                let idx = self.eval_expr(scope, a, locals);
+                if self.mems.contains_key(&key) {
+                    Target::Mem(key, idx as usize)
+                } else {
+                    Target::Bit(key, idx as u32)
+                }
+            }
+            E::MBT(ref m, ref h, ref l) => {
+                let (h, l) = range(
+                    self.eval_expr(scope, h, locals),
+                    self.eval_expr(scope, l, locals),
+                );
+                match self.target(scope, m, locals) {
+                    Target::Whole(key) => Target::Range(key, h, l),
+                    Target::Mem(key, idx) => Target::MemRange(key, idx, h, l),
+                    _ => panic!("Unsupported assignment target: {:?}", e),
+                }
+            }
+            E::Cat(ref items) => Target::Cat(
+                items
+                    .iter()
+                    .map(|x| (self.target(scope, x, locals), self.width(scope, x)))
+                    .collect(),
+            ),
+            _ => panic!("Unsupported assignment target: {:?}", e),
+        }
+    }
+
+    fn apply(&mut self, t: Target, v: u64) {
+        Simulator::apply_to(&mut self.signals, &mut self.mems, t, v);
+    }
+
+    fn apply_to(
+        signals: &mut HashMap<String, Signal>,
+        mems: &mut HashMap<String, (i32, Vec<u64>)>,
+        t: Target,
+        v: u64,
+    ) {
+        match t {
+            Target::Mem(key, idx) => {
+                if let Some(&mut (w, ref mut mem)) = mems.get_mut(&key) {
+                    if idx < mem.len() {
+                        mem[idx] = v & mask(w);
+                    }
+                }
+            }
+            Target::MemRange(key, idx, h, l) => {
+                if let Some(&mut (w, ref mut mem)) = mems.get_mut(&key) {
+                    if idx < mem.len() {
+                        let m = range_mask(h, l);
+                        mem[idx] =
+                            ((mem[idx] & !m) | (v.checked_shl(l).unwrap_or(0) & m)) & mask(w);
+                    }
+                }
+            }
+            Target::Whole(key) => {
+                if let Some(s) = signals.get_mut(&key) {
+                    s.value = v & mask(s.width);
+                }
+            }
+            Target::Bit(key, i) => {
+                if let Some(s) = signals.get_mut(&key) {
+                    if i < 64 {
+                        s.value = (s.value & !(1 << i)) | ((v & 1) << i);
+                    }
+                }
+            }
+            Target::Range(key, h, l) => {
+                if let Some(s) = signals.get_mut(&key) {
+                    let m = range_mask(h, l);
+                    s.value =
+                        ((s.value & !m) | (v.checked_shl(l).unwrap_or(0) & m)) & mask(s.width);
+                }
+            }
+            Target::Cat(parts) => {
+                let mut v = v;
+                for (t, w) in parts.into_iter().rev() {
+                    Simulator::apply_to(signals, mems, t, v & mask(w));
+                    v = v.checked_shr(w as u32).unwrap_or(0);
+                }
+            }
+        }
+    }
+
+    // 文の実行
+    fn exec(&mut self, scope: usize, e: &E, frame: &mut Frame) {
+        let (l, r, blocking) = match *e {
+            E::SB(ref l, ref r) => (l, r, frame.blocking),
+            E::SBX(sub, ref l, ref r) => (l, r, sub == Subst::Blocking),
+            _ => {
+                for st in self.select(scope, e, None) {
+                    self.exec(scope, st, frame);
+                }
+                return;
+            }
+        };
+        let v = self.eval_rhs(scope, l, r, None);
+        let t = self.target(scope, l, None);
+        if blocking {
+            self.apply(t, v);
+        } else {
+            frame.nonblock.push((t, v));
+        }
+    }
+
+    // if/case で実行される文の選択
+    fn select<'b>(
+        &self,
+        scope: usize,
+        e: &'b E,
+        locals: Option<&HashMap<String, Signal>>,
+    ) -> &'b [Box<E>] {
+        match *e {
+            E::BL(ref list) => {
+                for (i, x) in list.iter().enumerate() {
+                    let hit = match *x.cond {
+                        // 先頭の条件無し分岐は無条件に実行される
+                        E::Null => i == 0 || !x.if_,
+                        ref c => self.eval_expr(scope, c, locals) != 0,
+                    };
+                    if hit {
+                        return &x.stmt;
+                    }
+                }
+                &[]
+            }
+            E::CS(ref c) => {
+                let sel = self.read_var(scope, &c.CaseVar, locals);
+                c.Select
+                    .iter()
+                    .find(|x| match *x.CaseT {
+                        E::Null => false,
+                        ref l => self.eval_expr(scope, l, locals) == sel,
+                    })
+                    .or_else(|| c.Select.iter().find(|x| matches!(*x.CaseT, E::Null)))
+                    .map_or(&[], |x| &x.CaseS)
+            }
+            _ => &[],
+        }
+    }
+}
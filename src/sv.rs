@@ -0,0 +1,313 @@
+//! SystemVerilog 構文の出力
+
+use generate::{GenItem, Generate};
+use memory::Memory;
+use std::cmp;
+use vcore::*;
+
+// インデントの出力
+fn tab(indent: i32) -> String {
+    "    ".repeat(cmp::max(indent, 0) as usize)
+}
+
+// 式の出力(式の構文は Verilog と共通)
+fn expr(e: &E) -> String {
+    decomp_ast(Box::new(e.clone()), "", 0)
+}
+
+impl WireVar {
+    fn print_as_sv_port(&self) -> String {
+        let kind = match self.io_param {
+            IOType::Input => "input  logic ",
+            IOType::Output => "output logic ",
+            IOType::InOut => "inout  wire ",
+            IOType::Param | IOType::Genvar | IOType::None => return String::new(),
+        };
+        format!("{}{}{}", kind, self.print_range(), self.name)
+    }
+
+    fn print_as_sv_local(&self) -> String {
+        match self.io_param {
+            IOType::Param => format!("localparam {} = {};", self.name, expr(&self.value)),
+            IOType::Genvar => format!("genvar {};", self.name),
+            _ => format!(
+                "logic {}{}{};",
+                self.print_range(),
+                self.name,
+                self.print_array()
+            ),
+        }
+    }
+}
+
+impl FsmModule {
+    // ステート型の名前
+    fn sv_type(&self) -> String {
+        format!("{}_t", _StrOut(self.state_reg.clone()))
+    }
+
+    pub(crate) fn state_names(&self) -> Vec<String> {
+        self.states
+            .iter()
+            .map(|s| _StrOut(s.state.clone()))
+            .collect()
+    }
+
+    // typedef enum によるステート型とステートレジスタの宣言
+    fn print_sv_decl(&self) -> String {
+        let reg = _StrOut(self.state_reg.clone());
+        let width = self.state_width();
+        // 符号化方式の指定があれば各ステートの値を明示する
+        let list = self
+            .state_names()
+            .iter()
+            .zip(self.codes())
+            .map(|(n, c)| match self.encoding {
+                Some(_) => format!("        {} = {}'b{:0w$b}", n, width, c, w = width as usize),
+                None => format!("        {}", n),
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        let attr = self.encoding_attr().map_or(String::new(), |a| {
+            format!("(* fsm_encoding = \"{}\" *) ", a)
+        });
+        format!(
+            "    typedef enum logic [{}:0] {{\n{}\n    }} {};\n    {}{} {}, {}_Next;\n",
+            width - 1,
+            list,
+            self.sv_type(),
+            attr,
+            self.sv_type(),
+            reg,
+            reg
+        )
+    }
+
+    fn print_sv(&self) -> String {
+        let clk = _StrOut(self.clk.clone());
+        let rst = _StrOut(self.rst.clone());
+        let reg = _StrOut(self.state_reg.clone());
+        let init = _StrOut(self.states[0].state.clone());
+        let mut st = String::new();
+        st += &format!(
+            "    always_ff @(posedge {} or posedge {}) begin\n",
+            clk, rst
+        );
+        st += &format!(
+            "        if ({} == 1) begin\n            {} <= {};\n        end\n",
+            rst, reg, init
+        );
+        st += &format!(
+            "        else begin\n            {} <= {}_Next;\n        end\n    end\n\n",
+            reg, reg
+        );
+        st += &format!("    always_ff @(posedge {}) begin\n", clk);
+        st += &format!("        if ({}) {}_Next <= {};\n", rst, reg, init);
+        st += "        else begin\n";
+        st += &format!("            unique case ({})\n", reg);
+        for s in &self.states {
+            st += &format!("                {} : begin\n", _StrOut(s.state.clone()));
+            st += &print_if_list(&s.branch, false, 5);
+            st += "                end\n";
+        }
+        st += "            endcase\n        end\n    end\n";
+        if let Some(al) = self.output_always() {
+            st += "\n";
+            st += &al.print_sv();
+        }
+        st
+    }
+}
+
+// 文の出力
+fn print_stmt(e: &E, blocking: bool, indent: i32) -> String {
+    match *e {
+        E::SB(ref l, ref r) => format!(
+            "{}{} {} {};\n",
+            tab(indent),
+            expr(l),
+            if blocking { "=" } else { "<=" },
+            expr(r)
+        ),
+        E::SBX(sub, ref l, ref r) => {
+            print_stmt(&E::SB(l.clone(), r.clone()), sub == Subst::Blocking, indent)
+        }
+        E::BL(ref list) => print_if_list(list, blocking, indent),
+        E::CS(ref c) => {
+            let mut st = format!("{}unique case ({})\n", tab(indent), c.CaseVar.name);
+            for x in &c.Select {
+                let label = match *x.CaseT {
+                    E::Null => "default".to_string(),
+                    ref l => expr(l),
+                };
+                st += &format!("{}{} : begin\n", tab(indent + 1), label);
+                for s in &x.CaseS {
+                    st += &print_stmt(s, blocking, indent + 2);
+                }
+                st += &format!("{}end\n", tab(indent + 1));
+            }
+            st += &format!("{}endcase\n", tab(indent));
+            st
+        }
+        _ => format!("{}{};\n", tab(indent), expr(e)),
+    }
+}
+
+// if - else if - else の出力
+fn print_if_list(list: &[IfElseAST], blocking: bool, indent: i32) -> String {
+    let mut st = String::new();
+    for (i, x) in IfElseAST::reachable(list).iter().enumerate() {
+        if i == 0 {
+            if let E::Null = *x.cond {
+                // 条件無しの分岐は文のみを出力
+                for s in &x.stmt {
+                    st += &print_stmt(s, blocking, indent);
+                }
+                return st;
+            }
+            st += &format!("{}if ({}) begin\n", tab(indent), expr(&x.cond));
+        } else if x.if_ {
+            st += &format!("{}else if ({}) begin\n", tab(indent), expr(&x.cond));
+        } else {
+            st += &format!("{}else begin\n", tab(indent));
+        }
+        for s in &x.stmt {
+            st += &print_stmt(s, blocking, indent + 1);
+        }
+        st += &format!("{}end\n", tab(indent));
+    }
+    st
+}
+
+impl Always {
+    fn print_sv(&self) -> String {
+        let pos = self.posedges.iter().map(|p| format!("posedge {}", p.name));
+        let neg = self.negedges.iter().map(|n| format!("negedge {}", n.name));
+        let list = pos.chain(neg).collect::<Vec<_>>().join(" or ");
+        let head = if list.is_empty() {
+            "always_comb".to_string()
+        } else {
+            format!("always_ff @({})", list)
+        };
+        let body = self
+            .stmt
+            .iter()
+            .map(|s| print_stmt(s, self.subst() == Subst::Blocking, 2))
+            .collect::<Vec<_>>()
+            .join("");
+        format!("    {} begin\n{}    end\n", head, body)
+    }
+}
+
+impl Func {
+    fn print_sv(&self) -> String {
+        let mut st = String::new();
+        if let E::Ldc(ref top) = *self.top {
+            st += &format!(
+                "\n    function automatic logic {}{}(\n",
+                top.print_range(),
+                top.name
+            );
+        }
+        st += &self
+            .input
+            .iter()
+            .filter_map(|x| match **x {
+                E::Ldc(ref wr) => Some(format!(
+                    "        input logic {}{}",
+                    wr.print_range(),
+                    wr.name
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        st += "\n    );\n";
+        for s in &self.stmt {
+            st += &print_stmt(s, true, 2);
+        }
+        st += "    endfunction\n\n";
+        st
+    }
+}
+
+impl VModule {
+    // SystemVerilog 構文の出力
+    pub(crate) fn emit_sv(&self) -> String {
+        let fsm = self
+            .fsm
+            .iter()
+            .filter(|f| !f.states.is_empty())
+            .collect::<Vec<_>>();
+        // FSM のステートとレジスタは typedef enum として宣言する
+        let mut fsm_names = Vec::new();
+        for f in &fsm {
+            let reg = _StrOut(f.state_reg.clone());
+            fsm_names.extend(f.state_names());
+            fsm_names.push(reg.clone() + "_Next");
+            fsm_names.push(reg);
+        }
+
+        let mut st = format!("module {} ", self.name);
+        st += &WireVar::print_params(&self.io_param);
+        let ports = self
+            .io_port
+            .iter()
+            .filter(|p| p.is_port())
+            .collect::<Vec<_>>();
+        if ports.is_empty() {
+            st += "();\n";
+        } else {
+            let list = ports
+                .iter()
+                .map(|port| format!("    {}", port.print_as_sv_port()))
+                .collect::<Vec<_>>()
+                .join(",\n");
+            st += &format!("(\n{}\n);\n", list);
+        }
+        for wr in &self.local_param {
+            if !fsm_names.contains(&wr.name) {
+                st += &format!("    {}\n", wr.print_as_sv_local());
+            }
+        }
+        for f in &fsm {
+            st += &f.print_sv_decl();
+        }
+        st += &Memory::print_init_list(&self.memory);
+        st += &Assign::print_list(&self.assign);
+        st += &Instance::print_list(&self.instance);
+        st += &self
+            .always
+            .iter()
+            .map(|al| al.print_sv())
+            .collect::<Vec<_>>()
+            .join("\n");
+        st += &Generate::print_list(&self.generate, &|x| match *x {
+            GenItem::Always(ref al) => al.print_sv(),
+            _ => x.print(),
+        });
+        st += &self
+            .func
+            .iter()
+            .map(|f| f.print_sv())
+            .collect::<Vec<_>>()
+            .join("");
+        if !fsm.is_empty() || !self.axi.is_empty() || !self.inline.is_empty() {
+            st += &fsm
+                .iter()
+                .map(|f| f.print_sv())
+                .collect::<Vec<_>>()
+                .join("\n");
+            st += &self
+                .axi
+                .iter()
+                .enumerate()
+                .map(|(i, axi)| print_axi(axi.clone(), i as i32))
+                .collect::<Vec<_>>()
+                .join("");
+            st += &self.inline;
+        }
+        st += "\nendmodule\n";
+        st
+    }
+}
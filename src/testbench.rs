@@ -0,0 +1,217 @@
+//! VModule のポート一覧からのテストベンチ生成
+
+use vcore::*;
+
+// 時刻ごとの入力変更と期待値
+#[derive(Clone, Debug)]
+enum Stim {
+    Drive(String, u64),
+    Expect(String, u64),
+}
+
+/// Verilog テストベンチの生成器
+#[derive(Clone, Debug)]
+pub struct Testbench {
+    dut: VModule,
+    clocks: Vec<(String, u64)>,
+    resets: Vec<(String, u64, u64)>,
+    vectors: Vec<(u64, Stim)>,
+    now: u64,
+    dumpfile: String,
+}
+
+impl Testbench {
+    /// テストベンチの生成器の作成
+    pub fn new(dut: &VModule) -> Testbench {
+        Testbench {
+            dut: dut.clone(),
+            clocks: Vec::new(),
+            resets: Vec::new(),
+            vectors: Vec::new(),
+            now: 0,
+            dumpfile: format!("{}_tb.vcd", dut.name),
+        }
+    }
+
+    // ポートの検索
+    fn port<T: Into<Box<E>>>(&self, signal: T) -> &WireVar {
+        let name = _StrOut(signal);
+        match self.dut.io_port.iter().find(|p| p.name == name) {
+            Some(p) => p,
+            None => panic!("module {} has no port {}", self.dut.name, name),
+        }
+    }
+
+    // 値を設定する入力ポートの検索
+    fn input<T: Into<Box<E>>>(&self, signal: T) -> String {
+        let port = self.port(signal);
+        if let IOType::Input = port.io_param {
+            port.name.clone()
+        } else {
+            panic!("port {} is not an input", port.name)
+        }
+    }
+
+    /// クロック信号の指定(period は 2 以上の周期、奇数なら High を 1 長くする)
+    pub fn clock<T: Into<Box<E>>>(&mut self, clk: T, period: u64) -> &mut Testbench {
+        let name = self.input(clk);
+        // 半周期が 0 だと #0 の無限ループになる
+        if period < 2 {
+            panic!("clock {} period {} must be at least 2", name, period)
+        }
+        self.clocks.push((name, period));
+        self
+    }
+
+    /// リセット信号の指定(時刻 0 から duration の間 active の値を出力)
+    pub fn reset<T: Into<Box<E>>>(&mut self, rst: T, active: u64, duration: u64) -> &mut Testbench {
+        let name = self.input(rst);
+        self.resets.push((name, active, duration));
+        self
+    }
+
+    /// 以降の入力変更と期待値の時刻の指定
+    pub fn at(&mut self, time: u64) -> &mut Testbench {
+        self.now = time;
+        self
+    }
+
+    /// 入力ポートへの値の設定
+    pub fn drive<T: Into<Box<E>>>(&mut self, signal: T, value: u64) -> &mut Testbench {
+        let name = self.input(signal);
+        self.vectors.push((self.now, Stim::Drive(name, value)));
+        self
+    }
+
+    /// ポートの期待値(同時刻では入力変更より先に比較する)
+    pub fn expect<T: Into<Box<E>>>(&mut self, signal: T, value: u64) -> &mut Testbench {
+        let name = self.port(signal).name.clone();
+        self.vectors.push((self.now, Stim::Expect(name, value)));
+        self
+    }
+
+    /// 時刻、入力値、期待値の組によるテストベクタの追加
+    pub fn vector(
+        &mut self,
+        time: u64,
+        drive: Vec<(Box<E>, u64)>,
+        expect: Vec<(Box<E>, u64)>,
+    ) -> &mut Testbench {
+        self.at(time);
+        for (s, v) in drive {
+            self.drive(s, v);
+        }
+        for (s, v) in expect {
+            self.expect(s, v);
+        }
+        self
+    }
+
+    /// 波形ファイル名の指定
+    pub fn dumpfile(&mut self, path: &str) -> &mut Testbench {
+        self.dumpfile = path.to_string();
+        self
+    }
+
+    /// テストベンチの出力
+    pub fn gen(&self) -> String {
+        let tb = format!("{}_tb", self.dut.name);
+        let mut st = format!("`timescale 1ns / 1ps\n\nmodule {};\n", tb);
+        for p in &self.dut.io_param {
+            st += &format!(
+                "    localparam {} = {};\n",
+                p.name,
+                decomp_ast(p.value.clone(), "", 0)
+            );
+        }
+        for p in &self.dut.io_port {
+            let kind = match p.io_param {
+                IOType::Input => "reg ",
+                _ => "wire",
+            };
+            st += &format!("    {} {}{};\n", kind, p.print_range(), p.name);
+        }
+
+        // 被テストモジュールの全ポートを同名の信号に接続
+        let mut dut = Instance::new(&self.dut, "dut");
+        for p in &self.dut.io_param {
+            dut.param(&p.name, Box::new(E::Ldc(p.clone())));
+        }
+        for p in &self.dut.io_port {
+            dut.port(&p.name, Box::new(E::Ldc(p.clone())));
+        }
+        st += "\n";
+        st += &Instance::print_list(&[dut]);
+
+        for (clk, period) in &self.clocks {
+            let low = period / 2;
+            st += &format!("\n    initial begin\n        {} = 0;\n", clk);
+            if period % 2 == 0 {
+                st += &format!("        forever #{} {} = ~{};\n", low, clk, clk);
+            } else {
+                st += &format!(
+                    "        forever begin\n            #{} {} = 1;\n            #{} {} = 0;\n        end\n",
+                    low,
+                    clk,
+                    period - low,
+                    clk
+                );
+            }
+            st += "    end\n";
+        }
+        st += &format!(
+            "\n    initial begin\n        $dumpfile(\"{}\");\n        $dumpvars(0, {});\n    end\n",
+            self.dumpfile, tb
+        );
+
+        // リセットの解除をテストベクタに含めて時刻順に並べる
+        let mut events = self
+            .resets
+            .iter()
+            .map(|(rst, active, duration)| (*duration, Stim::Drive(rst.clone(), 1 - (active & 1))))
+            .collect::<Vec<_>>();
+        events.extend(self.vectors.iter().cloned());
+        events.sort_by_key(|(t, s)| {
+            (
+                *t,
+                match *s {
+                    Stim::Expect(..) => 0,
+                    Stim::Drive(..) => 1,
+                },
+            )
+        });
+
+        st += "\n    initial begin\n";
+        for p in &self.dut.io_port {
+            if let IOType::Input = p.io_param {
+                if self.clocks.iter().any(|c| c.0 == p.name) {
+                    continue;
+                }
+                let init = self
+                    .resets
+                    .iter()
+                    .find(|r| r.0 == p.name)
+                    .map_or(0, |r| r.1);
+                st += &format!("        {} = {};\n", p.name, init);
+            }
+        }
+        let mut now = 0;
+        for (t, s) in &events {
+            if *t > now {
+                st += &format!("        #{};\n", t - now);
+                now = *t;
+            }
+            st += &match *s {
+                Stim::Drive(ref n, v) => format!("        {} = {};\n", n, v),
+                Stim::Expect(ref n, v) => format!(
+                    "        if ({} !== {}) $error(\"%0t: {} = %0d, expected {}\", $time, {});\n",
+                    n, v, n, v, n
+                ),
+            };
+        }
+        let tail = self.clocks.iter().map(|c| c.1).max().unwrap_or(1);
+        st += &format!("        #{};\n        $finish;\n    end\n", tail);
+        st += "\nendmodule\n";
+        st
+    }
+}
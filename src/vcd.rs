@@ -0,0 +1,149 @@
+//! シミュレーション結果の VCD (Value Change Dump) 出力
+
+use sim::Simulator;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+/// 記録中の VCD
+#[derive(Clone, Debug)]
+pub(crate) struct Vcd {
+    header: String,
+    body: String,
+    // (階層名, 識別子, ビット幅)
+    vars: Vec<(String, String, i32)>,
+    last: HashMap<String, u64>,
+    last_time: Option<u64>,
+}
+
+// VCD の識別子(印字可能文字による 94 進数)
+fn code(mut n: usize) -> String {
+    let mut st = String::new();
+    loop {
+        st.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            return st;
+        }
+        n -= 1;
+    }
+}
+
+// '*' を任意の文字列とするパターン照合
+fn glob(pattern: &str, name: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == name,
+        Some(p) => {
+            let (head, rest) = (&pattern[..p], &pattern[p + 1..]);
+            name.starts_with(head)
+                && (0..=name.len() - head.len()).any(|i| {
+                    name.is_char_boundary(head.len() + i) && glob(rest, &name[head.len() + i..])
+                })
+        }
+    }
+}
+
+fn print_value(value: u64, width: i32, id: &str) -> String {
+    if width <= 1 {
+        format!("{}{}\n", value & 1, id)
+    } else {
+        format!("b{:b} {}\n", value, id)
+    }
+}
+
+impl Simulator {
+    /// VCD の記録開始(filter は "u_cnt.*" などの階層名パターン、空の場合は全信号)
+    pub fn trace_vcd(&mut self, filter: &[&str]) {
+        let mut header = String::new();
+        header += "$version Verugent $end\n$timescale 1ns $end\n";
+        let top = self.scopes[0].module.name.clone();
+        let mut vars = Vec::new();
+        let mut stack: Vec<String> = Vec::new();
+        header += &format!("$scope module {} $end\n", top);
+        // スコープはインスタンスの深さ優先順に並んでいる
+        for scope in &self.scopes {
+            let path = scope
+                .path
+                .split('.')
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>();
+            while !path.starts_with(&stack) {
+                stack.pop();
+                header += "$upscope $end\n";
+            }
+            for name in &path[stack.len()..] {
+                header += &format!("$scope module {} $end\n", name);
+                stack.push(name.clone());
+            }
+            let m = &scope.module;
+            for wr in m.io_port.iter().chain(m.local_param.iter()) {
+                let key = scope.path.clone() + &wr.name;
+                let sig = match self.signals.get(&key) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                if !filter.is_empty() && !filter.iter().any(|p| glob(p, &key)) {
+                    continue;
+                }
+                let id = code(vars.len());
+                let kind = if wr.reg_set { "reg" } else { "wire" };
+                header += &format!("$var {} {} {} {} $end\n", kind, sig.width, id, wr.name);
+                vars.push((key, id, sig.width));
+            }
+        }
+        for _ in 0..stack.len() + 1 {
+            header += "$upscope $end\n";
+        }
+        header += "$enddefinitions $end\n";
+
+        let mut vcd = Vcd {
+            header,
+            body: format!("#{}\n$dumpvars\n", self.time),
+            vars,
+            last: HashMap::new(),
+            last_time: Some(self.time),
+        };
+        for (key, id, width) in &vcd.vars {
+            let v = self.signals[key].value;
+            vcd.body += &print_value(v, *width, id);
+            vcd.last.insert(key.clone(), v);
+        }
+        vcd.body += "$end\n";
+        self.vcd = Some(vcd);
+    }
+
+    /// 記録した VCD の取得
+    pub fn vcd(&self) -> Option<String> {
+        self.vcd.as_ref().map(|vcd| vcd.header.clone() + &vcd.body)
+    }
+
+    /// 記録した VCD のファイル出力
+    pub fn save_vcd(&self, path: &str) -> io::Result<()> {
+        match self.vcd() {
+            Some(st) => fs::write(path, st),
+            None => Err(io::Error::other("VCD tracing is not started")),
+        }
+    }
+
+    // 変化した信号の記録
+    pub(crate) fn sample(&mut self) {
+        let time = self.time;
+        let vcd = match self.vcd {
+            Some(ref mut vcd) => vcd,
+            None => return,
+        };
+        for (key, id, width) in &vcd.vars {
+            let v = self.signals[key].value;
+            if vcd.last.get(key) == Some(&v) {
+                continue;
+            }
+            if vcd.last_time != Some(time) {
+                vcd.body += &format!("#{}\n", time);
+                vcd.last_time = Some(time);
+            }
+            vcd.body += &print_value(v, *width, id);
+            vcd.last.insert(key.clone(), v);
+        }
+    }
+}
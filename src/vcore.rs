@@ -1,8 +1,13 @@
 ﻿#![allow(dead_code)]
 #![allow(non_snake_case)]
+use error::VerugentError;
+use generate::{GenItem, Generate};
+use literal::Literal;
+use memory::Memory;
 use std::ops::*;
 use std::string::String;
 use std::*;
+use width::{clog2_of, Width};
 
 #[macro_export]
 macro_rules! F {
@@ -43,18 +48,29 @@ macro_rules! F {
     };
 }
 
+/// コード生成の出力言語
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Verilog,
+    SystemVerilog,
+    Vhdl,
+}
+
 #[derive(Clone, Debug)]
 pub struct VModule {
-    name: String,
-    io_port: Vec<WireVar>,
-    io_param: Vec<WireVar>,
-    local_param: Vec<WireVar>,
-    always: Vec<Always>,
-    assign: Vec<Assign>,
-    func: Vec<Func>,
-    fsm: Vec<FsmModule>,
-    axi: Vec<Bus>,
-    inline: String,
+    pub(crate) name: String,
+    pub(crate) io_port: Vec<WireVar>,
+    pub(crate) io_param: Vec<WireVar>,
+    pub(crate) local_param: Vec<WireVar>,
+    pub(crate) always: Vec<Always>,
+    pub(crate) assign: Vec<Assign>,
+    pub(crate) func: Vec<Func>,
+    pub(crate) fsm: Vec<FsmModule>,
+    pub(crate) axi: Vec<Bus>,
+    pub(crate) instance: Vec<Instance>,
+    pub(crate) generate: Vec<Generate>,
+    pub(crate) memory: Vec<Memory>,
+    pub(crate) inline: String,
 }
 
 pub trait VSet<T> {
@@ -75,9 +91,7 @@ where
         let width = *width.into();
         let len = if let E::Num(i) = width { i } else { 0 };
         tmp.input(name, len);
-        if let E::Ldc(wr) = width {
-            tmp.width(&(&wr.width_p));
-        };
+        tmp.width_from(width);
         self.io_port.push(tmp.clone());
         return _V(tmp);
     }
@@ -87,9 +101,7 @@ where
         let width = *width.into();
         let len = if let E::Num(i) = width { i } else { 0 };
         tmp.inout(name, len);
-        if let E::Ldc(wr) = width {
-            tmp.width(&(&wr.width_p));
-        };
+        tmp.width_from(width);
         self.io_port.push(tmp.clone());
         return _V(tmp);
     }
@@ -99,9 +111,7 @@ where
         let width = *width.into();
         let len = if let E::Num(i) = width { i } else { 0 };
         tmp.output(name, len);
-        if let E::Ldc(wr) = width {
-            tmp.width(&(&wr.width_p));
-        };
+        tmp.width_from(width);
         self.io_port.push(tmp.clone());
         return _V(tmp);
     }
@@ -111,9 +121,7 @@ where
         let width = *Width.into();
         let len = if let E::Num(i) = width { i } else { 0 };
         tmp.output_reg(name, len);
-        if let E::Ldc(wr) = width {
-            tmp.width(&(&wr.width_p));
-        };
+        tmp.width_from(width);
         self.io_port.push(tmp.clone());
         return _V(tmp);
     }
@@ -123,9 +131,7 @@ where
         let width = *Width.into();
         let len = if let E::Num(i) = width { i } else { 0 };
         tmp.wire(name, len);
-        if let E::Ldc(wr) = width {
-            tmp.width(&(&wr.width_p));
-        };
+        tmp.width_from(width);
         self.local_param.push(tmp.clone());
         return _V(tmp);
     }
@@ -135,15 +141,64 @@ where
         let width = *width.into();
         let len = if let E::Num(i) = width { i } else { 0 };
         tmp.reg(name, len);
-        if let E::Ldc(wr) = width {
-            tmp.width(&(&wr.width_p));
-        };
+        tmp.width_from(width);
         self.local_param.push(tmp.clone());
         return _V(tmp);
     }
 }
 
+/// 符号付きの幅指定(input("A", Signed(8)) で input signed [7:0] A)
+pub struct Signed<T>(pub T);
+
+impl<T> VSet<Signed<T>> for VModule
+where
+    T: Into<Box<E>>,
+{
+    fn input(&mut self, name: &str, width: Signed<T>) -> Box<E> {
+        let v = self.input(name, width.0);
+        self.set_signed(v)
+    }
+
+    fn inout(&mut self, name: &str, width: Signed<T>) -> Box<E> {
+        let v = self.inout(name, width.0);
+        self.set_signed(v)
+    }
+
+    fn output(&mut self, name: &str, width: Signed<T>) -> Box<E> {
+        let v = self.output(name, width.0);
+        self.set_signed(v)
+    }
+
+    fn reg_out(&mut self, name: &str, width: Signed<T>) -> Box<E> {
+        let v = self.reg_out(name, width.0);
+        self.set_signed(v)
+    }
+
+    fn wire(&mut self, name: &str, width: Signed<T>) -> Box<E> {
+        let v = self.wire(name, width.0);
+        self.set_signed(v)
+    }
+
+    fn reg(&mut self, name: &str, width: Signed<T>) -> Box<E> {
+        let v = self.reg(name, width.0);
+        self.set_signed(v)
+    }
+}
+
 impl VModule {
+    // 宣言済みの信号を符号付きにする
+    fn set_signed(&mut self, v: Box<E>) -> Box<E> {
+        let name = _StrOut(v);
+        let wr = self
+            .io_port
+            .iter_mut()
+            .chain(self.local_param.iter_mut())
+            .find(|x| x.name == name)
+            .unwrap();
+        wr.signed = true;
+        _V(wr.clone())
+    }
+
     /// モジュールの生成
     pub fn new(Name: &str) -> VModule {
         VModule {
@@ -156,22 +211,27 @@ impl VModule {
             func: Vec::new(),
             fsm: Vec::new(),
             axi: Vec::new(),
+            instance: Vec::new(),
+            generate: Vec::new(),
+            memory: Vec::new(),
             inline: String::new(),
         }
     }
 
     /// パラメータの追加
-    pub fn add_io_param(&mut self, name: &str, value: i32) -> Box<E> {
+    pub fn add_io_param<T: Into<Box<E>>>(&mut self, name: &str, value: T) -> Box<E> {
         let mut tmp = WireVar::new();
-        tmp.parameter(name, value);
+        tmp.parameter(name, 0);
+        tmp.value = value.into();
         self.io_param.push(tmp.clone());
         return _V(tmp);
     }
 
     /// ローカルパラメータの追加
-    pub fn add_local_param(&mut self, name: &str, Value: i32) -> Box<E> {
+    pub fn add_local_param<T: Into<Box<E>>>(&mut self, name: &str, Value: T) -> Box<E> {
         let mut tmp = WireVar::new();
-        tmp.parameter(name, Value);
+        tmp.parameter(name, 0);
+        tmp.value = Value.into();
         self.local_param.push(tmp.clone());
         return _V(tmp);
     }
@@ -188,13 +248,197 @@ impl VModule {
 
     /// モジュールの AST 解析と Verilog 構文の出力
     pub fn gen(&self) -> String {
+        self.gen_with(Backend::Verilog)
+    }
+
+    /// 出力言語を指定したコード生成
+    /// (VHDL で表せない構文は出力されずコメントで示されるので、try_gen_with() で確認する)
+    pub fn gen_with(&self, backend: Backend) -> String {
+        // インスタンス接続のエラーは try_gen() で報告する
+        match backend {
+            Backend::Verilog => self.emit(),
+            Backend::SystemVerilog => self.emit_sv(),
+            Backend::Vhdl => {
+                let (st, errs) = self.emit_vhdl();
+                errs.iter()
+                    .map(|e| format!("-- error: {}\n", e))
+                    .collect::<String>()
+                    + &st
+            }
+        }
+    }
+
+    /// 生成前の検査を行い、問題があれば全てのエラーを返す
+    pub fn try_gen(&self) -> Result<String, Vec<VerugentError>> {
+        self.try_gen_with(Backend::Verilog)
+    }
+
+    /// 出力言語を指定した try_gen()(出力言語で表せない構文もエラーとして返す)
+    pub fn try_gen_with(&self, backend: Backend) -> Result<String, Vec<VerugentError>> {
+        let mut errs = self.validate();
+        let st = match backend {
+            Backend::Verilog if errs.is_empty() => self.emit(),
+            Backend::SystemVerilog if errs.is_empty() => self.emit_sv(),
+            // VHDL で表せない構文は出力時に検出する
+            Backend::Vhdl => {
+                let (st, list) = self.emit_vhdl();
+                for e in list {
+                    if !errs.iter().any(|x| x.to_string() == e.to_string()) {
+                        errs.push(e);
+                    }
+                }
+                st
+            }
+            _ => String::new(),
+        };
+        if errs.is_empty() {
+            Ok(st)
+        } else {
+            Err(errs)
+        }
+    }
+
+    /// 生成前の検査
+    pub fn validate(&self) -> Vec<VerugentError> {
+        if !self.generate.is_empty() {
+            return self.unroll_generate().validate();
+        }
+        let mut errs = Vec::new();
+        for port in self.io_port.iter().filter(|p| !p.is_port()) {
+            errs.push(VerugentError::InvalidPort {
+                module: self.name.clone(),
+                signal: port.name.clone(),
+            });
+        }
+        for a in &self.assign {
+            let context = format!("assign {}", decomp_ast(a.lhs.clone(), "", 0));
+            if matches!(*a.lhs, E::Null) || matches!(*a.rhs, E::Null) {
+                errs.push(VerugentError::NullExpression {
+                    module: self.name.clone(),
+                    context: context.clone(),
+                });
+            }
+            self.validate_expr(&a.lhs, &context, &mut errs);
+            self.validate_expr(&a.rhs, &context, &mut errs);
+        }
+        for al in &self.always {
+            for s in &al.stmt {
+                self.validate_expr(s, "always block", &mut errs);
+            }
+        }
+        for f in &self.func {
+            let context = format!("function {}", _StrOut(f.top.clone()));
+            for s in &f.stmt {
+                self.validate_expr(s, &context, &mut errs);
+            }
+        }
+        for fsm in &self.fsm {
+            let state_reg = _StrOut(fsm.state_reg.clone());
+            let mut msgs = fsm.errors.clone();
+            if fsm.states.is_empty() {
+                msgs.push("no state added".to_string());
+            }
+            for m in msgs {
+                errs.push(VerugentError::Fsm {
+                    module: self.name.clone(),
+                    state_reg: state_reg.clone(),
+                    message: m,
+                });
+            }
+            for s in &fsm.states {
+                for b in &s.branch {
+                    self.validate_expr(&b.cond, &state_reg, &mut errs);
+                    for x in &b.stmt {
+                        self.validate_expr(x, &state_reg, &mut errs);
+                    }
+                }
+            }
+        }
+        for mem in &self.memory {
+            for message in mem.check(self) {
+                errs.push(VerugentError::Memory {
+                    module: self.name.clone(),
+                    memory: mem.name.clone(),
+                    message,
+                });
+            }
+        }
+        for bus in &self.axi {
+            let name = match *bus {
+                Bus::AxiMaster(_) => "AXI master",
+                Bus::AxiStream(_) => "AXI stream",
+                _ => continue,
+            };
+            errs.push(VerugentError::UnsupportedBus {
+                module: self.name.clone(),
+                bus: name.to_string(),
+            });
+        }
+        for inst in &self.instance {
+            errs.extend(inst.verify(self));
+        }
+        errs
+    }
+
+    // 式中の未知の演算子と空の式の検査
+    fn validate_expr(&self, e: &E, context: &str, errs: &mut Vec<VerugentError>) {
+        let mut operands: Vec<&Box<E>> = Vec::new();
+        match *e {
+            E::Bin(_, ref l, ref r) => operands.extend(vec![l, r]),
+            E::Red(_, ref a) | E::Un(_, ref a) => operands.push(a),
+            E::PL(ref d, ref t, ref f) => operands.extend(vec![d, t, f]),
+            E::SB(ref l, ref r) | E::SBX(_, ref l, ref r) | E::MEM(ref l, ref r) => {
+                operands.extend(vec![l, r])
+            }
+            E::MBT(ref m, ref h, ref l) => operands.extend(vec![m, h, l]),
+            E::Func(_, ref args) | E::Cat(ref args) => operands.extend(args.iter()),
+            E::Rep(ref n, ref x) => operands.extend(vec![n, x]),
+            E::Signed(ref a) | E::Unsigned(ref a) | E::Clog2(ref a) => operands.push(a),
+            E::BL(ref list) => {
+                for x in list {
+                    self.validate_expr(&x.cond, context, errs);
+                    for s in &x.stmt {
+                        self.validate_expr(s, context, errs);
+                    }
+                }
+            }
+            E::CS(ref c) => {
+                for x in &c.Select {
+                    self.validate_expr(&x.CaseT, context, errs);
+                    for s in &x.CaseS {
+                        self.validate_expr(s, context, errs);
+                    }
+                }
+            }
+            _ => {}
+        }
+        if operands.iter().any(|x| matches!(***x, E::Null)) {
+            errs.push(VerugentError::NullExpression {
+                module: self.name.clone(),
+                context: context.to_string(),
+            });
+        }
+        for x in operands {
+            self.validate_expr(x, context, errs);
+        }
+    }
+
+    // Verilog 構文の出力
+    fn emit(&self) -> String {
         let mut st = String::new();
         st += &format!("module {} ", self.name);
         st += &WireVar::print_params(&self.io_param);
         st += &WireVar::print_ports(&self.io_port);
-        st += &WireVar::print_local_params(&self.local_param);
+        st += &self
+            .local_param
+            .iter()
+            .map(|wr| format!("    {}{}\n", self.fsm_attr(wr), wr.print_as_local_param()))
+            .collect::<String>();
+        st += &Memory::print_init_list(&self.memory);
         st += &Assign::print_list(&self.assign);
+        st += &Instance::print_list(&self.instance);
         st += &Always::print_list(&self.always);
+        st += &Generate::print_list(&self.generate, &GenItem::print);
         st += &Func::print_list(&self.func);
 
         if self.fsm.len() != 0 || self.axi.len() != 0 || self.inline.len() != 0 {
@@ -219,12 +463,58 @@ impl VModule {
         return st;
     }
 
+    // FSM のステートレジスタに付ける符号化方式の属性
+    pub(crate) fn fsm_attr(&self, wr: &WireVar) -> String {
+        self.fsm
+            .iter()
+            .find(|f| _StrOut(f.state_reg.clone()) == wr.name)
+            .and_then(|f| f.encoding_attr())
+            .map_or(String::new(), |a| {
+                format!("(* fsm_encoding = \"{}\" *) ", a)
+            })
+    }
+
+    /// サブモジュールのインスタンス化
+    pub fn instance(&mut self, module: &VModule, name: &str) -> &mut Instance {
+        if self.instance.iter().any(|inst| inst.name == name) {
+            panic!("Some name instance exist. :{}\n", name)
+        }
+        self.instance.push(Instance::new(module, name));
+        self.instance.last_mut().unwrap()
+    }
+
     /// Inline verilog
     pub fn inline(&mut self, code: &str) {
         self.inline += code;
         self.inline += "\n\n";
     }
 
+    // パラメータ値の取得(他のパラメータを含む式は評価する)
+    pub(crate) fn param_value(&self, name: &str) -> Option<i32> {
+        self.io_param
+            .iter()
+            .chain(self.local_param.iter())
+            .find(|p| p.name == name)
+            .and_then(|p| self.const_eval(&p.value))
+            .map(|v| v as i32)
+    }
+
+    // 信号のビット幅(パラメータはデフォルト値で評価)
+    pub(crate) fn resolve_width(&self, wr: &WireVar) -> Option<i32> {
+        match wr.width_p {
+            None => Some(cmp::max(wr.width, 1)),
+            Some(ref e) => self.const_eval(e).map(|v| v as i32),
+        }
+    }
+
+    // メモリの配列長(パラメータはデフォルト値で評価)
+    pub(crate) fn resolve_length(&self, wr: &WireVar) -> Option<i32> {
+        match wr.length_p {
+            None => Some(wr.length),
+            Some(ref e) => self.const_eval(e).map(|v| v as i32),
+        }
+    }
+
     pub fn out_func_name(&mut self) -> Vec<String> {
         let mut st = Vec::new();
         let tmp = self.func.clone();
@@ -269,47 +559,7 @@ pub trait FSMTrait<T> {
 
 impl FSMTrait<FsmModule> for VModule {
     fn fsm(&mut self, fsm: FsmModule) -> Box<E> {
-        let self_fsm = self.fsm.clone();
-        for n in self_fsm {
-            if _StrOut(n.clone().state_reg) == _StrOut(fsm.clone().state_reg) {
-                panic!("Some name FSM exist. :{}\n", _StrOut(fsm.clone().state_reg))
-            }
-        }
-
-        let tmp = fsm.clone();
-        let mut stmt = fsm.StateOut();
-        let state = *(tmp.clone().StateReg());
-        let p;
-        let mut np = WireVar::new();
-        let mut n = 0;
-        for ss in &mut stmt {
-            self.local_param.push(WireVar {
-                name: ss.getStateName(),
-                io_param: IOType::Param,
-                width: 0,
-                length: 0,
-                reg_set: false,
-                value: n,
-                width_p: "_".to_string(),
-                length_p: "_".to_string(),
-            });
-            n += 1;
-        }
-
-        if let E::Ldc(x) = state {
-            p = x.clone();
-            let nam = p.name.clone() + "_Next";
-            if let E::Ldc(wr) = *WireVar::new().reg(&nam, 32) {
-                np = wr;
-            }
-        } else {
-            return Box::new(E::Null);
-        }
-        self.local_param.push(p);
-        self.local_param.push(np);
-        self.fsm.push(tmp.clone());
-
-        return tmp.StateReg();
+        self.fsm(&fsm)
     }
 }
 
@@ -321,39 +571,43 @@ impl FSMTrait<&FsmModule> for VModule {
                 panic!("Some name FSM exist. :{}\n", _StrOut(fsm.clone().state_reg))
             }
         }
-
-        let tmp = fsm.clone();
-        let retE = fsm.clone().StateReg();
-        let mut stmt = fsm.clone().StateOut();
-        let state = *(tmp.clone().StateReg());
-        let p;
-        let mut np = WireVar::new();
-        let mut n = 0;
-        for ss in &mut stmt {
+        let mut tmp = fsm.clone();
+        tmp.errors.extend(fsm.code_errors());
+        let width = fsm.state_width();
+        for (ss, code) in tmp.states.iter_mut().zip(fsm.codes()) {
+            // i32 に収まらない one-hot 符号は幅付きのリテラルにする
+            let value = if code <= i32::MAX as u64 {
+                _Num(code as i32)
+            } else {
+                Box::new(E::Lit(Literal::new(width as usize, u128::from(code))))
+            };
+            if let E::Ldc(ref mut wr) = *ss.state {
+                wr.value = value.clone();
+            }
             self.local_param.push(WireVar {
                 name: ss.getStateName(),
                 io_param: IOType::Param,
                 width: 0,
                 length: 0,
                 reg_set: false,
-                value: n,
-                width_p: "_".to_string(),
-                length_p: "_".to_string(),
+                value,
+                width_p: None,
+                length_p: None,
+                signed: false,
             });
-            n += 1;
         }
 
-        if let E::Ldc(x) = state {
-            p = x.clone();
-            let nam = p.name.clone() + "_Next";
-            if let E::Ldc(wr) = *WireVar::new().reg(&nam, 32) {
-                np = wr;
+        let name = _StrOut(fsm.state_reg.clone());
+        tmp.state_reg = WireVar::new().reg(&name, width);
+        for reg in [
+            tmp.state_reg.clone(),
+            WireVar::new().reg(&(name + "_Next"), width),
+        ] {
+            if let E::Ldc(wr) = *reg {
+                self.local_param.push(wr);
             }
-        } else {
-            return Box::new(E::Null);
         }
-        self.local_param.push(p);
-        self.local_param.push(np);
+        let retE = tmp.state_reg.clone();
         self.fsm.push(tmp);
 
         return retE;
@@ -590,11 +844,13 @@ where
     fn Mem(&mut self, name: &str, args: (T, U)) -> Box<E> {
         let mut tmp = WireVar::new();
         tmp.mem(name, 0, 0);
-        if let E::Ldc(wr) = *args.0.into() {
-            tmp.width(&(wr.name));
+        match *args.0.into() {
+            E::Num(i) => tmp.width = i,
+            e => tmp.width_from(e),
         };
-        if let E::Ldc(wr) = *args.1.into() {
-            tmp.length(&(wr.name));
+        match *args.1.into() {
+            E::Num(i) => tmp.length = i,
+            e => tmp.length_p = Some(Box::new(e)),
         };
         self.local_param.push(tmp.clone());
         return _V(tmp);
@@ -612,6 +868,7 @@ pub enum IOType {
     Output,
     InOut,
     Param,
+    Genvar,
     None,
 }
 
@@ -619,14 +876,15 @@ pub enum IOType {
 
 #[derive(Clone, Debug)]
 pub struct WireVar {
-    name: String,
-    io_param: IOType,
-    width: i32,
-    length: i32,
-    reg_set: bool,
-    value: i32,
-    width_p: String,
-    length_p: String,
+    pub(crate) name: String,
+    pub(crate) io_param: IOType,
+    pub(crate) width: i32,
+    pub(crate) length: i32,
+    pub(crate) reg_set: bool,
+    pub(crate) value: Box<E>,
+    pub(crate) width_p: Option<Box<E>>,
+    pub(crate) length_p: Option<Box<E>>,
+    pub(crate) signed: bool,
 }
 
 /**
@@ -643,24 +901,37 @@ impl WireVar {
             width: 0,
             length: 0,
             reg_set: false,
-            value: 0,
-            width_p: "_".to_string(),
-            length_p: "_".to_string(),
+            value: _Num(0),
+            width_p: None,
+            length_p: None,
+            signed: false,
         }
     }
 
     /// パラメータによる長さ設定メソッド
     pub fn length(&mut self, S: &str) -> WireVar {
-        self.length_p = S.to_string();
+        self.length_p = Some(WireVar::new().parameter(S, 0));
         self.clone()
     }
 
     /// パラメータによる幅設定メソッド
     pub fn width(&mut self, S: &str) -> WireVar {
-        self.width_p = S.to_string();
+        self.width_p = Some(WireVar::new().parameter(S, 0));
         self.clone()
     }
 
+    /// 他の変数・パラメータ・定数式からの幅設定メソッド
+    fn width_from(&mut self, width: E) {
+        match width {
+            E::Num(_) => {}
+            E::Ldc(ref wr) if !matches!(wr.io_param, IOType::Param) => {
+                self.width = wr.width;
+                self.width_p = wr.width_p.clone();
+            }
+            e => self.width_p = Some(Box::new(e)),
+        }
+    }
+
     /// パラメータ設定メソッド:input
     pub fn input(&mut self, Name: &str, Width: i32) -> Box<E> {
         self.name = Name.to_string();
@@ -701,7 +972,7 @@ impl WireVar {
     /// パラメータ設定メソッド:parameter
     pub fn parameter(&mut self, Name: &str, Value: i32) -> Box<E> {
         self.name = Name.to_string();
-        self.value = Value;
+        self.value = _Num(Value);
 
         self.io_param = IOType::Param;
         _V(self.clone())
@@ -735,7 +1006,11 @@ impl WireVar {
     }
 
     pub fn print_as_param(&self) -> String {
-        format!("parameter {} = {}", self.name, self.value)
+        format!(
+            "parameter {} = {}",
+            self.name,
+            decomp_ast(self.value.clone(), "", 0)
+        )
     }
 
     pub fn print_params(params: &[WireVar]) -> String {
@@ -750,21 +1025,49 @@ impl WireVar {
         format!("#(\n{}\n)", param_list)
     }
 
-    pub fn print_as_port(&self) -> String {
-        let range = format!("[{}-1:0]", self.width);
-        let array = format!("[{}-1:0]", self.length);
-        match self.io_param {
-            IOType::Input => format!("input  {} {} {}", range, self.name, array),
-            IOType::Output => format!("output {} {} {}", range, self.name, array),
-            IOType::InOut => format!("inout {} {} {}", range, self.name, array),
-            IOType::Param => panic!(),
-            IOType::None => panic!(),
+    // 符号とビット幅指定の出力(1bit の場合は幅を省略)
+    pub(crate) fn print_range(&self) -> String {
+        let sign = if self.signed { "signed " } else { "" };
+        if let Some(ref w) = self.width_p {
+            format!("{}[{}:0] ", sign, minus_one(w))
+        } else if self.width > 1 {
+            format!("{}[{}:0] ", sign, self.width - 1)
+        } else {
+            sign.to_string()
         }
     }
 
+    // メモリ配列長指定の出力
+    pub(crate) fn print_array(&self) -> String {
+        if let Some(ref l) = self.length_p {
+            format!(" [0:{}]", minus_one(l))
+        } else if self.length > 0 {
+            format!(" [0:{}]", self.length - 1)
+        } else {
+            String::new()
+        }
+    }
+
+    // 入出力の種別か(それ以外は validate() でエラーとして報告し、出力しない)
+    pub(crate) fn is_port(&self) -> bool {
+        matches!(self.io_param, IOType::Input | IOType::Output | IOType::InOut)
+    }
+
+    pub fn print_as_port(&self) -> String {
+        let kind = match self.io_param {
+            IOType::Input => "input  ",
+            IOType::Output if self.reg_set => "output reg ",
+            IOType::Output => "output ",
+            IOType::InOut => "inout  ",
+            IOType::Param | IOType::Genvar | IOType::None => return String::new(),
+        };
+        format!("{}{}{}", kind, self.print_range(), self.name)
+    }
+
     pub fn print_ports(ports: &[WireVar]) -> String {
+        let ports = ports.iter().filter(|p| p.is_port()).collect::<Vec<_>>();
         if ports.len() == 0 {
-            return "".to_string();
+            return "();\n".to_string();
         }
         let param_list = ports
             .iter()
@@ -774,8 +1077,23 @@ impl WireVar {
         format!("(\n{}\n);\n", param_list)
     }
 
+    /// 変数の種類(localparam, wire, reg, メモリ)に応じた宣言の出力
     pub fn print_as_local_param(&self) -> String {
-        format!("parameter {} = {};", self.name, self.value)
+        match self.io_param {
+            IOType::Param => format!(
+                "localparam {} = {};",
+                self.name,
+                decomp_ast(self.value.clone(), "", 0)
+            ),
+            IOType::Genvar => format!("genvar {};", self.name),
+            _ if self.reg_set => format!(
+                "reg {}{}{};",
+                self.print_range(),
+                self.name,
+                self.print_array()
+            ),
+            _ => format!("wire {}{};", self.print_range(), self.name),
+        }
     }
 
     pub fn print_local_params(local_params: &[WireVar]) -> String {
@@ -819,8 +1137,8 @@ where
 
 #[derive(Clone, Debug)]
 pub struct Assign {
-    lhs: Box<E>,
-    rhs: Box<E>,
+    pub(crate) lhs: Box<E>,
+    pub(crate) rhs: Box<E>,
 }
 
 impl Assign {
@@ -841,11 +1159,11 @@ impl Assign {
         self.clone()
     }
 
-    fn print(&self) -> String {
+    pub(crate) fn print(&self) -> String {
         format!(
             "assign {} = {};",
-            &decomp_ast(false, self.lhs.clone(), "", 0),
-            &decomp_ast(false, self.rhs.clone(), "", 0)
+            &decomp_ast(self.lhs.clone(), "", 0),
+            &decomp_ast(self.rhs.clone(), "", 0)
         )
     }
 
@@ -860,12 +1178,157 @@ impl Assign {
     }
 }
 
+/// サブモジュールのインスタンス
+#[derive(Clone, Debug)]
+pub struct Instance {
+    pub(crate) module: String,
+    pub(crate) name: String,
+    pub(crate) ports: Vec<WireVar>,
+    pub(crate) params: Vec<WireVar>,
+    pub(crate) port_map: Vec<(String, Box<E>)>,
+    pub(crate) param_map: Vec<(String, Box<E>)>,
+    pub(crate) sub: Box<VModule>,
+}
+
+impl Instance {
+    pub(crate) fn new(module: &VModule, name: &str) -> Instance {
+        Instance {
+            module: module.name.clone(),
+            name: name.to_string(),
+            ports: module.io_port.clone(),
+            params: module.io_param.clone(),
+            port_map: Vec::new(),
+            param_map: Vec::new(),
+            sub: Box::new(module.clone()),
+        }
+    }
+
+    /// ポートの接続
+    pub fn port<T: Into<Box<E>>>(&mut self, name: &str, signal: T) -> &mut Instance {
+        self.port_map.push((name.to_string(), signal.into()));
+        self
+    }
+
+    /// パラメータの上書き
+    pub fn param<T: Into<Box<E>>>(&mut self, name: &str, value: T) -> &mut Instance {
+        self.param_map.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// 接続の検査(存在しないポート・パラメータ、ビット幅の不一致)
+    fn verify(&self, parent: &VModule) -> Vec<VerugentError> {
+        let mut msgs = Vec::new();
+        for (i, (pname, _)) in self.param_map.iter().enumerate() {
+            if !self.params.iter().any(|p| &p.name == pname) {
+                msgs.push(format!("module {} has no parameter {}", self.module, pname));
+            }
+            if self.param_map[..i].iter().any(|p| &p.0 == pname) {
+                msgs.push(format!("parameter {} overridden twice", pname));
+            }
+        }
+        for (i, (pname, sig)) in self.port_map.iter().enumerate() {
+            if self.port_map[..i].iter().any(|p| &p.0 == pname) {
+                msgs.push(format!("port {} connected twice", pname));
+            }
+            let port = match self.ports.iter().find(|p| &p.name == pname) {
+                Some(port) => port,
+                None => {
+                    msgs.push(format!("module {} has no port {}", self.module, pname));
+                    continue;
+                }
+            };
+            if let E::Null = **sig {
+                msgs.push(format!("port {} connected to an empty expression", pname));
+                continue;
+            }
+            let sig_width = parent.infer_width(sig);
+            if let (Some(pw), Some(sw)) = (self.port_width(port, parent), sig_width) {
+                let mismatch = match sw {
+                    Width::Sized(w) => w != pw,
+                    Width::Unsized(w) => w > pw,
+                };
+                if mismatch {
+                    msgs.push(format!(
+                        "port {} is {} bit but {} is {} bit",
+                        pname,
+                        pw,
+                        decomp_ast(sig.clone(), "", 0),
+                        sw.bits()
+                    ));
+                }
+            }
+        }
+        msgs.into_iter()
+            .map(|message| VerugentError::Instance {
+                module: parent.name.clone(),
+                instance: self.name.clone(),
+                message,
+            })
+            .collect()
+    }
+
+    // パラメータ上書きを反映したポート幅
+    fn port_width(&self, port: &WireVar, parent: &VModule) -> Option<i32> {
+        self.overridden(parent).resolve_width(port)
+    }
+
+    // パラメータ上書きを反映したサブモジュール(定数でない上書き値は評価できない値とする)
+    pub(crate) fn overridden(&self, parent: &VModule) -> VModule {
+        let mut sub = (*self.sub).clone();
+        for (name, value) in &self.param_map {
+            let v = match parent.const_eval(value) {
+                Some(v) => _Num(v as i32),
+                None => Box::new(E::Null),
+            };
+            for p in sub.io_param.iter_mut().filter(|p| p.name == *name) {
+                p.value = v.clone();
+            }
+        }
+        sub
+    }
+
+    pub(crate) fn print(&self) -> String {
+        let mut st = format!("    {} ", self.module);
+        if !self.param_map.is_empty() {
+            let list = self
+                .param_map
+                .iter()
+                .map(|(n, v)| format!("        .{}({})", n, decomp_ast(v.clone(), "", 0)))
+                .collect::<Vec<_>>()
+                .join(",\n");
+            st += &format!("#(\n{}\n    ) ", list);
+        }
+        let list = self
+            .port_map
+            .iter()
+            .map(|(n, s)| format!("        .{}({})", n, decomp_ast(s.clone(), "", 0)))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        st += &format!("{} (\n{}\n    );\n", self.name, list);
+        st
+    }
+
+    pub fn print_list(list: &[Instance]) -> String {
+        list.iter()
+            .map(|inst| inst.print())
+            .collect::<Vec<_>>()
+            .join("")
+    }
+}
+
+/// 代入方法(ブロッキング代入 = / ノンブロッキング代入 <=)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subst {
+    Blocking,
+    NonBlocking,
+}
+
 #[derive(Clone, Debug)]
 pub struct Always {
-    name: String,
-    stmt: Vec<Box<E>>,
-    posedges: Vec<WireVar>,
-    negedges: Vec<WireVar>,
+    pub(crate) name: String,
+    pub(crate) stmt: Vec<Box<E>>,
+    pub(crate) posedges: Vec<WireVar>,
+    pub(crate) negedges: Vec<WireVar>,
 }
 
 pub fn posedge<T: Into<Box<E>>>(edge: T) -> Always {
@@ -911,19 +1374,17 @@ impl Always {
     pub fn print(&self) -> String {
         let pos = self.posedges.iter().map(|p| format!("posedge {}", p.name));
         let neg = self.negedges.iter().map(|n| format!("negedge {}", n.name));
-        let list = pos.chain(neg).collect::<Vec<_>>().join(" or ");
+        let mut list = pos.chain(neg).collect::<Vec<_>>().join(" or ");
+        if list.is_empty() {
+            list = "*".to_string();
+        }
         let body = self
             .stmt
             .iter()
-            .map(|stmt| decomp_ast(false, stmt.clone(), &self.clone().blockout(), 2))
+            .map(|stmt| decomp_ast(stmt.clone(), &self.clone().blockout(), 2))
             .collect::<Vec<_>>()
             .join("");
-        vec![
-            format!("    always @({}) begin", list),
-            body,
-            format!("    end"),
-        ]
-        .join("\n")
+        format!("    always @({}) begin\n{}    end\n", list, body)
     }
 
     pub fn print_list(list: &[Self]) -> String {
@@ -933,7 +1394,7 @@ impl Always {
         list.iter()
             .map(|always| always.print())
             .collect::<Vec<_>>()
-            .join("")
+            .join("\n")
     }
 }
 
@@ -962,6 +1423,15 @@ impl Always {
         self.clone()
     }
 
+    // ブロック内の代入方法
+    pub(crate) fn subst(&self) -> Subst {
+        if self.name == "block" {
+            Subst::Blocking
+        } else {
+            Subst::NonBlocking
+        }
+    }
+
     pub fn posedge<T: Into<Box<E>>>(&mut self, edge: T) -> Always {
         let e = *edge.into();
         match e {
@@ -1083,9 +1553,9 @@ impl Always {
 
 #[derive(Clone, Debug)]
 pub struct Func {
-    top: Box<E>,
-    input: Vec<Box<E>>,
-    stmt: Vec<Box<E>>,
+    pub(crate) top: Box<E>,
+    pub(crate) input: Vec<Box<E>>,
+    pub(crate) stmt: Vec<Box<E>>,
 }
 
 impl Func {
@@ -1101,20 +1571,15 @@ impl Func {
         let mut st: String = String::new();
         let e = self.top.clone();
         if let E::Ldc(wrtop) = (*e).clone() {
-            st += &format!("\n    function [{}:0] ", wrtop.width - 1);
-            st += &decomp_ast(false, e, "", 1);
+            st += &format!("\n    function {}", wrtop.print_range());
+            st += &decomp_ast(e, "", 1);
         }
         st += "(\n";
         let mut i = 0;
         for inpt in self.input.clone() {
             if let E::Ldc(wr) = (*inpt).clone() {
-                if wr.width > 0 {
-                    st += &format!("        input [{}:0]", wr.width - 1);
-                    st += &decomp_ast(false, inpt, "", 2);
-                } else {
-                    st += "        input ";
-                    st += &decomp_ast(false, inpt, "", 2);
-                }
+                st += &format!("        input {}", wr.print_range());
+                st += &decomp_ast(inpt, "", 2);
                 i += 1;
                 if i != self.input.len() {
                     st += ",\n";
@@ -1122,8 +1587,16 @@ impl Func {
             }
         }
         st += "\n    );\n";
-        for s in self.stmt.clone() {
-            st += &decomp_ast(false, s, "block", 2);
+        if self.stmt.len() > 1 {
+            st += "        begin\n";
+            for s in self.stmt.clone() {
+                st += &decomp_ast(s, "block", 3);
+            }
+            st += "        end\n";
+        } else {
+            for s in self.stmt.clone() {
+                st += &decomp_ast(s, "block", 2);
+            }
         }
         st += "    endfunction\n\n";
         st
@@ -1141,6 +1614,16 @@ impl Func {
     }
 }
 
+/// 連接 {a, b, ...} の生成
+#[macro_export]
+macro_rules! cat {
+    ( $($x: expr),* ) => (
+        {let temp_vec: Vec<Box<$crate::vcore::E>> = vec![$($x.clone().into()),*];
+        $crate::vcore::concat(temp_vec)
+        }
+    )
+}
+
 #[macro_export]
 macro_rules! func_args {
     ( $($x: expr),* ) => (
@@ -1269,9 +1752,9 @@ impl Func {
 
 #[derive(Clone, Debug)]
 pub struct IfElseAST {
-    if_: bool,         // if文フラグ
-    cond: Box<E>,      // if文条件式
-    stmt: Vec<Box<E>>, // 実行式
+    pub(crate) if_: bool,         // if文フラグ
+    pub(crate) cond: Box<E>,      // if文条件式
+    pub(crate) stmt: Vec<Box<E>>, // 実行式
 }
 
 impl IfElseAST {
@@ -1287,8 +1770,23 @@ impl IfElseAST {
 }
 
 impl IfElseAST {
+    // 条件無しの分岐までのリスト(以降の分岐には到達しないので出力しない)
+    pub(crate) fn reachable(list: &[IfElseAST]) -> &[IfElseAST] {
+        let end = list.iter().enumerate().position(|(i, x)| {
+            if i == 0 {
+                matches!(*x.cond, E::Null)
+            } else {
+                !x.if_
+            }
+        });
+        match end {
+            Some(n) => &list[..n + 1],
+            None => list,
+        }
+    }
+
     pub fn print_list(list: Vec<IfElseAST>, cnfg: &str, indent: i32) -> String {
-        let tmp = list;
+        let tmp = IfElseAST::reachable(&list).to_vec();
         let mut num = 0;
         let mut st = String::new();
 
@@ -1309,7 +1807,7 @@ impl IfElseAST {
                         }
                         st += "if(";
                         num += 1;
-                        st += &decomp_ast(false, x.getTerms(), "", 0);
+                        st += &decomp_ast(x.getTerms(), "", 0);
                         st += ") begin\n";
                     }
                 }
@@ -1318,7 +1816,7 @@ impl IfElseAST {
                     st += "    ";
                 }
                 st += "else if(";
-                st += &decomp_ast(false, x.getTerms(), "", 0);
+                st += &decomp_ast(x.getTerms(), "", 0);
                 st += ") begin\n";
             } else {
                 for _ in 0..indent {
@@ -1329,12 +1827,12 @@ impl IfElseAST {
 
             if nonBranch {
                 for y in n.clone() {
-                    st += &decomp_ast(false, y, cnfg, indent);
+                    st += &decomp_ast(y, cnfg, indent);
                 }
                 return st;
             }
             for y in n.clone() {
-                st += &decomp_ast(false, y, cnfg, indent + 1);
+                st += &decomp_ast(y, cnfg, indent + 1);
             }
 
             for _ in 0..indent {
@@ -1399,8 +1897,8 @@ impl Ifset for Box<E> {
 
 #[derive(Clone, Debug)]
 pub struct CaseStmt {
-    CaseVar: WireVar,
-    Select: Vec<Case_>,
+    pub(crate) CaseVar: WireVar,
+    pub(crate) Select: Vec<Case_>,
 }
 
 impl CaseStmt {
@@ -1443,22 +1941,23 @@ impl CaseStmt {
                     st += "default ";
                 }
                 _ => {
-                    st += &decomp_ast(false, e, cnfg, indent + 1);
+                    st += &decomp_ast(e, cnfg, indent + 1);
                 }
             }
             st += " :";
-            let n = ef.len();
-            if n > 1 {
+            // 入れ子の if/case は 1 文でも begin/end で囲む
+            let block = ef.len() > 1 || ef.iter().any(|y| matches!(**y, E::BL(_) | E::CS(_)));
+            if block {
                 st += "begin \n";
             }
             for y in ef {
-                if n > 1 {
-                    st += &decomp_ast(false, y, cnfg, indent + 2);
+                if block {
+                    st += &decomp_ast(y, cnfg, indent + 2);
                 } else {
-                    st += &decomp_ast(false, y, cnfg, 0);
+                    st += &decomp_ast(y, cnfg, 0);
                 }
             }
-            if n > 1 {
+            if block {
                 for _ in 0..indent + 1 {
                     st += "    ";
                 }
@@ -1557,16 +2056,153 @@ where
 
 // --------------------------------------------------------------------------------------------------------------------
 
+/// 二項演算子
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Or,
+    And,
+    Xor,
+    LOr,
+    LAnd,
+    Shl,
+    Shr,
+    Sra,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl BinOp {
+    /// Verilog の演算子記号
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Or => "|",
+            BinOp::And => "&",
+            BinOp::Xor => "^",
+            BinOp::LOr => "||",
+            BinOp::LAnd => "&&",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Sra => ">>>",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+        }
+    }
+
+    /// Verilog の演算子の優先順位(値が大きいほど強く結合する)
+    pub fn precedence(self) -> i32 {
+        match self {
+            BinOp::Mul | BinOp::Div | BinOp::Mod => 10,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Shl | BinOp::Shr | BinOp::Sra => 8,
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => 7,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::And => 5,
+            BinOp::Xor => 4,
+            BinOp::Or => 3,
+            BinOp::LAnd => 2,
+            BinOp::LOr => 1,
+        }
+    }
+
+    /// 比較演算子
+    pub fn is_compare(self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge
+        )
+    }
+
+    /// 算術演算子
+    pub fn is_arith(self) -> bool {
+        matches!(
+            self,
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod
+        )
+    }
+}
+
+/// 単項演算子
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    /// ビット反転 ~
+    Not,
+    /// 論理否定 !
+    LNot,
+    /// 符号反転 -
+    Neg,
+}
+
+impl UnaryOp {
+    /// Verilog の演算子記号
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Not => "~",
+            UnaryOp::LNot => "!",
+            UnaryOp::Neg => "-",
+        }
+    }
+}
+
+/// リダクション演算子
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReduceOp {
+    And,
+    Or,
+    Xor,
+    Nand,
+    Nor,
+    Xnor,
+}
+
+impl ReduceOp {
+    /// Verilog の演算子記号
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ReduceOp::And => "&",
+            ReduceOp::Or => "|",
+            ReduceOp::Xor => "^",
+            ReduceOp::Nand => "~&",
+            ReduceOp::Nor => "~|",
+            ReduceOp::Xnor => "~^",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum E {
     Null,
     Ldc(WireVar),                // 変数
     Num(i32),                    // 数値
-    No(Box<E>),                  // Not構文
-    Red(String, Box<E>),         // リダクション構文
-    Bin(String, Box<E>, Box<E>), // 二項演算
+    Lit(Literal),                // 幅・基数指定の数値
+    Un(UnaryOp, Box<E>),         // 単項演算
+    Red(ReduceOp, Box<E>),       // リダクション構文
+    Bin(BinOp, Box<E>, Box<E>),  // 二項演算
+    Cat(Vec<Box<E>>),            // 連接
+    Rep(Box<E>, Box<E>),         // 繰り返し
+    Signed(Box<E>),              // $signed
+    Unsigned(Box<E>),            // $unsigned
+    Clog2(Box<E>),               // $clog2
     PL(Box<E>, Box<E>, Box<E>),  // 分岐構文
     SB(Box<E>, Box<E>),          // 代入文
+    SBX(Subst, Box<E>, Box<E>),  // 代入方法を指定した代入文
     CS(CaseStmt),                // case文
     BL(Vec<IfElseAST>),          // if, else if, else文
     Func(Box<E>, Vec<Box<E>>),   // function文
@@ -1599,16 +2235,55 @@ impl From<&i32> for Box<E> {
     }
 }
 
+impl From<Literal> for Box<E> {
+    fn from(l: Literal) -> Self {
+        Box::new(E::Lit(l))
+    }
+}
+
+impl From<u64> for Box<E> {
+    fn from(v: u64) -> Self {
+        Box::new(E::Lit(Literal::from(v)))
+    }
+}
+
+impl From<u128> for Box<E> {
+    fn from(v: u128) -> Self {
+        Box::new(E::Lit(Literal::from(v)))
+    }
+}
+
+impl From<i64> for Box<E> {
+    fn from(v: i64) -> Self {
+        Box::new(E::Lit(Literal::from(v)))
+    }
+}
+
 // 変数出力関数
-fn _V(V: WireVar) -> Box<E> {
+pub(crate) fn _V(V: WireVar) -> Box<E> {
     Box::new(E::Ldc(V))
 }
 
+/// 定数式の $clog2(x)
+pub fn clog2<T: Into<Box<E>>>(x: T) -> Box<E> {
+    Box::new(E::Clog2(x.into()))
+}
+
 // 数値出力関数
 pub fn _Num(num: i32) -> Box<E> {
     Box::new(E::Num(num))
 }
 
+/// 連接 {a, b, ...}(先頭が上位ビット)
+pub fn concat(items: Vec<Box<E>>) -> Box<E> {
+    Box::new(E::Cat(items))
+}
+
+/// 繰り返し {n{x}}
+pub fn replicate<T: Into<Box<E>>, U: Into<Box<E>>>(n: T, x: U) -> Box<E> {
+    Box::new(E::Rep(n.into(), x.into()))
+}
+
 // 代入演算関数
 pub fn _Veq<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
     Box::new(E::SB(L.into(), R.into()))
@@ -1626,97 +2301,97 @@ pub fn _Branch<T: Into<Box<E>>, U: Into<Box<E>>, V: Into<Box<E>>>(
 // 演算子関数
 /// "+" addition
 fn _Add<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("add".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Add, L.into(), R.into()))
 }
 
 /// "-" substruction
 fn _Sub<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("sub".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Sub, L.into(), R.into()))
 }
 
 /// "*" multipication
 fn _Mul<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("mul".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Mul, L.into(), R.into()))
 }
 
 /// "/" division
 fn _Div<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("div".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Div, L.into(), R.into()))
 }
 
 /// "%" modulo
 fn _Mod<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("mod".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Mod, L.into(), R.into()))
 }
 
 /// "||" or
 fn _LOr<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("lor".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::LOr, L.into(), R.into()))
 }
 
 /// "&&" and
 fn _LAnd<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("land".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::LAnd, L.into(), R.into()))
 }
 
 /// "|" or
 fn _Or<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("or".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Or, L.into(), R.into()))
 }
 
 /// "&" and
 fn _And<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("and".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::And, L.into(), R.into()))
 }
 
 /// "^" exclusive or
 fn _Xor<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("xor".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Xor, L.into(), R.into()))
 }
 
 /// "==" equal
 pub fn _Eq<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("equal".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Eq, L.into(), R.into()))
 }
 
 /// "!=" not equal
 pub fn _Neq<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("Not equal".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Ne, L.into(), R.into()))
 }
 
 /// "<<" left shift
 fn _LSH<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("lshift".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Shl, L.into(), R.into()))
 }
 
 /// ">>" right shift
 fn _RSH<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("rshift".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Shr, L.into(), R.into()))
 }
 
 /// ">>>" right arithmetic shift
 pub fn _RSHA<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("rshifta".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Sra, L.into(), R.into()))
 }
 
-/// "<" more than
+/// "<" (右辺が左辺より大きい more than)
 fn _MTH<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("more_than".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Lt, L.into(), R.into()))
 }
 
-/// ">" less than
+/// ">" (右辺が左辺より小さい less than)
 fn _LTH<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("less_than".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Gt, L.into(), R.into()))
 }
 
-/// "<=" or more
+/// "<=" (右辺が左辺以上 or more)
 fn _OMR<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("or_more".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Le, L.into(), R.into()))
 }
 
-/// ">=" or less
+/// ">=" (右辺が左辺以下 or less)
 fn _OLS<T: Into<Box<E>>, U: Into<Box<E>>>(L: T, R: U) -> Box<E> {
-    Box::new(E::Bin("or_less".to_string(), L.into(), R.into()))
+    Box::new(E::Bin(BinOp::Ge, L.into(), R.into()))
 }
 
 /**
@@ -1729,7 +2404,67 @@ pub trait Notc {
 
 impl Notc for Box<E> {
     fn not(&self) -> Box<E> {
-        Box::new(E::No(self.clone()))
+        Box::new(E::Un(UnaryOp::Not, self.clone()))
+    }
+}
+
+// 符号の変換
+pub trait SignCast {
+    /// $signed(x)
+    fn signed(&self) -> Box<E>;
+    /// $unsigned(x)
+    fn unsigned(&self) -> Box<E>;
+}
+
+impl SignCast for Box<E> {
+    fn signed(&self) -> Box<E> {
+        Box::new(E::Signed(self.clone()))
+    }
+
+    fn unsigned(&self) -> Box<E> {
+        Box::new(E::Unsigned(self.clone()))
+    }
+}
+
+// リダクション演算子生成
+pub trait Reduce {
+    /// &x
+    fn red_and(&self) -> Box<E>;
+    /// |x
+    fn red_or(&self) -> Box<E>;
+    /// ^x
+    fn red_xor(&self) -> Box<E>;
+    /// ~&x
+    fn red_nand(&self) -> Box<E>;
+    /// ~|x
+    fn red_nor(&self) -> Box<E>;
+    /// ~^x
+    fn red_xnor(&self) -> Box<E>;
+}
+
+impl Reduce for Box<E> {
+    fn red_and(&self) -> Box<E> {
+        Box::new(E::Red(ReduceOp::And, self.clone()))
+    }
+
+    fn red_or(&self) -> Box<E> {
+        Box::new(E::Red(ReduceOp::Or, self.clone()))
+    }
+
+    fn red_xor(&self) -> Box<E> {
+        Box::new(E::Red(ReduceOp::Xor, self.clone()))
+    }
+
+    fn red_nand(&self) -> Box<E> {
+        Box::new(E::Red(ReduceOp::Nand, self.clone()))
+    }
+
+    fn red_nor(&self) -> Box<E> {
+        Box::new(E::Red(ReduceOp::Nor, self.clone()))
+    }
+
+    fn red_xnor(&self) -> Box<E> {
+        Box::new(E::Red(ReduceOp::Xnor, self.clone()))
     }
 }
 
@@ -1737,7 +2472,7 @@ impl Not for Box<E> {
     type Output = Box<E>;
 
     fn not(self) -> Box<E> {
-        Box::new(E::No(self.clone()))
+        Box::new(E::Un(UnaryOp::Not, self.clone()))
     }
 }
 
@@ -1995,13 +2730,19 @@ where
 }
 
 // compare構文生成
+// メソッド名は右辺から見た比較(a.lt(b) は b が a より小さい a>b)で、
+// F!(a < b) はこれを入れ替えて呼び出す
 pub trait PartialOrd<Rhs = Self> {
+    /// ">" の比較
     fn lt(self, other: Rhs) -> Box<E>;
 
+    /// ">=" の比較
     fn le(self, other: Rhs) -> Box<E>;
 
+    /// "<" の比較
     fn gt(self, other: Rhs) -> Box<E>;
 
+    /// "<=" の比較
     fn ge(self, other: Rhs) -> Box<E>;
 }
 
@@ -2050,6 +2791,12 @@ where
 // 代入文生成
 pub trait Subs<Rhs = Self> {
     fn sst(&self, other: Rhs) -> Box<E>;
+
+    /// ブロックの設定によらないブロッキング代入
+    fn sst_block(&self, other: Rhs) -> Box<E>;
+
+    /// ブロックの設定によらないノンブロッキング代入
+    fn sst_non(&self, other: Rhs) -> Box<E>;
 }
 
 impl<T> Subs<T> for Box<E>
@@ -2059,6 +2806,14 @@ where
     fn sst(&self, other: T) -> Box<E> {
         _Veq(self.clone(), other.into())
     }
+
+    fn sst_block(&self, other: T) -> Box<E> {
+        Box::new(E::SBX(Subst::Blocking, self.clone(), other.into()))
+    }
+
+    fn sst_non(&self, other: T) -> Box<E> {
+        Box::new(E::SBX(Subst::NonBlocking, self.clone(), other.into()))
+    }
 }
 
 // 論理演算子生成
@@ -2114,111 +2869,69 @@ where
  * 出力関数以外はデバッグ用関数のため削除しても問題はない
  **/
 
+// 幅・長さの式から 1 を引いた最上位の添字の出力
+pub(crate) fn minus_one(e: &E) -> String {
+    let msb = match *e {
+        E::Num(n) => E::Num(n - 1),
+        _ => E::Bin(BinOp::Sub, Box::new(e.clone()), _Num(1)),
+    };
+    decomp_ast(Box::new(msb), "", 0)
+}
+
+/// 単項演算子と一次式の優先順位
+const UNARY_PREC: i32 = 11;
+
+// 式の優先順位(条件演算子が最も弱い)
+fn prec(e: &E) -> i32 {
+    match *e {
+        E::Bin(op, _, _) => op.precedence(),
+        E::PL(_, _, _) => 0,
+        _ => UNARY_PREC,
+    }
+}
+
+// 連接の要素の出力
+fn cat_items(items: &[Box<E>], cnfg: &str) -> String {
+    items
+        .iter()
+        .map(|x| decomp_ast(x.clone(), cnfg, 0))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 演算子の被演算子の出力
+// 優先順位が min より低い式と、直前の演算子と繋がって別の記号になる式は括弧で囲む
+fn operand(e: &E, min: i32, before: &str, cnfg: &str) -> String {
+    let st = decomp_ast(Box::new(e.clone()), cnfg, 0);
+    let glue = match (before.chars().last(), st.chars().next()) {
+        (Some(a), Some(b)) => [
+            "&&", "||", "~&", "~|", "~^", "^~", "--", "++", "**", "//", "/*",
+        ]
+        .contains(&format!("{}{}", a, b).as_str()),
+        _ => false,
+    };
+    if prec(e) < min || glue {
+        format!("({})", st)
+    } else {
+        st
+    }
+}
+
 /// 分解出力関数
-fn decomp_ast(Parenthesis: bool, ast: Box<E>, cnfg: &str, indent: i32) -> String {
+pub(crate) fn decomp_ast(ast: Box<E>, cnfg: &str, indent: i32) -> String {
     let e = *ast;
     let mut st = String::new();
 
     match e {
-        E::Bin(ref bin, ref l, ref r) => {
-            let tmp = bin.as_str();
+        E::Bin(op, ref l, ref r) => {
+            let (sym, prec) = (op.symbol(), op.precedence());
             for _ in 0..indent {
                 st += "    ";
             }
-            if Parenthesis {
-                match tmp {
-                    "add" => st += "(",
-                    "sub" => st += "(",
-                    "or" => st += "(",
-                    "lor" => st += "(",
-                    _ => st += "",
-                }
-            }
-            let mut pareset = false;
-            st += &decomp_ast(false, l.clone(), cnfg, 0);
-            match tmp {
-                "add" => {
-                    st += "+";
-                }
-                "sub" => {
-                    st += "-";
-                }
-                "mul" => {
-                    st += "*";
-                    pareset = true
-                }
-                "div" => {
-                    st += "/";
-                    pareset = true
-                }
-                "mod" => {
-                    st += "%";
-                    pareset = true
-                }
-                "or" => {
-                    st += "|";
-                }
-                "and" => {
-                    st += "&";
-                }
-                "xor" => {
-                    st += "^";
-                }
-                "lor" => {
-                    st += "||";
-                }
-                "land" => {
-                    st += "&&";
-                }
-                "lshift" => {
-                    st += "<<";
-                }
-                "rshift" => {
-                    st += ">>";
-                }
-                "rshifta" => {
-                    st += ">>>";
-                }
-                "equal" => {
-                    st += "==";
-                }
-                "Not equal" => {
-                    st += "!=";
-                }
-                "more_than" => {
-                    st += "<";
-                }
-                "less_than" => {
-                    st += ">";
-                }
-                "or_more" => {
-                    st += "<=";
-                }
-                "or_less" => {
-                    st += ">=";
-                }
-                _ => panic!("No correspond syntax : error operator -- {}", tmp),
-            }
-            st += &decomp_ast(pareset, r.clone(), cnfg, 0);
-            if Parenthesis {
-                match tmp {
-                    "add" => {
-                        st += ")";
-                    }
-                    "sub" => {
-                        st += ")";
-                    }
-                    "or" => {
-                        st += ")";
-                    }
-                    "lor" => {
-                        st += ")";
-                    }
-                    _ => {
-                        st += "";
-                    }
-                }
-            }
+            // 左結合のため右辺は同じ優先順位でも括弧で囲む
+            st += &operand(l, prec, "", cnfg);
+            st += sym;
+            st += &operand(r, prec + 1, sym, cnfg);
         }
         E::Ldc(ref wr) => {
             st += &format!("{}", wr.name);
@@ -2226,28 +2939,38 @@ fn decomp_ast(Parenthesis: bool, ast: Box<E>, cnfg: &str, indent: i32) -> String
         E::Num(ref i) => {
             st += &format!("{}", i);
         }
+        E::Lit(ref l) => {
+            st += &l.to_string();
+        }
         E::PL(ref d, ref t, ref f) => {
             st += "(";
-            st += &decomp_ast(false, d.clone(), cnfg, 0);
+            st += &decomp_ast(d.clone(), cnfg, 0);
             st += ")? ";
-            st += &decomp_ast(false, t.clone(), cnfg, 0);
+            st += &decomp_ast(t.clone(), cnfg, 0);
             st += ": ";
 
-            st += &decomp_ast(false, f.clone(), cnfg, 0);
+            st += &decomp_ast(f.clone(), cnfg, 0);
         }
         E::SB(ref l, ref r) => {
             for _ in 0..indent {
                 st += "    ";
             }
-            st += &decomp_ast(false, l.clone(), cnfg, indent);
+            st += &decomp_ast(l.clone(), cnfg, indent);
             if cnfg.to_string() == "block".to_string() {
                 st += " = ";
             } else {
                 st += " <= ";
             }
-            st += &decomp_ast(false, r.clone(), cnfg, 0);
+            st += &decomp_ast(r.clone(), cnfg, 0);
             st += ";\n";
         }
+        E::SBX(sub, ref l, ref r) => {
+            let cnfg = match sub {
+                Subst::Blocking => "block",
+                Subst::NonBlocking => "Non",
+            };
+            st += &decomp_ast(Box::new(E::SB(l.clone(), r.clone())), cnfg, indent);
+        }
         E::CS(ref c) => {
             let cn = &*c;
             st += &CaseStmt::print(cn.clone(), cnfg, indent);
@@ -2259,28 +2982,28 @@ fn decomp_ast(Parenthesis: bool, ast: Box<E>, cnfg: &str, indent: i32) -> String
         E::MEM(ref m, ref a) => {
             let ma = &*m;
             let aa = &*a;
-            st += &decomp_ast(false, ma.clone(), cnfg, indent);
+            st += &decomp_ast(ma.clone(), cnfg, indent);
             st += &format!("[");
-            st += &decomp_ast(false, aa.clone(), cnfg, 0);
+            st += &decomp_ast(aa.clone(), cnfg, 0);
             st += &format!("]");
         }
         E::MBT(ref m, ref a, ref b) => {
             let mn = &*m;
             let aa = &*a;
             let bb = &*b;
-            st += &decomp_ast(false, mn.clone(), cnfg, indent);
+            st += &decomp_ast(mn.clone(), cnfg, indent);
             st += &format!("[");
-            st += &decomp_ast(false, aa.clone(), cnfg, 0);
+            st += &decomp_ast(aa.clone(), cnfg, 0);
             st += &format!(":");
-            st += &decomp_ast(false, bb.clone(), cnfg, 0);
+            st += &decomp_ast(bb.clone(), cnfg, 0);
             st += &format!("]");
         }
         E::Func(ref a, ref v) => {
-            st += &decomp_ast(false, a.clone(), cnfg, 0);
+            st += &decomp_ast(a.clone(), cnfg, 0);
             st += &format!("(");
             let mut i: usize = 0;
             for x in v.clone() {
-                st += &decomp_ast(false, x.clone(), cnfg, 0);
+                st += &decomp_ast(x.clone(), cnfg, 0);
                 i += 1;
                 if v.len() != i {
                     st += &format!(", ");
@@ -2288,27 +3011,32 @@ fn decomp_ast(Parenthesis: bool, ast: Box<E>, cnfg: &str, indent: i32) -> String
             }
             st += &format!(")");
         }
-        E::No(ref b) => {
-            let bb = &*b;
-            st += "~";
-            st += &decomp_ast(false, bb.clone(), cnfg, 0);
-        }
-        E::Red(ref r, ref a) => {
-            let tmp = r.as_str();
-            match tmp.clone() {
-                "and" => {
-                    st += "&";
-                }
-                "or" => st += "|",
-                "xor" => st += "^",
-                "nand" => st += "~&",
-                "nor" => st += "~|",
-                "xnor" => st += "~^",
-                _ => {
-                    return st;
-                }
-            }
-            st += &decomp_ast(false, a.clone(), cnfg, 0);
+        E::Un(op, ref b) => {
+            st += op.symbol();
+            st += &operand(b, UNARY_PREC, op.symbol(), cnfg);
+        }
+        E::Red(op, ref a) => {
+            st += op.symbol();
+            st += &operand(a, UNARY_PREC, op.symbol(), cnfg);
+        }
+        E::Cat(ref items) => {
+            st += &format!("{{{}}}", cat_items(items, cnfg));
+        }
+        E::Signed(ref a) => {
+            st += &format!("$signed({})", decomp_ast(a.clone(), cnfg, 0));
+        }
+        E::Unsigned(ref a) => {
+            st += &format!("$unsigned({})", decomp_ast(a.clone(), cnfg, 0));
+        }
+        E::Clog2(ref a) => {
+            st += &format!("$clog2({})", decomp_ast(a.clone(), cnfg, 0));
+        }
+        E::Rep(ref n, ref x) => {
+            let inner = match **x {
+                E::Cat(ref items) => cat_items(items, cnfg),
+                _ => decomp_ast(x.clone(), cnfg, 0),
+            };
+            st += &format!("{{{}{{{}}}}}", decomp_ast(n.clone(), cnfg, 0), inner);
         }
         _ => {
             st += "";
@@ -2354,6 +3082,9 @@ impl FsmModule {
             st += &s.print();
         }
         st += "            endcase \n        end\n    end\n\n";
+        if let Some(al) = self.output_always() {
+            st += &al.print();
+        }
 
         return st;
     }
@@ -2376,7 +3107,7 @@ impl StateModule {
 }
 
 /// AXIインタフェース出力関数
-fn print_axi(axi: Bus, num: i32) -> String {
+pub(crate) fn print_axi(axi: Bus, num: i32) -> String {
     let tmp = axi.clone();
     let mut st = String::new();
     match tmp {
@@ -2518,14 +3249,11 @@ fn print_axi_lite_slave(axi: AxiLite, count: i32) -> String {
         if let E::Null = *(x.0.clone()) {
             continue;
         }
-        st += &format!(
-            "\n            if( {} ) begin \n",
-            &decomp_ast(false, x.0, "", 0)
-        );
+        st += &format!("\n            if( {} ) begin \n", &decomp_ast(x.0, "", 0));
         st += &format!(
             "                    {} <= {};\n",
             _StrOut(reg_tmp[i as usize].clone()),
-            &decomp_ast(false, x.1, "", 0)
+            &decomp_ast(x.1, "", 0)
         );
         st += "            end\n";
     }
@@ -2793,14 +3521,31 @@ macro_rules! Blank {
     };
 }
 
+/// FSM のステート符号化方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum FsmEncoding {
+    Binary,
+    Gray,
+    OneHot,
+    /// ステートの追加順に指定する符号
+    Custom(Vec<u64>),
+}
+
 /// FSMモジュール
 #[derive(Debug, Clone)]
 pub struct FsmModule {
-    clk: Box<E>,
-    rst: Box<E>,
-    state_reg: Box<E>,
-    states: Vec<StateModule>,
+    pub(crate) clk: Box<E>,
+    pub(crate) rst: Box<E>,
+    pub(crate) state_reg: Box<E>,
+    pub(crate) states: Vec<StateModule>,
     Current_state: i32,
+    // 出力のデフォルト値(出力, 値)
+    pub(crate) defaults: Vec<(Box<E>, Box<E>)>,
+    // 直前に追加した遷移(ステート番号, 分岐番号)
+    last_branch: Option<(usize, usize)>,
+    // 符号化方式(None は属性無しの 2 進符号)
+    pub(crate) encoding: Option<FsmEncoding>,
+    pub(crate) errors: Vec<String>,
 }
 
 impl FsmModule {
@@ -2812,6 +3557,10 @@ impl FsmModule {
             state_reg: state,
             states: Vec::new(),
             Current_state: 0,
+            defaults: Vec::new(),
+            last_branch: None,
+            encoding: None,
+            errors: Vec::new(),
         }
     }
 
@@ -2833,6 +3582,8 @@ impl FsmModule {
         let tmp = StateModule {
             state: Box::new(E::Ldc(p)),
             branch: Vec::new(),
+            moore: Vec::new(),
+            mealy: Vec::new(),
         };
         self.states.push(tmp);
 
@@ -2863,7 +3614,19 @@ impl FsmModule {
         st = st + "_Next";
         let NState = WireVar::new().reg(&st, 0);
         let Goto_ = WireVar::new().parameter(State_name, 0);
-        self.states[self.Current_state as usize].set_branch(Branch.into(), F!(NState = Goto_));
+        if self.states.is_empty() {
+            self.errors
+                .push(format!("goto {} called before AddState", State_name));
+            return self.clone();
+        }
+        let cur = self.Current_state as usize;
+        if cur >= self.states.len() {
+            self.errors
+                .push(format!("goto {} called without a current state", State_name));
+            return self.clone();
+        }
+        self.states[cur].set_branch(Branch.into(), F!(NState = Goto_));
+        self.last_branch = Some((cur, self.states[cur].branch.len() - 1));
 
         self.clone()
     }
@@ -2877,19 +3640,190 @@ impl FsmModule {
         };
         st = st + "_Next";
         let NState = WireVar::new().reg(&st, 0);
-        let NameCurrentState = self.states[((self.Current_state - 1) as usize)].getStateName();
+        if self.states.is_empty() {
+            self.errors
+                .push(format!("from {} called before AddState", State_name));
+            return self.clone();
+        }
+        if self.Current_state < 1 || self.Current_state as usize > self.states.len() {
+            self.errors
+                .push(format!("from {} called without a current state", State_name));
+            return self.clone();
+        }
+        let NameCurrentState = self.states[(self.Current_state - 1) as usize].getStateName();
         let branch = Branch.into();
-        for x in &mut self.states {
+        for (i, x) in self.states.iter_mut().enumerate() {
             let Nx = x.getStateName();
             if Nx == State_name.to_string() {
                 let Goto_ = WireVar::new().parameter(&NameCurrentState, 0);
                 x.set_branch(branch.clone(), F!(NState = Goto_));
+                self.last_branch = Some((i, x.branch.len() - 1));
             }
         }
 
         self.clone()
     }
 
+    /// カレントステートの Moore 出力(ステート中は保持)
+    pub fn output<T: Into<Box<E>>, U: Into<Box<E>>>(&mut self, signal: T, value: U) -> FsmModule {
+        let (signal, value) = (signal.into(), value.into());
+        match self.states.get_mut(self.Current_state as usize) {
+            Some(s) => s.moore.push((signal, value)),
+            None => self
+                .errors
+                .push(format!("output {} called before AddState", _StrOut(signal))),
+        }
+        self.clone()
+    }
+
+    /// 直前に追加した遷移の Mealy 出力(遷移条件が成立している間のみ)
+    pub fn mealy<T: Into<Box<E>>, U: Into<Box<E>>>(&mut self, signal: T, value: U) -> FsmModule {
+        let (signal, value) = (signal.into(), value.into());
+        match self.last_branch {
+            Some((s, b)) => self.states[s].mealy.push((b, signal, value)),
+            None => self
+                .errors
+                .push(format!("mealy {} called before goto", _StrOut(signal))),
+        }
+        self.clone()
+    }
+
+    /// 出力のデフォルト値(指定の無い出力は 0)
+    pub fn output_default<T: Into<Box<E>>, U: Into<Box<E>>>(
+        &mut self,
+        signal: T,
+        value: U,
+    ) -> FsmModule {
+        self.defaults.push((signal.into(), value.into()));
+        self.clone()
+    }
+
+    // 出力の組み合わせ回路(出力が無ければ None)
+    pub(crate) fn output_always(&self) -> Option<Always> {
+        let reg = match *self.state_reg {
+            E::Ldc(ref wr) => wr.clone(),
+            _ => return None,
+        };
+        let mut outs = self.defaults.clone();
+        for s in &self.states {
+            let used = s
+                .moore
+                .iter()
+                .map(|x| &x.0)
+                .chain(s.mealy.iter().map(|x| &x.1));
+            for sig in used {
+                let name = decomp_ast(sig.clone(), "", 0);
+                if !outs.iter().any(|x| decomp_ast(x.0.clone(), "", 0) == name) {
+                    outs.push((sig.clone(), _Num(0)));
+                }
+            }
+        }
+        if outs.is_empty() {
+            return None;
+        }
+        let mut stmt = outs.iter().map(|x| _Veq(&x.0, &x.1)).collect::<Vec<_>>();
+        let mut select = CaseStmt {
+            CaseVar: reg,
+            Select: Vec::new(),
+        };
+        for s in &self.states {
+            let mut items = s.moore.iter().map(|x| _Veq(&x.0, &x.1)).collect::<Vec<_>>();
+            // 遷移の優先順位を保つため Mealy 出力の無い遷移も条件に含める
+            if let Some(last) = s.mealy.iter().map(|m| m.0).max() {
+                let list = s.branch[..=last]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| IfElseAST {
+                        if_: b.if_,
+                        cond: b.cond.clone(),
+                        stmt: s
+                            .mealy
+                            .iter()
+                            .filter(|m| m.0 == i)
+                            .map(|m| _Veq(&m.1, &m.2))
+                            .collect(),
+                    })
+                    .collect();
+                items.push(Box::new(E::BL(list)));
+            }
+            if !items.is_empty() {
+                select.SetCaseS(s.state.clone(), items);
+            }
+        }
+        if !select.Select.is_empty() {
+            stmt.push(Box::new(E::CS(select)));
+        }
+        let mut al = Always::new();
+        al.stmt = stmt;
+        Some(al)
+    }
+
+    /// ステートの符号化方式(ステートレジスタに fsm_encoding 属性を付ける)
+    pub fn encoding(&mut self, encoding: FsmEncoding) -> FsmModule {
+        self.encoding = Some(encoding);
+        self.clone()
+    }
+
+    // 各ステートの符号
+    pub(crate) fn codes(&self) -> Vec<u64> {
+        let n = self.states.len() as u64;
+        match self.encoding {
+            None | Some(FsmEncoding::Binary) => (0..n).collect(),
+            Some(FsmEncoding::Gray) => (0..n).map(|i| i ^ (i >> 1)).collect(),
+            // 64 ステートを超える one-hot 符号は code_errors() で検出する
+            Some(FsmEncoding::OneHot) => (0..n)
+                .map(|i| 1u64.checked_shl(i as u32).unwrap_or(0))
+                .collect(),
+            Some(FsmEncoding::Custom(ref codes)) => codes.clone(),
+        }
+    }
+
+    // 符号化方式に必要な最小のステートレジスタ幅
+    pub(crate) fn state_width(&self) -> i32 {
+        match self.encoding {
+            Some(FsmEncoding::OneHot) => cmp::max(self.states.len() as i32, 1),
+            Some(FsmEncoding::Custom(_)) => {
+                let max = self.codes().into_iter().max().unwrap_or(0);
+                cmp::max(64 - max.leading_zeros() as i32, 1)
+            }
+            _ => cmp::max(clog2_of(self.states.len() as i64) as i32, 1),
+        }
+    }
+
+    // fsm_encoding 属性の値
+    pub(crate) fn encoding_attr(&self) -> Option<&'static str> {
+        self.encoding.as_ref().map(|e| match *e {
+            FsmEncoding::Binary => "sequential",
+            FsmEncoding::Gray => "gray",
+            FsmEncoding::OneHot => "one_hot",
+            FsmEncoding::Custom(_) => "none",
+        })
+    }
+
+    // 符号の検査(ステート数との不一致、重複、64 ビットを超える one-hot 符号)
+    fn code_errors(&self) -> Vec<String> {
+        let n = self.states.len();
+        let mut msgs = Vec::new();
+        match self.encoding {
+            Some(FsmEncoding::OneHot) if n > 64 => msgs.push(format!(
+                "one-hot encoding of {} states is wider than 64 bits",
+                n
+            )),
+            Some(FsmEncoding::Custom(ref codes)) => {
+                if codes.len() != n {
+                    msgs.push(format!("{} states but {} codes", n, codes.len()));
+                }
+                for (i, c) in codes.iter().enumerate() {
+                    if codes[..i].contains(c) {
+                        msgs.push(format!("duplicate state code {}", c));
+                    }
+                }
+            }
+            _ => {}
+        }
+        msgs
+    }
+
     // セットパラメータの取得
     pub fn Param(&mut self, name: &str) -> Box<E> {
         let SelfS = self.states.clone();
@@ -2928,9 +3862,13 @@ impl FsmModule {
 
 /// 1ステートモデル
 #[derive(Debug, Clone)]
-struct StateModule {
-    state: Box<E>,
-    branch: Vec<IfElseAST>,
+pub(crate) struct StateModule {
+    pub(crate) state: Box<E>,
+    pub(crate) branch: Vec<IfElseAST>,
+    // Moore 出力(出力, 値)
+    pub(crate) moore: Vec<(Box<E>, Box<E>)>,
+    // Mealy 出力(遷移の分岐番号, 出力, 値)
+    pub(crate) mealy: Vec<(usize, Box<E>, Box<E>)>,
 }
 
 impl StateModule {
@@ -2946,8 +3884,9 @@ impl StateModule {
         tmp.push(Form.into());
 
         match e {
+            // 条件無しの遷移は else として出力する
             E::Null => self.branch.push(IfElseAST {
-                if_: true,
+                if_: false,
                 cond: Box::new(e),
                 stmt: tmp,
             }),
@@ -2981,7 +3920,7 @@ impl StateModule {
 /// -------------------------------------------------------------------------------------------------------------------
 
 #[derive(Debug, Clone)]
-enum Bus {
+pub(crate) enum Bus {
     AxiLite(AxiLite),
     AxiSlave(Axi4Slave),
     AxiMaster(AxiMaster),
@@ -3379,7 +4318,7 @@ pub fn _StrOut<T: Into<Box<E>>>(e: T) -> String {
     let m = *e.into();
     match m {
         E::Ldc(WR) => WR.name,
-        E::Bin(ref Op, _, _) => Op.clone(),
+        E::Bin(op, _, _) => op.symbol().to_string(),
         _ => "Null".to_string(),
     }
 }
@@ -0,0 +1,1092 @@
+//! VHDL-2008 構文の出力
+
+use error::VerugentError;
+use generate::{shift, GenItem, Generate};
+use lint::substs;
+use literal::Bit;
+use std::cell::RefCell;
+use std::cmp;
+use vcore::*;
+use width::Width;
+
+/// VHDL の予約語
+const RESERVED: &str = "\
+    abs access after alias all and architecture array assert assume attribute begin block body \
+    buffer bus case component configuration constant context cover default disconnect downto \
+    else elsif end entity exit fairness file for force function generate generic group guarded \
+    if impure in inertial inout is label library linkage literal loop map mod nand new next nor \
+    not null of on open or others out package parameter port postponed procedure process \
+    property protected pure range record register reject release rem report restrict return rol \
+    ror select sequence severity signal shared sla sll sra srl strong subtype then to transport \
+    type unaffected units until use variable vmode vprop vunit wait when while with xnor xor";
+
+// 識別子の出力(VHDL で使えない名前は拡張識別子にする)
+fn id(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let basic = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.ends_with('_')
+        && !name.contains("__")
+        && !RESERVED.split_whitespace().any(|w| w == lower);
+    if basic {
+        name.to_string()
+    } else {
+        format!("\\{}\\", name)
+    }
+}
+
+fn tab(indent: i32) -> String {
+    "    ".repeat(cmp::max(indent, 0) as usize)
+}
+
+// $clog2 の ieee.math_real による表現
+fn clog2(x: &str) -> String {
+    format!("integer(ceil(log2(real({}))))", x)
+}
+
+fn num_type(signed: bool) -> &'static str {
+    if signed {
+        "signed"
+    } else {
+        "unsigned"
+    }
+}
+
+fn is_memory(wr: &WireVar) -> bool {
+    wr.length > 0 || wr.length_p.is_some()
+}
+
+// 式の出力時の文脈
+struct Ctx<'a> {
+    m: &'a VModule,
+    // function 内で戻り値の変数に置き換える名前
+    func: Option<String>,
+    // 列挙型として宣言された FSM のステートとステートレジスタ
+    enums: Vec<String>,
+    // VHDL で表せない構文
+    errors: &'a RefCell<Vec<String>>,
+}
+
+impl<'a> Ctx<'a> {
+    // VHDL で表せない構文の記録
+    fn unsupported(&self, message: String) -> String {
+        self.errors.borrow_mut().push(message);
+        String::new()
+    }
+
+    // 定数式(幅、配列長、パラメータ値)の出力
+    fn int_expr(&self, e: &E) -> String {
+        // 優先順位の低い被演算子と右側の同順位の被演算子は括弧で囲む
+        let operand = |x: &E, op: BinOp, right: bool| match *x {
+            E::Bin(o, _, _)
+                if o.precedence() < op.precedence()
+                    || (right && o.precedence() == op.precedence()) =>
+            {
+                format!("({})", self.int_expr(x))
+            }
+            _ => self.int_expr(x),
+        };
+        match *e {
+            E::Num(n) => n.to_string(),
+            E::Lit(ref l) => l.to_i64().unwrap_or(0).to_string(),
+            E::Ldc(ref wr) => id(&wr.name),
+            E::Bin(op, ref l, ref r) if op.is_arith() => {
+                let sym = if op == BinOp::Mod {
+                    " mod "
+                } else {
+                    op.symbol()
+                };
+                format!("{}{}{}", operand(l, op, false), sym, operand(r, op, true))
+            }
+            E::Un(UnaryOp::Neg, ref x) => format!("-{}", operand(x, BinOp::Mul, false)),
+            E::Clog2(ref x) => clog2(&self.int_expr(x)),
+            _ => self.unsupported(format!(
+                "constant expression {}",
+                decomp_ast(Box::new(e.clone()), "", 0)
+            )),
+        }
+    }
+
+    // 幅・配列長から 1 を引いた最上位の添字
+    fn msb_of(&self, e: &E) -> String {
+        match *e {
+            E::Num(n) => (n - 1).to_string(),
+            _ => self.int_expr(&E::Bin(
+                BinOp::Sub,
+                Box::new(e.clone()),
+                Box::new(E::Num(1)),
+            )),
+        }
+    }
+
+    // ビット幅の VHDL 表現
+    fn width_of(&self, wr: &WireVar) -> Option<String> {
+        if let Some(ref w) = wr.width_p {
+            Some(self.int_expr(w))
+        } else if wr.width > 1 {
+            Some(wr.width.to_string())
+        } else {
+            None
+        }
+    }
+
+    // 信号の型
+    fn type_of(&self, wr: &WireVar) -> String {
+        if let Some(ref w) = wr.width_p {
+            format!("{}({} downto 0)", num_type(wr.signed), self.msb_of(w))
+        } else if wr.width > 1 {
+            format!("{}({} downto 0)", num_type(wr.signed), wr.width - 1)
+        } else {
+            "std_logic".to_string()
+        }
+    }
+
+    fn is_bool(&self, e: &E) -> bool {
+        match *e {
+            E::Bin(op, _, _) => op.is_compare() || op == BinOp::LAnd || op == BinOp::LOr,
+            E::Un(UnaryOp::Not, ref x) => self.is_bool(x),
+            E::Un(UnaryOp::LNot, _) => true,
+            _ => false,
+        }
+    }
+
+    fn is_bit(&self, e: &E) -> bool {
+        !self.is_bool(e) && self.m.infer_width(e) == Some(Width::Sized(1))
+    }
+
+    fn is_enum(&self, e: &E) -> bool {
+        match *e {
+            E::Ldc(ref wr) => self.enums.contains(&wr.name),
+            _ => false,
+        }
+    }
+
+    // 整数として扱う式(定数、パラメータ、genvar)
+    fn is_int(&self, e: &E) -> bool {
+        match *e {
+            E::Num(_) => true,
+            E::Ldc(ref wr) => {
+                matches!(wr.io_param, IOType::Param | IOType::Genvar) && !self.is_enum(e)
+            }
+            E::Bin(op, ref l, ref r) => op.is_arith() && self.is_int(l) && self.is_int(r),
+            E::Un(UnaryOp::Neg, ref x) => self.is_int(x),
+            E::Clog2(_) => true,
+            _ => false,
+        }
+    }
+
+    fn paren(&self, e: &E) -> String {
+        match *e {
+            E::Bin(_, _, _) | E::Un(_, _) => format!("({})", self.expr(e)),
+            _ => self.expr(e),
+        }
+    }
+
+    // 整数を相手側の型に合わせたリテラルとして出力
+    fn literal(&self, n: i32, other: &E) -> String {
+        if self.is_bit(other) {
+            if n == 0 { "'0'" } else { "'1'" }.to_string()
+        } else {
+            match *other {
+                E::Ldc(_) | E::MEM(_, _) | E::MBT(_, _, _) => {
+                    format!(
+                        "to_{}({}, {}'length)",
+                        num_type(self.m.is_signed(other)),
+                        n,
+                        self.expr(other)
+                    )
+                }
+                _ => {
+                    let w = self.m.infer_width(other).map_or(32, |w| w.bits());
+                    format!("to_{}({}, {})", num_type(self.m.is_signed(other)), n, w)
+                }
+            }
+        }
+    }
+
+    // 二項演算のオペランド
+    fn operand(&self, e: &E, other: &E, arith: bool) -> String {
+        match *e {
+            E::Num(n) if !arith && !self.is_int(other) => self.literal(n, other),
+            _ => self.paren(e),
+        }
+    }
+
+    fn index(&self, e: &E) -> String {
+        if self.is_int(e) {
+            self.expr(e)
+        } else {
+            format!("to_integer({})", self.expr(e))
+        }
+    }
+
+    // 条件式(boolean)の出力
+    fn cond(&self, e: &E) -> String {
+        if self.is_bool(e) {
+            self.expr(e)
+        } else if self.is_bit(e) {
+            format!("{} = '1'", self.paren(e))
+        } else {
+            format!("{} /= 0", self.paren(e))
+        }
+    }
+
+    fn expr(&self, e: &E) -> String {
+        match *e {
+            E::Ldc(ref wr) => match self.func {
+                Some(ref f) if *f == wr.name => id(&(wr.name.clone() + "_v")),
+                _ => id(&wr.name),
+            },
+            E::Num(n) => n.to_string(),
+            E::Lit(ref l) => {
+                let bits = l
+                    .bits()
+                    .iter()
+                    .rev()
+                    .map(|&b| match b {
+                        Bit::Zero => '0',
+                        Bit::One => '1',
+                        Bit::X => 'X',
+                        Bit::Z => 'Z',
+                    })
+                    .collect::<String>();
+                if l.width == Some(1) {
+                    format!("'{}'", bits)
+                } else {
+                    format!("{}'(\"{}\")", num_type(l.signed), bits)
+                }
+            }
+            E::Bin(op, ref l, ref r) => {
+                let sym = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Mod => "mod",
+                    BinOp::Or => "or",
+                    BinOp::And => "and",
+                    BinOp::Xor => "xor",
+                    BinOp::Eq => "=",
+                    BinOp::Ne => "/=",
+                    BinOp::Lt => "<",
+                    BinOp::Gt => ">",
+                    BinOp::Le => "<=",
+                    BinOp::Ge => ">=",
+                    BinOp::LOr => return format!("({}) or ({})", self.cond(l), self.cond(r)),
+                    BinOp::LAnd => return format!("({}) and ({})", self.cond(l), self.cond(r)),
+                    BinOp::Shl => {
+                        return format!("shift_left({}, {})", self.expr(l), self.index(r))
+                    }
+                    // numeric_std の shift_right は signed なら算術シフトになる
+                    BinOp::Shr | BinOp::Sra => {
+                        return format!("shift_right({}, {})", self.expr(l), self.index(r))
+                    }
+                };
+                let arith = op.is_arith() || op.is_compare();
+                format!(
+                    "{} {} {}",
+                    self.operand(l, r, arith && !self.is_bit(r)),
+                    sym,
+                    self.operand(r, l, arith && !self.is_bit(l))
+                )
+            }
+            E::Un(UnaryOp::Not, ref x) => format!("not {}", self.paren(x)),
+            E::Un(UnaryOp::LNot, ref x) => format!("not ({})", self.cond(x)),
+            // unsigned には単項のマイナスが無いため 2 の補数で表す
+            E::Un(UnaryOp::Neg, ref x) if self.is_int(x) => format!("-{}", self.paren(x)),
+            E::Un(UnaryOp::Neg, ref x) => format!("(not {}) + 1", self.paren(x)),
+            E::Red(op, ref x) => {
+                let name = match op {
+                    ReduceOp::And => "and",
+                    ReduceOp::Or => "or",
+                    ReduceOp::Xor => "xor",
+                    ReduceOp::Nand => "nand",
+                    ReduceOp::Nor => "nor",
+                    ReduceOp::Xnor => "xnor",
+                };
+                format!("({} {})", name, self.paren(x))
+            }
+            E::MEM(ref m, ref a) => format!("{}({})", self.expr(m), self.index(a)),
+            E::MBT(ref m, ref h, ref l) => {
+                format!("{}({} downto {})", self.expr(m), self.expr(h), self.expr(l))
+            }
+            E::Func(ref top, ref args) => {
+                let name = _StrOut(top.clone());
+                let inputs = self
+                    .m
+                    .func
+                    .iter()
+                    .find(|f| _StrOut(f.top.clone()) == name)
+                    .map(|f| f.input.clone())
+                    .unwrap_or_default();
+                let list = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| match (&**a, inputs.get(i)) {
+                        (&E::Num(n), Some(inp)) => match **inp {
+                            E::Ldc(ref wr) => match self.width_of(wr) {
+                                Some(w) => format!("to_{}({}, {})", num_type(wr.signed), n, w),
+                                None => if n == 0 { "'0'" } else { "'1'" }.to_string(),
+                            },
+                            _ => n.to_string(),
+                        },
+                        _ => self.expr(a),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{}({})", id(&name), list)
+            }
+            E::Cat(ref items) => format!(
+                "unsigned'({})",
+                items
+                    .iter()
+                    .map(|x| self.paren(x))
+                    .collect::<Vec<_>>()
+                    .join(" & ")
+            ),
+            E::Rep(ref n, ref x) => {
+                let n = match self.m.const_eval(n) {
+                    Some(n) => n,
+                    None => {
+                        return self.unsupported(format!(
+                            "replication with a non-constant count {}",
+                            decomp_ast(n.clone(), "", 0)
+                        ))
+                    }
+                };
+                if self.is_bit(x) {
+                    format!("unsigned'({} downto 0 => {})", n - 1, self.expr(x))
+                } else {
+                    let x = self.paren(x);
+                    format!("unsigned'({})", vec![x; n as usize].join(" & "))
+                }
+            }
+            E::Signed(ref x) => format!("signed({})", self.expr(x)),
+            E::Unsigned(ref x) => format!("unsigned({})", self.expr(x)),
+            E::Clog2(ref x) => clog2(&self.expr(x)),
+            // VHDL-2008 の条件式は代入の右辺にのみ書ける
+            E::PL(_, _, _) => self.unsupported(format!(
+                "conditional expression {} outside an assignment value",
+                decomp_ast(Box::new(e.clone()), "", 0)
+            )),
+            // 空の式は validate() で検出する
+            E::Null => String::new(),
+            _ => self.unsupported(format!("expression {:?}", e)),
+        }
+    }
+
+    // 代入先の出力(連接は VHDL-2008 の集合体で表す)
+    fn target(&self, e: &E) -> String {
+        match *e {
+            E::Cat(ref items) => format!(
+                "({})",
+                items
+                    .iter()
+                    .map(|x| self.expr(x))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            _ => self.expr(e),
+        }
+    }
+
+    // 代入先に合わせた右辺の出力
+    fn value(&self, lhs: &E, rhs: &E) -> String {
+        // 連接の代入先は 'length を使えないため幅を直接書く
+        let target = match *lhs {
+            E::Cat(_) => self.m.infer_width(lhs).map_or(0, |w| w.bits()).to_string(),
+            _ => format!("{}'length", self.expr(lhs)),
+        };
+        let conv = format!("to_{}", num_type(self.m.is_signed(lhs)));
+        match *rhs {
+            E::PL(ref c, ref t, ref f) => {
+                return format!(
+                    "{} when {} else {}",
+                    self.value(lhs, t),
+                    self.cond(c),
+                    self.value(lhs, f)
+                )
+            }
+            E::Num(n) => {
+                return if self.is_bit(lhs) {
+                    if n == 0 { "'0'" } else { "'1'" }.to_string()
+                } else {
+                    format!("{}({}, {})", conv, n, target)
+                }
+            }
+            _ => {}
+        }
+        if self.is_enum(rhs) || self.is_enum(lhs) {
+            return self.expr(rhs);
+        }
+        if self.is_int(rhs) {
+            return format!("{}({}, {})", conv, self.expr(rhs), target);
+        }
+        if self.is_bool(rhs) {
+            return if self.is_bit(lhs) {
+                format!("'1' when {} else '0'", self.expr(rhs))
+            } else {
+                format!(
+                    "{}(1, {}) when {} else {}(0, {})",
+                    conv,
+                    target,
+                    self.expr(rhs),
+                    conv,
+                    target
+                )
+            };
+        }
+        let lw = self.m.infer_width(lhs).map(|w| w.bits());
+        let rw = self.m.infer_width(rhs).map(|w| w.bits());
+        if self.is_bit(lhs) {
+            return match *rhs {
+                E::Ldc(_) | E::MEM(_, _) | E::MBT(_, _, _) | E::Func(_, _) if rw > Some(1) => {
+                    format!("{}(0)", self.expr(rhs))
+                }
+                _ => self.expr(rhs),
+            };
+        }
+        if self.is_bit(rhs) {
+            return format!("(0 => {}, others => '0')", self.expr(rhs));
+        }
+        let arith = match *rhs {
+            E::Bin(op, _, _) => op.is_arith() || matches!(op, BinOp::Shl | BinOp::Shr | BinOp::Sra),
+            E::Un(UnaryOp::Neg, _) => true,
+            _ => false,
+        };
+        if arith || lw != rw {
+            format!("resize({}, {})", self.expr(rhs), target)
+        } else {
+            self.expr(rhs)
+        }
+    }
+
+    // 文の出力
+    fn stmt(&self, e: &E, indent: i32) -> String {
+        match *e {
+            E::SB(ref l, ref r) | E::SBX(_, ref l, ref r) => format!(
+                "{}{} {} {};\n",
+                tab(indent),
+                self.target(l),
+                if self.func.is_some() { ":=" } else { "<=" },
+                self.value(l, r)
+            ),
+            E::BL(ref list) => self.if_list(list, indent),
+            E::CS(ref c) => {
+                let sel = E::Ldc(c.CaseVar.clone());
+                let sel_st = if self.is_enum(&sel) || self.is_bit(&sel) {
+                    self.expr(&sel)
+                } else {
+                    format!("to_integer({})", self.expr(&sel))
+                };
+                let mut st = format!("{}case {} is\n", tab(indent), sel_st);
+                for x in &c.Select {
+                    let label = match *x.CaseT {
+                        E::Null => "others".to_string(),
+                        E::Num(n) if self.is_bit(&sel) => self.literal(n, &sel),
+                        ref l => self.expr(l),
+                    };
+                    st += &format!("{}when {} =>\n", tab(indent + 1), label);
+                    if x.CaseS.is_empty() {
+                        st += &format!("{}null;\n", tab(indent + 2));
+                    }
+                    for s in &x.CaseS {
+                        st += &self.stmt(s, indent + 2);
+                    }
+                }
+                if !c.Select.iter().any(|x| matches!(*x.CaseT, E::Null)) && !self.is_enum(&sel) {
+                    st += &format!(
+                        "{}when others =>\n{}null;\n",
+                        tab(indent + 1),
+                        tab(indent + 2)
+                    );
+                }
+                st += &format!("{}end case;\n", tab(indent));
+                st
+            }
+            _ => self.unsupported(format!("statement {:?}", e)),
+        }
+    }
+
+    // if - elsif - else の出力
+    fn if_list(&self, list: &[IfElseAST], indent: i32) -> String {
+        let mut st = String::new();
+        for (i, x) in IfElseAST::reachable(list).iter().enumerate() {
+            if i == 0 {
+                if let E::Null = *x.cond {
+                    // 条件無しの分岐は文のみを出力
+                    for s in &x.stmt {
+                        st += &self.stmt(s, indent);
+                    }
+                    return st;
+                }
+                st += &format!("{}if {} then\n", tab(indent), self.cond(&x.cond));
+            } else if x.if_ {
+                st += &format!("{}elsif {} then\n", tab(indent), self.cond(&x.cond));
+            } else {
+                st += &format!("{}else\n", tab(indent));
+            }
+            for s in &x.stmt {
+                st += &self.stmt(s, indent + 1);
+            }
+        }
+        st += &format!("{}end if;\n", tab(indent));
+        st
+    }
+
+    // always ブロックから process への変換
+    fn process(&self, al: &Always) -> String {
+        let mut edges = al
+            .posedges
+            .iter()
+            .map(|wr| (wr.name.clone(), "rising_edge"))
+            .chain(
+                al.negedges
+                    .iter()
+                    .map(|wr| (wr.name.clone(), "falling_edge")),
+            )
+            .collect::<Vec<_>>();
+        let body = |indent: i32| {
+            al.stmt
+                .iter()
+                .map(|s| self.stmt(s, indent))
+                .collect::<Vec<_>>()
+                .join("")
+        };
+        if edges.is_empty() {
+            return format!(
+                "    process (all)\n    begin\n{}    end process;\n",
+                body(2)
+            );
+        }
+
+        // 先頭の if 条件で参照されるエッジ信号は非同期リセットとして扱う
+        let first = match al.stmt.first().map(|s| &**s) {
+            Some(E::BL(list)) if al.stmt.len() == 1 && !matches!(*list[0].cond, E::Null) => {
+                Some(list)
+            }
+            _ => None,
+        };
+        let cond_st = first.map_or(String::new(), |l| decomp_ast(l[0].cond.clone(), "", 0));
+        let refers = |name: &str| {
+            cond_st
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .any(|w| w == name)
+        };
+        let (async_, clock): (Vec<_>, Vec<_>) = edges.drain(..).partition(|e| refers(&e.0));
+        let clk = match clock.first() {
+            Some(c) => c.clone(),
+            None => return self.unsupported("always block without a clock edge".to_string()),
+        };
+        let sens = clock
+            .iter()
+            .chain(async_.iter())
+            .map(|e| id(&e.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // シグナル代入では後続の読み出しが代入前の値になるため、ブロッキング代入は表せない
+        let mut subs = Vec::new();
+        substs(&al.stmt, al.subst(), &mut subs);
+        for (name, _) in subs.iter().filter(|x| x.1 == Subst::Blocking) {
+            self.unsupported(format!(
+                "blocking assignment to {} in a clocked process",
+                name
+            ));
+        }
+        let mut st = format!("    process ({})\n    begin\n", sens);
+        match first {
+            Some(list) if !async_.is_empty() => {
+                st += &format!("        if {} then\n", self.cond(&list[0].cond));
+                for s in &list[0].stmt {
+                    st += &self.stmt(s, 3);
+                }
+                st += &format!("        elsif {}({}) then\n", clk.1, id(&clk.0));
+                if list.len() > 1 {
+                    st += &self.if_list(&list[1..], 3);
+                }
+                st += "        end if;\n";
+            }
+            _ => {
+                st += &format!("        if {}({}) then\n", clk.1, id(&clk.0));
+                st += &body(3);
+                st += "        end if;\n";
+            }
+        }
+        st += "    end process;\n";
+        st
+    }
+
+    // generate ブロックの要素
+    fn item(&self, x: &GenItem) -> String {
+        match *x {
+            GenItem::Assign(ref a) => format!(
+                "    {} <= {};\n",
+                self.target(&a.lhs),
+                self.value(&a.lhs, &a.rhs)
+            ),
+            GenItem::Always(ref al) => self.process(al),
+            GenItem::Instance(ref inst) => self.instance(inst),
+            GenItem::Generate(ref g) => self.generate(g, 1),
+        }
+    }
+
+    // for ... generate / if ... generate への変換(indent は generate 行の深さ)
+    fn generate(&self, g: &Generate, indent: i32) -> String {
+        let body = |items: &[GenItem]| {
+            items
+                .iter()
+                .map(|x| match *x {
+                    GenItem::Generate(ref g) => self.generate(g, indent + 1),
+                    _ => shift(&self.item(x), indent),
+                })
+                .collect::<String>()
+        };
+        let first = &g.blocks[0];
+        let mut st = format!("{}{} : ", tab(indent), id(&first.label));
+        if let Some(ref lp) = g.lp {
+            // 1 ずつ増減するループのみ範囲で表せる
+            let var = |e: &E| matches!(*e, E::Ldc(ref wr) if wr.name == lp.var.name);
+            let step = match *lp.step {
+                E::Bin(op, ref v, ref n) if var(v) && self.m.const_eval(n) == Some(1) => Some(op),
+                _ => None,
+            };
+            let init = self.expr(&lp.init);
+            let range = match (&*lp.cond, step) {
+                (&E::Bin(BinOp::Lt, ref v, ref n), Some(BinOp::Add)) if var(v) => {
+                    let last = E::Bin(BinOp::Sub, n.clone(), Box::new(E::Num(1)));
+                    format!("{} to {}", init, self.expr(&last))
+                }
+                (&E::Bin(BinOp::Le, ref v, ref n), Some(BinOp::Add)) if var(v) => {
+                    format!("{} to {}", init, self.expr(n))
+                }
+                (&E::Bin(BinOp::Gt, ref v, ref n), Some(BinOp::Sub)) if var(v) => {
+                    let last = E::Bin(BinOp::Add, n.clone(), Box::new(E::Num(1)));
+                    format!("{} downto {}", init, self.expr(&last))
+                }
+                (&E::Bin(BinOp::Ge, ref v, ref n), Some(BinOp::Sub)) if var(v) => {
+                    format!("{} downto {}", init, self.expr(n))
+                }
+                _ => {
+                    return self
+                        .unsupported(format!("generate for {} not counting by one", first.label))
+                }
+            };
+            st += &format!("for {} in {} generate\n", id(&lp.var.name), range);
+            st += &body(&first.items);
+        } else {
+            for (i, b) in g.blocks.iter().enumerate() {
+                let label = if i > 0 {
+                    format!("{} : ", id(&b.label))
+                } else {
+                    String::new()
+                };
+                st += &match (i, &*b.cond) {
+                    (0, _) => format!("if {} generate\n", self.cond(&b.cond)),
+                    (_, &E::Null) => format!("{}else {}generate\n", tab(indent), label),
+                    _ => format!(
+                        "{}elsif {}{} generate\n",
+                        tab(indent),
+                        label,
+                        self.cond(&b.cond)
+                    ),
+                };
+                st += &body(&b.items);
+            }
+        }
+        st + &format!("{}end generate {};\n", tab(indent), id(&first.label))
+    }
+
+    fn function(&self, f: &Func) -> String {
+        let top = match *f.top {
+            E::Ldc(ref wr) => wr.clone(),
+            _ => return String::new(),
+        };
+        let ctx = Ctx {
+            m: self.m,
+            func: Some(top.name.clone()),
+            enums: self.enums.clone(),
+            errors: self.errors,
+        };
+        let inputs = f
+            .input
+            .iter()
+            .filter_map(|x| match **x {
+                E::Ldc(ref wr) => Some(format!("{} : {}", id(&wr.name), self.type_of(wr))),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let ret = if self.width_of(&top).is_some() {
+            "unsigned"
+        } else {
+            "std_logic"
+        };
+        let mut st = format!(
+            "\n    function {}({}) return {} is\n",
+            id(&top.name),
+            inputs,
+            ret
+        );
+        st += &format!(
+            "        variable {} : {};\n    begin\n",
+            id(&(top.name.clone() + "_v")),
+            self.type_of(&top)
+        );
+        for s in &f.stmt {
+            st += &ctx.stmt(s, 2);
+        }
+        st += &format!(
+            "        return {};\n    end function;\n",
+            id(&(top.name.clone() + "_v"))
+        );
+        st
+    }
+
+    fn instance(&self, inst: &Instance) -> String {
+        let mut st = format!(
+            "    {} : entity work.{}\n",
+            id(&inst.name),
+            id(&inst.module)
+        );
+        if !inst.param_map.is_empty() {
+            let list = inst
+                .param_map
+                .iter()
+                .map(|(n, v)| format!("            {} => {}", id(n), self.expr(v)))
+                .collect::<Vec<_>>()
+                .join(",\n");
+            st += &format!("        generic map (\n{}\n        )\n", list);
+        }
+        let list = inst
+            .port_map
+            .iter()
+            .map(|(n, s)| {
+                let actual = match (&**s, inst.ports.iter().find(|p| p.name == *n)) {
+                    (&E::Num(v), Some(p)) => match self.width_of(p) {
+                        Some(w) => format!("to_{}({}, {})", num_type(p.signed), v, w),
+                        None => if v == 0 { "'0'" } else { "'1'" }.to_string(),
+                    },
+                    _ => self.expr(s),
+                };
+                format!("            {} => {}", id(n), actual)
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        st += &format!("        port map (\n{}\n        );\n", list);
+        st
+    }
+}
+
+impl FsmModule {
+    // 列挙型によるステート型とステート信号の宣言
+    fn print_vhdl_decl(&self) -> String {
+        let reg = _StrOut(self.state_reg.clone());
+        let names = self
+            .state_names()
+            .iter()
+            .map(|n| id(n))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut st = format!(
+            "    type {} is ({});\n    signal {}, {} : {};\n",
+            id(&(reg.clone() + "_t")),
+            names,
+            id(&reg),
+            id(&(reg.clone() + "_Next")),
+            id(&(reg.clone() + "_t"))
+        );
+        if let Some(a) = self.encoding_attr() {
+            st += &format!(
+                "    attribute fsm_encoding of {} : signal is \"{}\";\n",
+                id(&reg),
+                a
+            );
+        }
+        // 指定した符号は列挙型の符号化属性で与える
+        if let Some(FsmEncoding::Custom(_)) = self.encoding {
+            let width = self.state_width() as usize;
+            let codes = self
+                .codes()
+                .iter()
+                .map(|c| format!("{:0w$b}", c, w = width))
+                .collect::<Vec<_>>()
+                .join(" ");
+            st += &format!(
+                "    attribute enum_encoding of {} : type is \"{}\";\n",
+                id(&(reg + "_t")),
+                codes
+            );
+        }
+        st
+    }
+
+    fn print_vhdl(&self, ctx: &Ctx) -> String {
+        let clk = id(&_StrOut(self.clk.clone()));
+        let rst = ctx.cond(&self.rst);
+        let reg = id(&_StrOut(self.state_reg.clone()));
+        let next = id(&(_StrOut(self.state_reg.clone()) + "_Next"));
+        let init = id(&_StrOut(self.states[0].state.clone()));
+        let mut st = format!(
+            "    process ({}, {})\n    begin\n",
+            clk,
+            id(&_StrOut(self.rst.clone()))
+        );
+        st += &format!(
+            "        if {} then\n            {} <= {};\n",
+            rst, reg, init
+        );
+        st += &format!(
+            "        elsif rising_edge({}) then\n            {} <= {};\n        end if;\n    end process;\n\n",
+            clk, reg, next
+        );
+        st += &format!(
+            "    process ({})\n    begin\n        if rising_edge({}) then\n",
+            clk, clk
+        );
+        st += &format!(
+            "            if {} then\n                {} <= {};\n            else\n",
+            rst, next, init
+        );
+        st += &format!("                case {} is\n", reg);
+        for s in &self.states {
+            st += &format!(
+                "                    when {} =>\n",
+                id(&_StrOut(s.state.clone()))
+            );
+            if s.branch.is_empty() {
+                st += "                        null;\n";
+            }
+            st += &ctx.if_list(&s.branch, 6);
+        }
+        st += "                end case;\n            end if;\n        end if;\n    end process;\n";
+        if let Some(al) = self.output_always() {
+            st += "\n";
+            st += &ctx.process(&al);
+        }
+        st
+    }
+}
+
+impl<'a> Ctx<'a> {
+    // メモリの初期値の集成体
+    fn mem_init(&self, wr: &WireVar) -> String {
+        let mem = match self.m.memory.iter().find(|x| x.name == wr.name) {
+            Some(x) if !x.init.is_empty() => x,
+            _ => return String::new(),
+        };
+        let word = |v: u64| match (self.m.resolve_width(wr), self.width_of(wr)) {
+            (_, None) => format!("'{}'", v & 1),
+            (Some(w), _) => format!(
+                "\"{}\"",
+                (0..w)
+                    .rev()
+                    .map(|i| if i < 64 && (v >> i) & 1 == 1 {
+                        '1'
+                    } else {
+                        '0'
+                    })
+                    .collect::<String>()
+            ),
+            (None, Some(w)) => format!("to_{}({}, {})", num_type(wr.signed), v, w),
+        };
+        let others = match self.width_of(wr) {
+            Some(_) => "(others => '0')",
+            None => "'0'",
+        };
+        let list = mem
+            .init
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| format!("{} => {}", i, word(v)))
+            .collect::<Vec<_>>();
+        format!(" := ({}, others => {})", list.join(", "), others)
+    }
+}
+
+impl VModule {
+    // VHDL 構文の出力と VHDL で表せない構文のエラー(表せない構文は出力されない)
+    pub(crate) fn emit_vhdl(&self) -> (String, Vec<VerugentError>) {
+        let errors = RefCell::new(Vec::new());
+        if !self.axi.is_empty() {
+            errors.borrow_mut().push("AXI interface".to_string());
+        }
+        // Verilog の記述をそのまま埋め込むことはできない
+        if !self.inline.is_empty() {
+            errors.borrow_mut().push("inline Verilog code".to_string());
+        }
+        // 2 つのプロセスから 1 つの信号には書き込めない
+        for mem in &self.memory {
+            if mem.ports.iter().filter(|p| p.we.is_some()).count() > 1 {
+                errors.borrow_mut().push(format!(
+                    "true dual-port RAM {} with two write ports",
+                    mem.name
+                ));
+            }
+        }
+        let fsm = self
+            .fsm
+            .iter()
+            .filter(|f| !f.states.is_empty())
+            .collect::<Vec<_>>();
+        let mut enums = Vec::new();
+        for f in &fsm {
+            let reg = _StrOut(f.state_reg.clone());
+            enums.extend(f.state_names());
+            enums.push(reg.clone() + "_Next");
+            enums.push(reg);
+        }
+        let ctx = Ctx {
+            m: self,
+            func: None,
+            enums,
+            errors: &errors,
+        };
+        let mut invalid = Vec::new();
+
+        let mut st = String::new();
+        st += "library ieee;\nuse ieee.std_logic_1164.all;\nuse ieee.numeric_std.all;\n\n";
+        st += &format!("entity {} is\n", id(&self.name));
+        if !self.io_param.is_empty() {
+            let list = self
+                .io_param
+                .iter()
+                .map(|p| {
+                    format!(
+                        "        {} : integer := {}",
+                        id(&p.name),
+                        ctx.int_expr(&p.value)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(";\n");
+            st += &format!("    generic (\n{}\n    );\n", list);
+        }
+        if !self.io_port.is_empty() {
+            let list = self
+                .io_port
+                .iter()
+                .filter_map(|p| {
+                    let dir = match p.io_param {
+                        IOType::Input => "in",
+                        IOType::Output => "out",
+                        IOType::InOut => "inout",
+                        IOType::Param | IOType::Genvar | IOType::None => {
+                            invalid.push(VerugentError::InvalidPort {
+                                module: self.name.clone(),
+                                signal: p.name.clone(),
+                            });
+                            return None;
+                        }
+                    };
+                    Some(format!(
+                        "        {} : {} {}",
+                        id(&p.name),
+                        dir,
+                        ctx.type_of(p)
+                    ))
+                })
+                .collect::<Vec<_>>()
+                .join(";\n");
+            st += &format!("    port (\n{}\n    );\n", list);
+        }
+        st += &format!("end entity {};\n\n", id(&self.name));
+
+        st += &format!("architecture rtl of {} is\n", id(&self.name));
+        for wr in &self.local_param {
+            if ctx.enums.contains(&wr.name) {
+                continue;
+            }
+            match wr.io_param {
+                // ループ変数は for ... generate で暗黙に宣言される
+                IOType::Genvar => {}
+                IOType::Param => {
+                    st += &format!(
+                        "    constant {} : integer := {};\n",
+                        id(&wr.name),
+                        ctx.int_expr(&wr.value)
+                    )
+                }
+                _ if is_memory(wr) => {
+                    let len = match wr.length_p {
+                        Some(ref l) => ctx.msb_of(l),
+                        None => (wr.length - 1).to_string(),
+                    };
+                    let ty = id(&(wr.name.clone() + "_t"));
+                    st += &format!(
+                        "    type {} is array (0 to {}) of {};\n    signal {} : {}{};\n",
+                        ty,
+                        len,
+                        ctx.type_of(wr),
+                        id(&wr.name),
+                        ty,
+                        ctx.mem_init(wr)
+                    );
+                }
+                _ => st += &format!("    signal {} : {};\n", id(&wr.name), ctx.type_of(wr)),
+            }
+        }
+        if fsm.iter().any(|f| f.encoding.is_some()) {
+            st += "    attribute fsm_encoding : string;\n";
+        }
+        if fsm
+            .iter()
+            .any(|f| matches!(f.encoding, Some(FsmEncoding::Custom(_))))
+        {
+            st += "    attribute enum_encoding : string;\n";
+        }
+        for f in &fsm {
+            st += &f.print_vhdl_decl();
+        }
+        for f in &self.func {
+            st += &ctx.function(f);
+        }
+        st += "begin\n";
+        for a in &self.assign {
+            st += &format!(
+                "    {} <= {};\n",
+                ctx.target(&a.lhs),
+                ctx.value(&a.lhs, &a.rhs)
+            );
+        }
+        for inst in &self.instance {
+            st += &ctx.instance(inst);
+        }
+        st += &self
+            .always
+            .iter()
+            .map(|al| ctx.process(al))
+            .collect::<Vec<_>>()
+            .join("\n");
+        st += &self
+            .generate
+            .iter()
+            .map(|g| ctx.generate(g, 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        st += &fsm
+            .iter()
+            .map(|f| f.print_vhdl(&ctx))
+            .collect::<Vec<_>>()
+            .join("\n");
+        st += "end architecture rtl;\n";
+        // $clog2 は ieee.math_real の関数で表す
+        if st.contains("log2(real(") {
+            st = st.replacen(
+                "use ieee.numeric_std.all;\n",
+                "use ieee.numeric_std.all;\nuse ieee.math_real.all;\n",
+                1,
+            );
+        }
+        invalid.extend(
+            errors
+                .into_inner()
+                .into_iter()
+                .map(|message| VerugentError::Unsupported {
+                    module: self.name.clone(),
+                    backend: "VHDL".to_string(),
+                    message,
+                }),
+        );
+        (st, invalid)
+    }
+}
@@ -0,0 +1,290 @@
+//! ビット幅推論と代入時のビット幅不一致検査
+
+use error::VerugentError;
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
+use vcore::*;
+
+/// 推論されたビット幅
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Width {
+    /// 幅の決まった式
+    Sized(i32),
+    /// 幅指定の無い定数(値の表現に必要な最小ビット数)
+    Unsized(i32),
+}
+
+impl Width {
+    pub fn bits(&self) -> i32 {
+        match *self {
+            Width::Sized(w) | Width::Unsized(w) => w,
+        }
+    }
+
+    // 二項演算のオペランド幅の合成
+    fn max(self, other: Width) -> Width {
+        match (self, other) {
+            (Width::Unsized(a), Width::Unsized(b)) => Width::Unsized(cmp::max(a, b)),
+            (a, b) => Width::Sized(cmp::max(a.bits(), b.bits())),
+        }
+    }
+}
+
+/// 代入時のビット幅不一致
+#[derive(Clone, Debug)]
+pub struct WidthMismatch {
+    pub module: String,
+    pub lhs: String,
+    pub rhs: String,
+    pub lhs_width: i32,
+    pub rhs_width: i32,
+    /// 右辺が符号付き(幅の拡張は符号拡張になる)
+    pub rhs_signed: bool,
+}
+
+impl WidthMismatch {
+    /// 上位ビットが切り捨てられるか
+    pub fn is_truncation(&self) -> bool {
+        self.rhs_width > self.lhs_width
+    }
+}
+
+impl fmt::Display for WidthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.is_truncation() {
+            "truncated"
+        } else if self.rhs_signed {
+            "sign-extended"
+        } else {
+            "zero-extended"
+        };
+        write!(
+            f,
+            "{}: {} ({} bit) = {} ({} bit) is {}",
+            self.module, self.lhs, self.lhs_width, self.rhs, self.rhs_width, kind
+        )
+    }
+}
+
+// 定数の表現に必要な最小ビット数
+fn const_bits(value: i64) -> i32 {
+    if value <= 0 {
+        1
+    } else {
+        64 - value.leading_zeros() as i32
+    }
+}
+
+// $clog2 の値(1 以下は 0)
+pub(crate) fn clog2_of(value: i64) -> i64 {
+    if value <= 1 {
+        0
+    } else {
+        64 - (value - 1).leading_zeros() as i64
+    }
+}
+
+impl VModule {
+    /// パラメータを含む定数式の評価
+    pub fn const_eval(&self, e: &E) -> Option<i64> {
+        match *e {
+            E::Num(i) => Some(i as i64),
+            E::Lit(ref l) => l.to_i64(),
+            E::Ldc(ref wr) => match wr.io_param {
+                IOType::Param => match self.param_value(&wr.name) {
+                    Some(v) => Some(v as i64),
+                    None => self.const_eval(&wr.value),
+                },
+                _ => None,
+            },
+            E::Bin(op, ref l, ref r) => {
+                let l = self.const_eval(l)?;
+                let r = self.const_eval(r)?;
+                match op {
+                    // 桁あふれする式は値不明として扱う
+                    BinOp::Add => l.checked_add(r),
+                    BinOp::Sub => l.checked_sub(r),
+                    BinOp::Mul => l.checked_mul(r),
+                    BinOp::Div => l.checked_div(r),
+                    BinOp::Mod => l.checked_rem(r),
+                    BinOp::Shl => {
+                        let s = u32::try_from(r).ok()?;
+                        l.checked_shl(s).filter(|v| v >> s == l)
+                    }
+                    BinOp::Shr | BinOp::Sra => l.checked_shr(u32::try_from(r).ok()?),
+                    BinOp::Or => Some(l | r),
+                    BinOp::And => Some(l & r),
+                    BinOp::Xor => Some(l ^ r),
+                    BinOp::LOr => Some((l != 0 || r != 0) as i64),
+                    BinOp::LAnd => Some((l != 0 && r != 0) as i64),
+                    BinOp::Eq => Some((l == r) as i64),
+                    BinOp::Ne => Some((l != r) as i64),
+                    BinOp::Lt => Some((l < r) as i64),
+                    BinOp::Gt => Some((l > r) as i64),
+                    BinOp::Le => Some((l <= r) as i64),
+                    BinOp::Ge => Some((l >= r) as i64),
+                }
+            }
+            E::Un(UnaryOp::Neg, ref a) => self.const_eval(a)?.checked_neg(),
+            E::Un(UnaryOp::LNot, ref a) => Some((self.const_eval(a)? == 0) as i64),
+            E::Signed(ref a) | E::Unsigned(ref a) => self.const_eval(a),
+            E::Clog2(ref a) => Some(clog2_of(self.const_eval(a)?)),
+            _ => None,
+        }
+    }
+
+    /// Verilog の幅規則に従った式のビット幅推論
+    pub fn infer_width(&self, e: &E) -> Option<Width> {
+        match *e {
+            E::Num(i) => Some(Width::Unsized(const_bits(i as i64))),
+            E::Lit(ref l) => Some(match l.width {
+                Some(w) => Width::Sized(w as i32),
+                None => Width::Unsized(l.bit_width() as i32),
+            }),
+            E::Ldc(ref wr) => match wr.io_param {
+                IOType::Param => self.const_eval(e).map(|v| Width::Unsized(const_bits(v))),
+                // genvar は integer として扱う
+                IOType::Genvar => Some(Width::Unsized(32)),
+                _ => self.resolve_width(wr).map(Width::Sized),
+            },
+            E::Un(UnaryOp::LNot, _) => Some(Width::Sized(1)),
+            E::Un(_, ref a) | E::Signed(ref a) | E::Unsigned(ref a) => self.infer_width(a),
+            E::Red(_, _) => Some(Width::Sized(1)),
+            E::Clog2(_) => self.const_eval(e).map(|v| Width::Unsized(const_bits(v))),
+            E::Bin(op, ref l, ref r) => match op {
+                BinOp::Add
+                | BinOp::Sub
+                | BinOp::Mul
+                | BinOp::Div
+                | BinOp::Mod
+                | BinOp::Or
+                | BinOp::And
+                | BinOp::Xor => Some(self.infer_width(l)?.max(self.infer_width(r)?)),
+                BinOp::Shl | BinOp::Shr | BinOp::Sra => self.infer_width(l),
+                _ => Some(Width::Sized(1)),
+            },
+            E::PL(_, ref t, ref f) => Some(self.infer_width(t)?.max(self.infer_width(f)?)),
+            E::SB(ref l, _) | E::SBX(_, ref l, _) => self.infer_width(l),
+            E::MEM(ref m, _) => match **m {
+                E::Ldc(ref wr) if wr.length > 0 || wr.length_p.is_some() => {
+                    self.resolve_width(wr).map(Width::Sized)
+                }
+                _ => Some(Width::Sized(1)),
+            },
+            E::MBT(_, ref h, ref l) => {
+                let h = self.const_eval(h)?;
+                let l = self.const_eval(l)?;
+                Some(Width::Sized((h - l).abs() as i32 + 1))
+            }
+            E::Func(ref top, _) => self.infer_width(top),
+            E::Cat(ref items) => items
+                .iter()
+                .map(|x| self.infer_width(x).map(|w| w.bits()))
+                .sum::<Option<i32>>()
+                .map(Width::Sized),
+            E::Rep(ref n, ref x) => {
+                let n = self.const_eval(n)?;
+                Some(Width::Sized(n as i32 * self.infer_width(x)?.bits()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Verilog の符号規則に従った式の符号(全オペランドが符号付きの場合のみ符号付き)
+    pub fn is_signed(&self, e: &E) -> bool {
+        match *e {
+            E::Num(_) | E::Signed(_) | E::Clog2(_) => true,
+            E::Lit(ref l) => l.signed,
+            E::Ldc(ref wr) => matches!(wr.io_param, IOType::Param | IOType::Genvar) || wr.signed,
+            E::Un(UnaryOp::LNot, _) => false,
+            E::Un(_, ref a) => self.is_signed(a),
+            E::Bin(op, ref l, ref r) => match op {
+                BinOp::Shl | BinOp::Shr | BinOp::Sra => self.is_signed(l),
+                _ if op.is_compare() || op == BinOp::LAnd || op == BinOp::LOr => false,
+                _ => self.is_signed(l) && self.is_signed(r),
+            },
+            E::PL(_, ref t, ref f) => self.is_signed(t) && self.is_signed(f),
+            // メモリの要素は符号付き、ビット選択は符号なし
+            E::MEM(ref m, _) => match **m {
+                E::Ldc(ref wr) => wr.signed && (wr.length > 0 || wr.length_p.is_some()),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// 代入文のビット幅不一致の検出
+    pub fn check_widths(&self) -> Vec<WidthMismatch> {
+        if !self.generate.is_empty() {
+            return self.unroll_generate().check_widths();
+        }
+        let mut errs = Vec::new();
+        for a in &self.assign {
+            self.check_assign(&a.lhs, &a.rhs, &mut errs);
+        }
+        for al in &self.always {
+            for s in &al.stmt {
+                self.check_stmt(s, &mut errs);
+            }
+        }
+        for f in &self.func {
+            for s in &f.stmt {
+                self.check_stmt(s, &mut errs);
+            }
+        }
+        errs
+    }
+
+    /// 検査エラーまたはビット幅不一致がある場合は出力しない gen()
+    pub fn gen_checked(&self) -> Result<String, Vec<VerugentError>> {
+        let mut errs = self.validate();
+        errs.extend(self.check_widths().into_iter().map(VerugentError::from));
+        if errs.is_empty() {
+            Ok(self.gen())
+        } else {
+            Err(errs)
+        }
+    }
+
+    fn check_stmt(&self, e: &E, errs: &mut Vec<WidthMismatch>) {
+        match *e {
+            E::SB(ref l, ref r) | E::SBX(_, ref l, ref r) => self.check_assign(l, r, errs),
+            E::BL(ref list) => {
+                for x in list {
+                    for s in &x.stmt {
+                        self.check_stmt(s, errs);
+                    }
+                }
+            }
+            E::CS(ref c) => {
+                for x in &c.Select {
+                    for s in &x.CaseS {
+                        self.check_stmt(s, errs);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_assign(&self, lhs: &E, rhs: &E, errs: &mut Vec<WidthMismatch>) {
+        let lw = match self.infer_width(lhs) {
+            Some(w) => w.bits(),
+            None => return,
+        };
+        let rw = match self.infer_width(rhs) {
+            Some(Width::Sized(w)) if w != lw => w,
+            Some(Width::Unsized(w)) if w > lw => w,
+            _ => return,
+        };
+        errs.push(WidthMismatch {
+            module: self.name.clone(),
+            lhs: decomp_ast(Box::new(lhs.clone()), "", 0),
+            rhs: decomp_ast(Box::new(rhs.clone()), "", 0),
+            lhs_width: lw,
+            rhs_width: rw,
+            rhs_signed: self.is_signed(rhs),
+        });
+    }
+}